@@ -0,0 +1,131 @@
+//! Synthetic event throughput for the detection engine.
+//!
+//! Runs the same event mix across several producer threads in three setups: every
+//! event serialized behind one global mutex, as the engine was before its state was
+//! split; the split state collapsed onto one lock shard; and the default sharding:
+//!
+//! ```text
+//! cargo bench --bench engine_throughput
+//! ```
+
+use crossbeam_channel::bounded;
+use parking_lot::Mutex;
+use std::{
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+use titan_vigil::{
+    runtime::{engine::Engine, host::NullHost},
+    support::config::Config,
+    telemetry::event::{FileOperation, TelemetryEvent},
+};
+
+const CONFIG: &str = r#"
+[general]
+suppress_ms = 1000
+
+[[watch.protected]]
+pattern = "\\Google\\Chrome\\User Data\\Default\\Login Data"
+name = "Chrome Passwords"
+
+[[watch.protected]]
+pattern = ".kdbx"
+match = "suffix"
+name = "KeePass"
+"#;
+
+const EVENTS_PER_THREAD: u64 = 200_000;
+
+/// One thread's stream: mostly FileKey churn on protected paths, plus process
+/// starts and a protected access every few events.
+fn stream(thread: u64) -> Vec<TelemetryEvent> {
+    let base = thread << 32;
+    let mut events = Vec::with_capacity(EVENTS_PER_THREAD as usize);
+    for i in 0..EVENTS_PER_THREAD / 4 {
+        let key = base | i;
+        let pid = 1000 + (thread * 64 + i % 64) as u32;
+        events.push(TelemetryEvent::ProcessStart {
+            pid,
+            image: format!(r"C:\Tools\worker{}.exe", i % 64),
+            cmdline: None,
+            parent_pid: None,
+            start_time: Some(i),
+            user: None,
+        });
+        events.push(TelemetryEvent::FileNameMapping {
+            file_key: key,
+            file_name: format!(r"C:\Users\u{thread}\Vault\db{i}.kdbx"),
+        });
+        events.push(TelemetryEvent::FileAccess {
+            pid,
+            event_id: 15,
+            operation: FileOperation::Read,
+            file_name: None,
+            file_key: key,
+            file_object: key,
+        });
+        events.push(TelemetryEvent::FileClose { file_key: key });
+    }
+    events
+}
+
+/// How the engine state is locked for one run.
+#[derive(Clone, Copy)]
+enum Locking {
+    /// One mutex held for the whole of every event, like the old `Mutex<EngineState>`.
+    Global,
+    Shards(usize),
+}
+
+fn run(locking: Locking, threads: u64) -> f64 {
+    let mut cfg = Config::from_toml(CONFIG).expect("bench config");
+    cfg.concurrency.state_shards = match locking {
+        Locking::Global => 1,
+        Locking::Shards(shards) => shards,
+    };
+    let global = Arc::new(Mutex::new(()));
+    let (tx, rx) = bounded(1024);
+    let engine = Arc::new(Engine::with_host(cfg, tx, Arc::new(NullHost)).expect("bench engine"));
+    let drain = thread::spawn(move || while rx.recv().is_ok() {});
+
+    let streams: Vec<_> = (0..threads).map(stream).collect();
+    let start = Instant::now();
+    let workers: Vec<_> = streams
+        .into_iter()
+        .map(|events| {
+            let engine = engine.clone();
+            let global = global.clone();
+            thread::spawn(move || {
+                for event in events {
+                    let _guard = matches!(locking, Locking::Global).then(|| global.lock());
+                    engine.handle_event(event);
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().expect("worker panicked");
+    }
+    let elapsed = start.elapsed().max(Duration::from_nanos(1));
+
+    drop(engine);
+    drain.join().expect("drain panicked");
+    (threads * EVENTS_PER_THREAD) as f64 / elapsed.as_secs_f64()
+}
+
+fn main() {
+    println!(
+        "{:>8} {:>14} {:>14} {:>14} {:>8}",
+        "threads", "global ev/s", "1 shard ev/s", "16 shards ev/s", "gain"
+    );
+    for threads in [1, 2, 4, 8] {
+        let global = run(Locking::Global, threads);
+        let single = run(Locking::Shards(1), threads);
+        let sharded = run(Locking::Shards(16), threads);
+        println!(
+            "{threads:>8} {global:>14.0} {single:>14.0} {sharded:>14.0} {:>7.2}x",
+            sharded / global
+        );
+    }
+}
@@ -1,4 +1,9 @@
 fn main() {
+    // Resource compilation needs rc.exe/windres; non-Windows builds only carry the core.
+    if std::env::var("CARGO_CFG_TARGET_OS").as_deref() != Ok("windows") {
+        return;
+    }
+
     let mut res = winres::WindowsResource::new();
     res.set("ProductName", "TITAN Vigil");
     res.set("FileDescription", "TITAN Vigil");
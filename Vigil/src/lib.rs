@@ -0,0 +1,5 @@
+pub mod output;
+pub mod runtime;
+pub mod support;
+pub mod telemetry;
+pub mod trust;
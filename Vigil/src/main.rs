@@ -1,18 +1,36 @@
 #![windows_subsystem = "console"]
 
-mod output;
-mod runtime;
-mod support;
-mod telemetry;
-mod trust;
-
-use anyhow::{Context, Result, anyhow};
-use crossbeam_channel::bounded;
-#[cfg(feature = "remote_endpoint")]
-use output::endpoint;
-use runtime::engine::Engine;
-use std::{fs, path::PathBuf, sync::Arc, thread, time::Duration};
-use support::{diag, win::to_wide};
+use anyhow::{Context, Result};
+use crossbeam_channel::unbounded;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+#[cfg(all(windows, feature = "remote_endpoint"))]
+use titan_vigil::output::endpoint;
+use titan_vigil::{
+    output::{
+        alerts::{Alert, AlertLogger},
+        schema,
+    },
+    runtime::{
+        baseline::{self, Baseline, read_baseline},
+        engine::Engine,
+    },
+    support::{
+        cli::{Cli, Command},
+        config::{Config, SensorMode},
+        diag,
+        identity::SensorIdentity,
+        reload::ConfigWatcher,
+    },
+    telemetry::{
+        journal::{self, JournalSource, ReplayHost},
+        source::EventSource,
+    },
+};
+#[cfg(windows)]
 use windows::{
     Win32::{
         Foundation::{CloseHandle, ERROR_NOT_ALL_ASSIGNED, GetLastError, LUID},
@@ -27,6 +45,29 @@ use windows::{
     },
     core::PCWSTR,
 };
+#[cfg(windows)]
+use {
+    anyhow::anyhow,
+    crossbeam_channel::bounded,
+    serde_json::json,
+    std::{
+        thread,
+        time::{Duration, Instant},
+    },
+    titan_vigil::{
+        output,
+        runtime::{
+            host::{self, Host},
+            response::PlatformResponder,
+        },
+        support::{metrics, win::to_wide},
+        telemetry::{
+            self,
+            journal::{JournalWriter, RecordingHost},
+        },
+        trust::cache::TrustCache,
+    },
+};
 
 fn main() -> Result<()> {
     if let Err(e) = run() {
@@ -38,12 +79,20 @@ fn main() -> Result<()> {
 
 fn run() -> Result<()> {
     diag::startup("startup begin");
-    unsafe {
-        let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
+    #[cfg(windows)]
+    {
+        unsafe {
+            let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
+        }
+        diag::startup("COM initialized");
     }
-    diag::startup("COM initialized");
 
-    let cli = support::cli::Cli::parse();
+    let cli = Cli::parse()?;
+    // Static output; needs no config.
+    if cli.command == Command::SchemaAlerts {
+        println!("{:#}", schema::alert_schemas());
+        return Ok(());
+    }
 
     let mut cfg_path = cli.config.clone();
     if !cli.config_explicit
@@ -57,9 +106,147 @@ fn run() -> Result<()> {
         }
     }
 
-    let cfg = support::config::Config::load(&cfg_path)
+    let cfg = Config::load(&cfg_path)
         .with_context(|| format!("failed to load config from {}", cfg_path.display()))?;
     diag::startup(&format!("config loaded from {}", cfg_path.display()));
+
+    match &cli.command {
+        Command::Run => run_live(&cli, cfg, &cfg_path),
+        Command::Replay { journal } => run_replay(&cli, cfg, journal),
+        Command::Reload => request_reload(&cfg_path),
+        Command::SchemaAlerts => unreachable!("handled before the config is loaded"),
+        Command::BaselineSuggest { baseline, min_hits } => {
+            let path = baseline
+                .clone()
+                .unwrap_or_else(|| state_file(&cfg.general.baseline_path, "baseline.json"));
+            let entries = read_baseline(&path)?;
+            let rules = Config::written_rules(&cfg_path)?;
+            print!("{}", baseline::suggest(&entries, *min_hits, &rules));
+            Ok(())
+        }
+    }
+}
+
+/// The config was already validated by `run`; leave the trigger for the sensor.
+fn request_reload(cfg_path: &Path) -> Result<()> {
+    let trigger = ConfigWatcher::trigger_for(cfg_path);
+    fs::write(&trigger, b"")
+        .with_context(|| format!("failed to write reload trigger {}", trigger.display()))?;
+    eprintln!(
+        "[TITAN Vigil] {} is valid; reload requested via {}",
+        cfg_path.display(),
+        trigger.display()
+    );
+    Ok(())
+}
+
+fn state_root() -> PathBuf {
+    std::env::var_os("LOCALAPPDATA")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("TITAN-Vigil-CE")
+}
+
+/// `%ProgramData%\TITAN-Vigil-CE`, for state a non-admin must not be able to write.
+#[cfg(windows)]
+fn protected_state_root() -> PathBuf {
+    std::env::var_os("ProgramData")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("TITAN-Vigil-CE")
+}
+
+/// `configured` when set, otherwise `name` next to the log directory.
+fn state_file(configured: &str, name: &str) -> PathBuf {
+    if configured.is_empty() {
+        state_root().join(name)
+    } else {
+        PathBuf::from(configured)
+    }
+}
+
+fn open_baseline(cfg: &Config) -> Result<Option<Arc<Baseline>>> {
+    if cfg.general.mode != SensorMode::Learn {
+        return Ok(None);
+    }
+    let path = state_file(&cfg.general.baseline_path, "baseline.json");
+    let baseline = Baseline::load(path.clone())?;
+    diag::startup(&format!(
+        "learn mode: recording accessors to {} ({} known)",
+        path.display(),
+        baseline.len()
+    ));
+    Ok(Some(Arc::new(baseline)))
+}
+
+fn sensor_identity() -> Result<SensorIdentity> {
+    SensorIdentity::load_or_create(&state_root().join("sensor_id"))
+}
+
+fn prepare_log_dir(sub: Option<&str>) -> Result<PathBuf> {
+    let mut log_dir = state_root().join("logs");
+    if let Some(sub) = sub {
+        log_dir = log_dir.join(sub);
+    }
+    fs::create_dir_all(&log_dir)
+        .with_context(|| format!("failed to create log directory {}", log_dir.display()))?;
+    Ok(log_dir)
+}
+
+fn run_replay(cli: &Cli, mut cfg: Config, journal_path: &std::path::Path) -> Result<()> {
+    // The token buckets refill on the wall clock, and a replay should report every alert.
+    cfg.rate_limit.enabled = false;
+    let records = journal::read_journal(journal_path)?;
+    let host = Arc::new(ReplayHost::from_records(&records));
+
+    // Unbounded: replay runs far faster than live ETW and must not shed alerts.
+    let (alert_tx, alert_rx) = unbounded::<Alert>();
+    let mut engine =
+        Engine::with_host(cfg.clone(), alert_tx, host)?.with_identity(sensor_identity()?);
+    let baseline = open_baseline(&cfg)?;
+    if let Some(baseline) = &baseline {
+        engine = engine.with_baseline(baseline.clone());
+    }
+    let engine = Arc::new(engine);
+    engine.preflight_trusted_handles()?;
+
+    JournalSource(records).start(engine.clone())?;
+    engine.flush_suppressed(true);
+    engine.flush_storm(true);
+    let excluded = engine.take_excluded_hits();
+    drop(engine);
+    if let Some(baseline) = &baseline {
+        baseline.save()?;
+    }
+
+    // Keep replayed alerts apart from the live sensor's logs.
+    let log_dir = prepare_log_dir(Some("replay"))?;
+    let logger = AlertLogger::new(&log_dir, &cfg)
+        .with_context(|| format!("failed to initialize logger in {}", log_dir.display()))?;
+    let mut count = 0usize;
+    for alert in alert_rx.iter() {
+        if cli.verbose {
+            println!("{}", alert.human_line());
+        }
+        logger.write(&alert)?;
+        count += 1;
+    }
+
+    eprintln!(
+        "[TITAN Vigil] replayed {}: {count} alerts written to {} ({excluded} excluded hits)",
+        journal_path.display(),
+        log_dir.display()
+    );
+    Ok(())
+}
+
+#[cfg(not(windows))]
+fn run_live(_cli: &Cli, _cfg: Config, _cfg_path: &Path) -> Result<()> {
+    anyhow::bail!("live monitoring requires Windows kernel ETW")
+}
+
+#[cfg(windows)]
+fn run_live(cli: &Cli, mut cfg: Config, cfg_path: &Path) -> Result<()> {
     ensure_elevated().context("elevation preflight failed")?;
     diag::startup("elevation check passed");
     ensure_kernel_trace_privilege().context("failed to enable SeSystemProfilePrivilege")?;
@@ -68,21 +255,76 @@ fn run() -> Result<()> {
     let (alert_tx, alert_rx) =
         bounded::<output::alerts::Alert>(cfg.concurrency.alert_channel_capacity);
 
-    let engine = Arc::new(Engine::new(cfg.clone(), alert_tx.clone()));
-
-    let log_root = std::env::var_os("LOCALAPPDATA")
-        .map(PathBuf::from)
-        .unwrap_or_else(|| PathBuf::from("."));
-    let log_dir = log_root.join("TITAN-Vigil-CE").join("logs");
-    fs::create_dir_all(&log_dir)
-        .with_context(|| format!("failed to create log directory {}", log_dir.display()))?;
+    let log_dir = prepare_log_dir(None)?;
     diag::startup(&format!("log dir ready: {}", log_dir.display()));
+
+    // Recording bypasses the trust cache so every verdict lands in the journal.
+    let mut trust_cache = None;
+    let mut recording = None;
+    let engine = match &cli.record {
+        Some(path) => {
+            let journal = Arc::new(JournalWriter::create(path)?);
+            let host: Arc<dyn Host> =
+                Arc::new(RecordingHost::new(host::default_host(), journal.clone()));
+            diag::startup(&format!("recording telemetry to {}", path.display()));
+            recording = Some(journal.clone());
+            Engine::with_host(cfg.clone(), alert_tx.clone(), host)?.with_journal(journal)
+        }
+        None if cfg.trust_cache.enabled => {
+            let path = if cfg.trust_cache.path.is_empty() {
+                protected_state_root().join("trust_cache.json")
+            } else {
+                PathBuf::from(&cfg.trust_cache.path)
+            };
+            let cache = Arc::new(TrustCache::load(&cfg.trust_cache, path.clone()));
+            diag::startup(&format!(
+                "trust cache loaded from {} ({} verdicts)",
+                path.display(),
+                cache.len()
+            ));
+            trust_cache = Some(cache.clone());
+            Engine::new(cfg.clone(), alert_tx.clone())?.with_trust_cache(cache)
+        }
+        None => Engine::new(cfg.clone(), alert_tx.clone())?,
+    };
+    let baseline = open_baseline(&cfg)?;
+    let engine = match &baseline {
+        Some(baseline) => engine.with_baseline(baseline.clone()),
+        None => engine,
+    };
+    let quarantine_dir = state_file(&cfg.response.quarantine_dir, "quarantine");
+    if cfg.response.enabled {
+        diag::startup(&format!(
+            "response actions enabled (dry_run={}, quarantine {})",
+            cfg.response.dry_run,
+            quarantine_dir.display()
+        ));
+    }
+    let identity = sensor_identity()?;
+    diag::startup(&format!(
+        "sensor {} on {}",
+        identity.sensor_id, identity.hostname
+    ));
+    let engine = Arc::new(engine.with_identity(identity));
+    engine.start_responder(Arc::new(PlatformResponder::new(quarantine_dir)))?;
+    let counters = engine.metrics().clone();
     let logger = Arc::new(
-        output::alerts::AlertLogger::new(&log_dir, &cfg)
-            .with_context(|| format!("failed to initialize logger in {}", log_dir.display()))?,
+        AlertLogger::new(&log_dir, &cfg)
+            .with_context(|| format!("failed to initialize logger in {}", log_dir.display()))?
+            .with_metrics(counters.clone()),
     );
     #[cfg(feature = "remote_endpoint")]
     let endpoint = Arc::new(endpoint::EndpointAlerter::from_config(&cfg.endpoint_alert));
+    if cfg.metrics.enabled {
+        let scraped = Arc::downgrade(&engine);
+        let addr = metrics::serve(&cfg.metrics.listen, move || {
+            scraped
+                .upgrade()
+                .map(|engine| engine.render_metrics())
+                .unwrap_or_default()
+        })?;
+        diag::startup(&format!("metrics served on http://{addr}/metrics"));
+    }
 
     if !cfg.general.quiet {
         if let Some(primary_log) = logger.primary_log_path() {
@@ -111,6 +353,8 @@ fn run() -> Result<()> {
         let logger = logger.clone();
         #[cfg(feature = "remote_endpoint")]
         let endpoint = endpoint.clone();
+        #[cfg(feature = "remote_endpoint")]
+        let counters = counters.clone();
         thread::Builder::new()
             .name(format!("vigil-alert-worker-{idx}"))
             .spawn(move || {
@@ -129,6 +373,7 @@ fn run() -> Result<()> {
                     if endpoint.is_enabled()
                         && let Err(e) = endpoint.send(&alert)
                     {
+                        counters.record_sink_error("endpoint");
                         eprintln!("[TML][ENDPOINT] {:?}", e);
                     }
                 }
@@ -137,16 +382,100 @@ fn run() -> Result<()> {
 
     let _ = engine.preflight_trusted_handles();
     diag::startup("preflight trusted handle scan completed");
-    let _session = telemetry::etw::start_etw(engine.clone())?;
+    engine
+        .start_trust_pool()
+        .context("failed to start trust workers")?;
+    let _session = telemetry::etw::EtwSource.start(engine.clone())?;
     diag::startup("ETW session started");
 
+    let mut watcher = ConfigWatcher::new(cfg_path);
+    let mut last_stats = Instant::now();
+    let mut reported_drops = 0;
     loop {
-        thread::sleep(Duration::from_secs(60));
-        let dropped = engine.take_dropped_alerts();
-        if dropped > 0 {
-            eprintln!("[TITAN Vigil] dropped {dropped} alerts due to backpressure");
+        thread::sleep(Duration::from_secs(2));
+        if watcher.poll() {
+            reload_config(&mut cfg, watcher.path(), &engine, &logger, &log_dir);
+        }
+        engine.flush_suppressed(false);
+        engine.flush_storm(false);
+        if let Some(journal) = &recording
+            && let Err(e) = journal.flush()
+        {
+            eprintln!("[JOURNAL] {e:?}");
+        }
+        if last_stats.elapsed() < Duration::from_secs(60) {
+            continue;
+        }
+        last_stats = Instant::now();
+
+        let dropped = engine.metrics().dropped_alerts();
+        if dropped > reported_drops {
+            eprintln!(
+                "[TITAN Vigil] dropped {} alerts due to backpressure",
+                dropped - reported_drops
+            );
+            reported_drops = dropped;
+        }
+        let excluded = engine.take_excluded_hits();
+        if excluded > 0 && !cfg.general.quiet {
+            eprintln!("[TITAN Vigil] {excluded} protected-path hits excluded in the last minute");
         }
+        if let Some(cache) = &trust_cache
+            && let Err(e) = cache.save()
+        {
+            eprintln!("[TRUST_CACHE] {e:?}");
+        }
+        if let Some(baseline) = &baseline
+            && let Err(e) = baseline.save()
+        {
+            eprintln!("[BASELINE] {e:?}");
+        }
+    }
+}
+
+/// Validate and apply the config at `path`. On any error the running config stays.
+#[cfg(windows)]
+fn reload_config(
+    cfg: &mut Config,
+    path: &Path,
+    engine: &Engine,
+    logger: &AlertLogger,
+    log_dir: &Path,
+) {
+    let failed = |err: anyhow::Error| {
+        diag::event(
+            "config_reload_failed",
+            json!({ "path": path.display().to_string(), "error": format!("{err:#}") }),
+        );
+    };
+    let next = match Config::load(path) {
+        Ok(next) => next,
+        Err(e) => return failed(e),
+    };
+    // Prepare everything that can fail before anything goes live.
+    let policy = match Engine::prepare_policy(next.clone()) {
+        Ok(policy) => policy,
+        Err(e) => return failed(e),
+    };
+    let sinks = match logger.open_sinks(&next) {
+        Ok(sinks) => sinks,
+        Err(e) => return failed(e),
+    };
+    engine.install_policy(policy);
+    logger.install_sinks(sinks);
+    if let Err(e) = output::siem::generate_sigma_rules(&next, log_dir) {
+        eprintln!("[SIEM] {e:?}");
     }
+
+    diag::event(
+        "config_reloaded",
+        json!({
+            "path": path.display().to_string(),
+            "rules": engine.rule_count(),
+            "restart_required": cfg.restart_required(&next),
+        }),
+    );
+    *cfg = next;
 }
 
 fn show_startup_error(err: &anyhow::Error) {
@@ -163,8 +492,16 @@ fn show_startup_error(err: &anyhow::Error) {
         msg.push('\n');
     }
 
+    #[cfg(windows)]
+    show_message_box(&msg);
+    #[cfg(not(windows))]
+    eprintln!("{msg}");
+}
+
+#[cfg(windows)]
+fn show_message_box(msg: &str) {
     let title = to_wide("TITAN Vigil");
-    let body = to_wide(&msg);
+    let body = to_wide(msg);
     unsafe {
         let _ = MessageBoxW(
             None,
@@ -175,6 +512,7 @@ fn show_startup_error(err: &anyhow::Error) {
     }
 }
 
+#[cfg(windows)]
 fn ensure_elevated() -> Result<()> {
     if is_process_elevated()? {
         return Ok(());
@@ -185,6 +523,7 @@ fn ensure_elevated() -> Result<()> {
     ))
 }
 
+#[cfg(windows)]
 fn is_process_elevated() -> Result<bool> {
     unsafe {
         let mut token = Default::default();
@@ -227,6 +566,7 @@ fn startup_hint(err: &anyhow::Error) -> Option<&'static str> {
     None
 }
 
+#[cfg(windows)]
 fn ensure_kernel_trace_privilege() -> Result<()> {
     const PRIV_NAME: &str = "SeSystemProfilePrivilege";
 
@@ -1,25 +1,163 @@
-use crate::support::config::Config;
+use crate::{
+    output::schema::ALERT_SCHEMA_VERSION,
+    support::{
+        config::{Config, ResponseKind},
+        identity::{self, SensorIdentity},
+        metrics::Metrics,
+    },
+    telemetry::event::FileOperation,
+};
 use anyhow::{Context, Result};
-use parking_lot::Mutex;
+use parking_lot::{Mutex, RwLock};
 use serde::{Deserialize, Serialize};
 use std::{
     collections::BTreeMap,
     fs::{File, OpenOptions},
     io::{BufWriter, Write},
     path::{Path, PathBuf},
+    sync::Arc,
     time::{SystemTime, UNIX_EPOCH},
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Alert {
+    /// [`ALERT_SCHEMA_VERSION`] of the emitting sensor.
+    #[serde(default)]
+    pub schema_version: u32,
+    /// ULID, unique per alert; lets a SIEM drop retransmits.
+    #[serde(default)]
+    pub alert_id: String,
+    /// RFC 3339 UTC with milliseconds.
+    #[serde(default)]
+    pub ts: String,
     pub ts_unix: u64,
+    /// Milliseconds since the epoch that `ts` and `ts_unix` are derived from; `ts`
+    /// carries them on the wire.
+    #[serde(skip)]
+    ts_ms: u64,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub sensor_id: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub hostname: String,
+    #[serde(default)]
+    pub sensor_version: String,
+    /// Operator labels from `general.tags`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
     pub pid: u32,
     pub process: String,
     pub target: String,
     pub data_name: String,
+    /// Every rule matching `target` under `watch.match_mode = "all"`, `data_name` first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub matched_rules: Vec<String>,
     pub event_id: u16,
+    #[serde(default)]
+    pub operation: FileOperation,
     pub kind: String,
     pub note: String,
+    /// Every rule/path behind a correlated alert such as `credential_sweep`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub related: Vec<RelatedHit>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_image: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cmdline: Option<String>,
+    /// `DOMAIN\user` running the process.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// Known ancestors, nearest parent first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ancestry: Vec<Ancestor>,
+    /// The process verdict missed `concurrency.trust_deadline_ms`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub trust_unknown: bool,
+    /// Repeats suppressed since the key's previous alert, and when the first and last
+    /// of them happened (unix seconds).
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub suppressed_count: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_seen: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_seen: Option<u64>,
+    /// Audit record of the rule's `response` action.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<ResponseRecord>,
+    /// Alerts held back by `[rate_limit]`, on `alert_storm` alerts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storm: Option<StormSummary>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResponseRecord {
+    pub action: ResponseKind,
+    pub outcome: ResponseOutcome,
+    /// What was done, or why nothing was.
+    pub detail: String,
+    pub ts_unix: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseOutcome {
+    Executed,
+    DryRun,
+    Skipped,
+    Failed,
+}
+
+impl ResponseRecord {
+    pub fn new(action: ResponseKind, outcome: ResponseOutcome, detail: String) -> Self {
+        let ts_unix = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        Self {
+            action,
+            outcome,
+            detail,
+            ts_unix,
+        }
+    }
+}
+
+impl ResponseOutcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Executed => "executed",
+            Self::DryRun => "dry_run",
+            Self::Skipped => "skipped",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StormSummary {
+    pub limited: u64,
+    /// Time from the first held-back alert to the summary.
+    pub window_ms: u64,
+    /// Busiest images and rules among the held-back alerts, highest count first.
+    pub top_images: Vec<StormCount>,
+    pub top_rules: Vec<StormCount>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StormCount {
+    pub name: String,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ancestor {
+    pub pid: u32,
+    pub image: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RelatedHit {
+    pub data_name: String,
+    pub target: String,
 }
 
 impl Alert {
@@ -32,87 +170,405 @@ impl Alert {
         kind: &str,
         note: &str,
     ) -> Self {
-        let ts_unix = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
+        let now_ms = identity::unix_millis();
 
         Self {
-            ts_unix,
+            schema_version: ALERT_SCHEMA_VERSION,
+            alert_id: identity::ulid(now_ms),
+            ts: identity::rfc3339_millis(now_ms),
+            ts_unix: now_ms / 1000,
+            ts_ms: now_ms,
+            sensor_id: String::new(),
+            hostname: String::new(),
+            sensor_version: env!("CARGO_PKG_VERSION").to_string(),
+            tags: Vec::new(),
             pid,
             process,
             target,
             data_name,
+            matched_rules: Vec::new(),
             event_id,
+            operation: FileOperation::Create,
             kind: kind.to_string(),
             note: note.to_string(),
+            related: Vec::new(),
+            parent_image: None,
+            cmdline: None,
+            user: None,
+            ancestry: Vec::new(),
+            trust_unknown: false,
+            suppressed_count: 0,
+            first_seen: None,
+            last_seen: None,
+            response: None,
+            storm: None,
         }
     }
 
+    /// Stamp the sensor that raised this alert.
+    pub fn with_sensor(mut self, identity: &SensorIdentity, tags: &[String]) -> Self {
+        self.sensor_id = identity.sensor_id.clone();
+        self.hostname = identity.hostname.clone();
+        self.tags = tags.to_vec();
+        self
+    }
+
+    pub fn with_operation(mut self, operation: FileOperation) -> Self {
+        self.operation = operation;
+        self
+    }
+
+    pub fn with_related(mut self, related: Vec<RelatedHit>) -> Self {
+        self.related = related;
+        self
+    }
+
+    pub fn with_lineage(mut self, cmdline: Option<String>, ancestry: Vec<Ancestor>) -> Self {
+        self.parent_image = ancestry.first().map(|a| a.image.clone());
+        self.cmdline = cmdline;
+        self.ancestry = ancestry;
+        self
+    }
+
+    pub fn with_user(mut self, user: Option<String>) -> Self {
+        self.user = user;
+        self
+    }
+
+    pub fn with_trust_unknown(mut self, trust_unknown: bool) -> Self {
+        self.trust_unknown = trust_unknown;
+        self
+    }
+
+    pub fn with_matched_rules(mut self, matched_rules: Vec<String>) -> Self {
+        self.matched_rules = matched_rules;
+        self
+    }
+
+    pub fn with_suppressed(mut self, count: u64, first_seen: u64, last_seen: u64) -> Self {
+        self.suppressed_count = count;
+        self.first_seen = Some(first_seen);
+        self.last_seen = Some(last_seen);
+        self
+    }
+
+    pub fn with_response(mut self, response: Option<ResponseRecord>) -> Self {
+        self.response = response;
+        self
+    }
+
+    pub fn with_storm(mut self, storm: StormSummary) -> Self {
+        self.storm = Some(storm);
+        self
+    }
+
+    /// `action:outcome`, e.g. `terminate:dry_run`.
+    fn response_summary(&self) -> Option<String> {
+        self.response
+            .as_ref()
+            .map(|r| format!("{}:{}", r.action.as_str(), r.outcome.as_str()))
+    }
+
+    /// Ancestor images, nearest parent first, as `a.exe > b.exe`.
+    fn ancestry_chain(&self) -> String {
+        self.ancestry
+            .iter()
+            .map(|a| a.image.as_str())
+            .collect::<Vec<_>>()
+            .join(" > ")
+    }
+
+    /// `data_name`, or every matched rule comma-joined in `all` mode.
+    fn rule_names(&self) -> String {
+        if self.matched_rules.is_empty() {
+            self.data_name.clone()
+        } else {
+            self.matched_rules.join(",")
+        }
+    }
+
+    fn related_rules(&self) -> String {
+        let mut names: Vec<&str> = Vec::new();
+        for hit in &self.related {
+            if !names.contains(&hit.data_name.as_str()) {
+                names.push(&hit.data_name);
+            }
+        }
+        names.join(",")
+    }
+
+    /// Storm counts as `name:count,...`.
+    fn storm_counts(counts: &[StormCount]) -> String {
+        counts
+            .iter()
+            .map(|c| format!("{}:{}", c.name, c.count))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
     pub fn human_line(&self) -> String {
-        format!(
-            "[{}] pid={} proc={} event_id={} kind={} data={} target={} note={}",
-            self.ts_unix,
+        let mut line = format!(
+            "[{}] schema={} id={} pid={} proc={} event_id={} op={} kind={} data={} target={} note={}",
+            self.ts,
+            self.schema_version,
+            self.alert_id,
             self.pid,
             self.process,
             self.event_id,
+            self.operation.as_str(),
             self.kind,
             self.data_name,
             self.target,
             self.note
-        )
+        );
+        if !self.matched_rules.is_empty() {
+            line.push_str(&format!(" rules={}", self.rule_names()));
+        }
+        if !self.related.is_empty() {
+            line.push_str(&format!(" related={}", self.related_rules()));
+        }
+        if let Some(parent) = &self.parent_image {
+            line.push_str(&format!(" parent={parent}"));
+        }
+        if let Some(user) = &self.user {
+            line.push_str(&format!(" user={user}"));
+        }
+        if self.trust_unknown {
+            line.push_str(" trust=unknown");
+        }
+        if self.suppressed_count > 0 {
+            line.push_str(&format!(" suppressed={}", self.suppressed_count));
+        }
+        if let Some(response) = self.response_summary() {
+            line.push_str(&format!(" response={response}"));
+        }
+        if let Some(storm) = &self.storm {
+            line.push_str(&format!(
+                " storm_limited={} top_images={} top_rules={}",
+                storm.limited,
+                Self::storm_counts(&storm.top_images),
+                Self::storm_counts(&storm.top_rules)
+            ));
+        }
+        if !self.hostname.is_empty() {
+            line.push_str(&format!(" host={}", self.hostname));
+        }
+        if !self.sensor_id.is_empty() {
+            line.push_str(&format!(" sensor={}", self.sensor_id));
+        }
+        if !self.tags.is_empty() {
+            line.push_str(&format!(" tags={}", self.tags.join(",")));
+        }
+        if let Some(cmdline) = &self.cmdline {
+            line.push_str(&format!(" cmdline={cmdline}"));
+        }
+        line
+    }
+
+    /// Milliseconds since the epoch; whole seconds for an alert decoded from JSON.
+    fn unix_millis(&self) -> u64 {
+        if self.ts_ms > 0 {
+            self.ts_ms
+        } else {
+            self.ts_unix * 1000
+        }
     }
 
     pub fn cef_line(&self) -> String {
         let sev = match self.kind.as_str() {
+            "credential_sweep" | "denylisted_image" => 10,
             "suspicious_whitelisted_handle_access" => 9,
-            "protected_resource_access" => 8,
+            "protected_resource_access" | "unauthorized_accessor" | "sensitive_operation" => 8,
             _ => 6,
         };
-        format!(
-            "CEF:0|TITAN|Vigil|1.0|{}|{}|{}|src={} suser={} msg={} filePath={} cs1Label=ruleName cs1={} cs2Label=eventKind cs2={}",
+        let mut line = format!(
+            "CEF:0|TITAN|Vigil|{}|{}|{}|{}|externalId={} rt={} cn1Label=schemaVersion cn1={} src={} suser={} msg={} filePath={} cs1Label=ruleName cs1={} cs2Label=eventKind cs2={} act={}",
+            sanitize_cef(&self.sensor_version),
             self.event_id,
             sanitize_cef(&self.data_name),
             sev,
+            sanitize_cef(&self.alert_id),
+            self.unix_millis(),
+            self.schema_version,
             self.pid,
             sanitize_cef(&self.process),
             sanitize_cef(&self.note),
             sanitize_cef(&self.target),
-            sanitize_cef(&self.data_name),
-            sanitize_cef(&self.kind)
-        )
+            sanitize_cef(&self.rule_names()),
+            sanitize_cef(&self.kind),
+            self.operation.as_str()
+        );
+        if !self.related.is_empty() {
+            line.push_str(&format!(
+                " cs3Label=relatedRules cs3={}",
+                sanitize_cef(&self.related_rules())
+            ));
+        }
+        if let Some(parent) = &self.parent_image {
+            line.push_str(&format!(
+                " cs4Label=parentImage cs4={}",
+                sanitize_cef(parent)
+            ));
+        }
+        if let Some(cmdline) = &self.cmdline {
+            line.push_str(&format!(
+                " cs5Label=commandLine cs5={}",
+                sanitize_cef(cmdline)
+            ));
+        }
+        if !self.ancestry.is_empty() {
+            line.push_str(&format!(
+                " cs6Label=ancestry cs6={}",
+                sanitize_cef(&self.ancestry_chain())
+            ));
+        }
+        if let Some(user) = &self.user {
+            line.push_str(&format!(" suid={}", sanitize_cef(user)));
+        }
+        if !self.hostname.is_empty() {
+            line.push_str(&format!(" dvchost={}", sanitize_cef(&self.hostname)));
+        }
+        if !self.sensor_id.is_empty() {
+            line.push_str(&format!(
+                " deviceExternalId={}",
+                sanitize_cef(&self.sensor_id)
+            ));
+        }
+        if !self.tags.is_empty() {
+            line.push_str(&format!(" cat={}", sanitize_cef(&self.tags.join(","))));
+        }
+        if self.trust_unknown {
+            line.push_str(" flexString1Label=trust flexString1=unknown");
+        }
+        if let Some(storm) = &self.storm {
+            line.push_str(&format!(" cnt={}", storm.limited));
+        } else if self.suppressed_count > 0 {
+            line.push_str(&format!(" cnt={}", self.suppressed_count));
+        }
+        if let (Some(first), Some(last)) = (self.first_seen, self.last_seen) {
+            line.push_str(&format!(" start={} end={}", first * 1000, last * 1000));
+        }
+        if let Some(response) = self.response_summary() {
+            line.push_str(&format!(
+                " flexString2Label=response flexString2={}",
+                sanitize_cef(&response)
+            ));
+        }
+        line
     }
 
-    pub fn sigma_json(&self) -> serde_json::Value {
+    /// The `sigma_json` record for this alert.
+    pub fn sigma_record(&self) -> SigmaRecord {
         let mut tags = Vec::new();
-        let data_low = self.data_name.to_lowercase();
+        let data_low = self.rule_names().to_lowercase();
         if data_low.contains("cookie") {
             tags.push("attack.collection");
         }
         if data_low.contains("password") {
             tags.push("attack.credential_access");
         }
-        serde_json::json!({
-            "ts_unix": self.ts_unix,
-            "title": "TITAN Vigil protected resource access",
-            "logsource": {
-                "product": "windows",
-                "service": "kernel-etw",
-                "category": "file_access"
+        if self.kind == "credential_sweep" && !tags.contains(&"attack.credential_access") {
+            tags.push("attack.credential_access");
+        }
+        let level = match self.kind.as_str() {
+            "credential_sweep" | "denylisted_image" => "critical",
+            _ => "high",
+        };
+        SigmaRecord {
+            schema_version: self.schema_version,
+            alert_id: self.alert_id.clone(),
+            ts: self.ts.clone(),
+            ts_unix: self.ts_unix,
+            sensor_id: self.sensor_id.clone(),
+            hostname: self.hostname.clone(),
+            sensor_version: self.sensor_version.clone(),
+            sensor_tags: self.tags.clone(),
+            title: "TITAN Vigil protected resource access".to_string(),
+            logsource: SigmaLogSource {
+                product: "windows".to_string(),
+                service: "kernel-etw".to_string(),
+                category: "file_access".to_string(),
             },
-            "detection": {
-                "pid": self.pid,
-                "process": self.process,
-                "file_target": self.target,
-                "rule_name": self.data_name,
-                "event_id": self.event_id,
-                "kind": self.kind,
+            detection: SigmaDetection {
+                pid: self.pid,
+                process: self.process.clone(),
+                file_target: self.target.clone(),
+                rule_name: self.data_name.clone(),
+                matched_rules: self.matched_rules.clone(),
+                event_id: self.event_id,
+                operation: self.operation,
+                kind: self.kind.clone(),
+                parent_image: self.parent_image.clone(),
+                cmdline: self.cmdline.clone(),
+                user: self.user.clone(),
+                trust_unknown: self.trust_unknown,
             },
-            "level": "high",
-            "tags": tags,
-            "note": self.note
-        })
+            ancestry: self.ancestry.clone(),
+            related: self.related.clone(),
+            response: self.response.clone(),
+            storm: self.storm.clone(),
+            suppressed_count: self.suppressed_count,
+            first_seen: self.first_seen,
+            last_seen: self.last_seen,
+            level: level.to_string(),
+            tags: tags.into_iter().map(str::to_string).collect(),
+            note: self.note.clone(),
+        }
     }
+
+    pub fn sigma_json(&self) -> serde_json::Value {
+        serde_json::to_value(self.sigma_record()).unwrap_or_default()
+    }
+}
+
+/// One `sigma_json` record. Its JSON Schema is `output::schema::sigma_json_schema`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SigmaRecord {
+    pub schema_version: u32,
+    pub alert_id: String,
+    pub ts: String,
+    pub ts_unix: u64,
+    pub sensor_id: String,
+    pub hostname: String,
+    pub sensor_version: String,
+    pub sensor_tags: Vec<String>,
+    pub title: String,
+    pub logsource: SigmaLogSource,
+    pub detection: SigmaDetection,
+    pub ancestry: Vec<Ancestor>,
+    pub related: Vec<RelatedHit>,
+    pub response: Option<ResponseRecord>,
+    pub storm: Option<StormSummary>,
+    pub suppressed_count: u64,
+    pub first_seen: Option<u64>,
+    pub last_seen: Option<u64>,
+    pub level: String,
+    /// ATT&CK tags derived from the rule names.
+    pub tags: Vec<String>,
+    pub note: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SigmaLogSource {
+    pub product: String,
+    pub service: String,
+    pub category: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SigmaDetection {
+    pub pid: u32,
+    pub process: String,
+    pub file_target: String,
+    pub rule_name: String,
+    pub matched_rules: Vec<String>,
+    pub event_id: u16,
+    pub operation: FileOperation,
+    pub kind: String,
+    pub parent_image: Option<String>,
+    pub cmdline: Option<String>,
+    pub user: Option<String>,
+    pub trust_unknown: bool,
 }
 
 #[derive(Clone, Copy)]
@@ -134,6 +590,15 @@ impl LogFormat {
         }
     }
 
+    fn name(self) -> &'static str {
+        match self {
+            Self::Jsonl => "jsonl",
+            Self::Text => "text",
+            Self::Cef => "cef",
+            Self::SigmaJson => "sigma_json",
+        }
+    }
+
     fn file_name(self) -> &'static str {
         match self {
             Self::Jsonl => "alerts.jsonl",
@@ -149,13 +614,82 @@ struct SinkWriter {
     writer: Mutex<BufWriter<File>>,
 }
 
-pub struct AlertLogger {
+/// Open log files for the configured formats.
+pub struct Sinks {
     paths: BTreeMap<String, PathBuf>,
-    sinks: Vec<SinkWriter>,
+    writers: Vec<SinkWriter>,
+}
+
+pub struct AlertLogger {
+    log_dir: PathBuf,
+    sinks: RwLock<Sinks>,
+    metrics: Option<Arc<Metrics>>,
 }
 
 impl AlertLogger {
     pub fn new(log_dir: &Path, cfg: &Config) -> Result<Self> {
+        Ok(Self {
+            log_dir: log_dir.to_path_buf(),
+            sinks: RwLock::new(Sinks::open(log_dir, cfg)?),
+            metrics: None,
+        })
+    }
+
+    /// Count failed writes per sink in `metrics`.
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Open the sinks for `cfg` without replacing the current ones.
+    pub fn open_sinks(&self, cfg: &Config) -> Result<Sinks> {
+        Sinks::open(&self.log_dir, cfg)
+    }
+
+    /// Write to `sinks` from now on, closing the current ones.
+    pub fn install_sinks(&self, sinks: Sinks) {
+        *self.sinks.write() = sinks;
+    }
+
+    pub fn primary_log_path(&self) -> Option<PathBuf> {
+        self.sinks.read().paths.values().next().cloned()
+    }
+
+    /// Write `alert` to every sink. A failing sink does not stop the others; the first
+    /// error is returned.
+    pub fn write(&self, alert: &Alert) -> Result<()> {
+        let mut first_err = None;
+        for sink in &self.sinks.read().writers {
+            if let Err(e) = sink.write(alert) {
+                if let Some(metrics) = &self.metrics {
+                    metrics.record_sink_error(sink.format.name());
+                }
+                first_err.get_or_insert(
+                    e.context(format!("failed to write {} alert", sink.format.name())),
+                );
+            }
+        }
+        first_err.map_or(Ok(()), Err)
+    }
+}
+
+impl SinkWriter {
+    fn write(&self, alert: &Alert) -> Result<()> {
+        let mut w = self.writer.lock();
+        match self.format {
+            LogFormat::Jsonl => serde_json::to_writer(&mut *w, alert)?,
+            LogFormat::Text => w.write_all(alert.human_line().as_bytes())?,
+            LogFormat::Cef => w.write_all(alert.cef_line().as_bytes())?,
+            LogFormat::SigmaJson => serde_json::to_writer(&mut *w, &alert.sigma_record())?,
+        }
+        w.write_all(b"\n")?;
+        w.flush()?;
+        Ok(())
+    }
+}
+
+impl Sinks {
+    fn open(log_dir: &Path, cfg: &Config) -> Result<Self> {
         let mut requested = cfg.siem.formats.clone();
         if !cfg.siem.enabled {
             requested.clear();
@@ -167,52 +701,26 @@ impl AlertLogger {
         }
 
         let mut paths = BTreeMap::new();
-        let mut sinks = Vec::new();
+        let mut writers = Vec::new();
         for value in requested {
             let Some(format) = LogFormat::parse(&value) else {
                 continue;
             };
             let (path, file) = open_sink_file(log_dir, format.file_name())
                 .with_context(|| format!("failed to open log sink for format {}", value))?;
-            sinks.push(SinkWriter {
+            writers.push(SinkWriter {
                 format,
                 writer: Mutex::new(BufWriter::new(file)),
             });
             paths.insert(value, path);
         }
 
-        Ok(Self { paths, sinks })
-    }
-
-    pub fn primary_log_path(&self) -> Option<&Path> {
-        self.paths.values().next().map(|p| p.as_path())
+        Ok(Self { paths, writers })
     }
+}
 
-    pub fn write(&self, alert: &Alert) -> Result<()> {
-        for sink in &self.sinks {
-            let mut w = sink.writer.lock();
-            match sink.format {
-                LogFormat::Jsonl => {
-                    serde_json::to_writer(&mut *w, alert)?;
-                    w.write_all(b"\n")?;
-                }
-                LogFormat::Text => {
-                    w.write_all(alert.human_line().as_bytes())?;
-                    w.write_all(b"\n")?;
-                }
-                LogFormat::Cef => {
-                    w.write_all(alert.cef_line().as_bytes())?;
-                    w.write_all(b"\n")?;
-                }
-                LogFormat::SigmaJson => {
-                    serde_json::to_writer(&mut *w, &alert.sigma_json())?;
-                    w.write_all(b"\n")?;
-                }
-            }
-            w.flush()?;
-        }
-        Ok(())
-    }
+fn is_zero(n: &u64) -> bool {
+    *n == 0
 }
 
 fn sanitize_cef(input: &str) -> String {
@@ -275,6 +783,7 @@ mod tests {
                 quiet: true,
                 jsonl: true,
                 suppress_ms: 1500,
+                ..GeneralConfig::default()
             },
             watch: WatchConfig::default(),
             allowlist: AllowlistConfig::default(),
@@ -288,6 +797,12 @@ mod tests {
                 sigma_rules_file: "sigma_rules.yml".to_string(),
             },
             trust_api: TrustApiConfig::default(),
+            correlation: Default::default(),
+            trust_cache: Default::default(),
+            response: Default::default(),
+            rate_limit: Default::default(),
+            metrics: Default::default(),
+            vars: Default::default(),
         }
     }
 
@@ -304,7 +819,10 @@ mod tests {
         );
 
         let cef = alert.cef_line();
-        assert!(cef.starts_with("CEF:0|TITAN|Vigil|1.0|12|"));
+        assert!(cef.starts_with(&format!(
+            "CEF:0|TITAN|Vigil|{}|12|",
+            env!("CARGO_PKG_VERSION")
+        )));
         assert!(cef.contains(r"Name\|Eq\=Test"));
         assert!(cef.contains(r"filePath=C:\\target\=a\\file"));
         assert!(cef.contains("msg=line1 line2"));
@@ -334,6 +852,131 @@ mod tests {
         );
     }
 
+    #[test]
+    fn operation_is_rendered_by_every_format() {
+        let alert = Alert::new(
+            30,
+            "proc.exe".to_string(),
+            r"C:\wallet.dat".to_string(),
+            "Wallet".to_string(),
+            18,
+            "sensitive_operation",
+            "note",
+        )
+        .with_operation(FileOperation::Delete);
+
+        assert!(alert.human_line().contains(" op=delete "));
+        assert!(alert.cef_line().ends_with(" act=delete"));
+        assert_eq!(alert.sigma_json()["detection"]["operation"], "delete");
+        let json = serde_json::to_value(&alert).expect("serialize");
+        assert_eq!(json["operation"], "delete");
+    }
+
+    #[test]
+    fn lineage_is_rendered_by_every_format() {
+        let alert = Alert::new(
+            40,
+            "powershell.exe".to_string(),
+            "Login Data".to_string(),
+            "Chrome Passwords".to_string(),
+            15,
+            "protected_resource_access",
+            "note",
+        )
+        .with_lineage(
+            Some("powershell.exe -enc AAAA=".to_string()),
+            vec![
+                Ancestor {
+                    pid: 30,
+                    image: "winword.exe".to_string(),
+                },
+                Ancestor {
+                    pid: 20,
+                    image: "explorer.exe".to_string(),
+                },
+            ],
+        )
+        .with_user(Some(r"CORP\bob".to_string()));
+
+        assert_eq!(alert.parent_image.as_deref(), Some("winword.exe"));
+        assert!(alert.human_line().contains(" parent=winword.exe "));
+        assert!(alert.human_line().contains(r" user=CORP\bob "));
+        let cef = alert.cef_line();
+        assert!(cef.contains(" suid=CORP"));
+        assert!(cef.contains("cs4=winword.exe"));
+        assert!(cef.contains(r"cs5=powershell.exe -enc AAAA\="));
+        assert!(cef.contains("cs6=winword.exe > explorer.exe"));
+        let sigma = alert.sigma_json();
+        assert_eq!(sigma["detection"]["parent_image"], "winword.exe");
+        assert_eq!(sigma["ancestry"][1]["pid"], 20);
+        assert_eq!(sigma["detection"]["user"], r"CORP\bob");
+        let json = serde_json::to_value(&alert).expect("serialize");
+        assert_eq!(json["cmdline"], "powershell.exe -enc AAAA=");
+        assert_eq!(json["ancestry"][0]["image"], "winword.exe");
+    }
+
+    #[test]
+    fn envelope_is_rendered_by_every_format() {
+        let identity = SensorIdentity {
+            sensor_id: "01HZY3M2Q8X4K7N5B6C9D0E1F2".to_string(),
+            hostname: "WS-0142".to_string(),
+        };
+        let alert = Alert::new(
+            40,
+            "stealer.exe".to_string(),
+            "Login Data".to_string(),
+            "Chrome Passwords".to_string(),
+            15,
+            "protected_resource_access",
+            "note",
+        )
+        .with_sensor(&identity, &["finance".to_string(), "emea".to_string()]);
+        let other = Alert::new(
+            40,
+            String::new(),
+            String::new(),
+            String::new(),
+            15,
+            "k",
+            "n",
+        );
+
+        assert_eq!(alert.alert_id.len(), 26);
+        assert_ne!(alert.alert_id, other.alert_id);
+        assert_eq!(alert.ts.len(), "2023-11-14T22:13:20.123Z".len());
+        assert!(alert.ts.ends_with('Z'));
+        assert_eq!(alert.unix_millis() / 1000, alert.ts_unix);
+        assert_eq!(
+            crate::support::identity::rfc3339_millis(alert.unix_millis()),
+            alert.ts
+        );
+
+        let human = alert.human_line();
+        assert!(human.starts_with(&format!("[{}] schema=1 id={} ", alert.ts, alert.alert_id)));
+        assert!(
+            human.contains(" host=WS-0142 sensor=01HZY3M2Q8X4K7N5B6C9D0E1F2 tags=finance,emea")
+        );
+        let cef = alert.cef_line();
+        assert!(cef.starts_with(&format!("CEF:0|TITAN|Vigil|{}|", env!("CARGO_PKG_VERSION"))));
+        assert!(cef.contains(&format!(
+            "externalId={} rt={}",
+            alert.alert_id,
+            alert.unix_millis()
+        )));
+        assert!(cef.contains(
+            "dvchost=WS-0142 deviceExternalId=01HZY3M2Q8X4K7N5B6C9D0E1F2 cat=finance,emea"
+        ));
+        let sigma = alert.sigma_json();
+        assert_eq!(sigma["alert_id"], alert.alert_id);
+        assert_eq!(sigma["hostname"], "WS-0142");
+        assert_eq!(sigma["sensor_tags"][1], "emea");
+        let json = serde_json::to_value(&alert).expect("serialize");
+        assert_eq!(json["ts"], alert.ts);
+        assert_eq!(json["sensor_id"], "01HZY3M2Q8X4K7N5B6C9D0E1F2");
+        assert_eq!(json["sensor_version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(json["tags"][0], "finance");
+    }
+
     #[test]
     fn logger_writes_configured_sink_files() {
         let ts = SystemTime::now()
@@ -162,6 +162,8 @@ mod tests {
 
         let got = recv_thread.join().expect("join recv thread");
         let decoded: Alert = serde_json::from_slice(&got).expect("decode alert json");
+        assert_eq!(decoded.alert_id, expected.alert_id);
+        assert_eq!(decoded.ts, expected.ts);
         assert_eq!(decoded.pid, expected.pid);
         assert_eq!(decoded.process, expected.process);
         assert_eq!(decoded.target, expected.target);
@@ -1,5 +1,7 @@
 pub mod alerts;
 #[cfg(feature = "remote_endpoint")]
 pub mod endpoint;
+#[cfg(windows)]
 pub mod notify;
+pub mod schema;
 pub mod siem;
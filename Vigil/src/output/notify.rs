@@ -1,4 +1,4 @@
-use crate::output::alerts::Alert;
+use crate::{output::alerts::Alert, telemetry::event::FileOperation};
 use std::{
     collections::HashMap,
     sync::{Mutex, OnceLock},
@@ -62,10 +62,14 @@ fn xml_escape(s: &str) -> String {
     out
 }
 
-fn verb_from_event(event_id: u16) -> &'static str {
-    match event_id {
-        12 => "accessed",
-        _ => "touched",
+fn verb_from_event(operation: FileOperation) -> &'static str {
+    match operation {
+        FileOperation::Create => "opened",
+        FileOperation::Read => "read",
+        FileOperation::Write => "wrote to",
+        FileOperation::Delete => "deleted",
+        FileOperation::Rename => "renamed",
+        FileOperation::SetInfo => "modified",
     }
 }
 
@@ -108,7 +112,7 @@ pub fn toast_from_alert(alert: &Alert) {
     }
 
     let proc = exe_basename(&alert.process);
-    let verb = verb_from_event(alert.event_id);
+    let verb = verb_from_event(alert.operation);
     let headline = format!("{proc} {verb} {}", alert.data_name);
 
     if let Err(e) = show_toast(&headline) {
@@ -0,0 +1,611 @@
+use serde_json::{Map, Value, json};
+
+/// Version of every alert record shape. Bump it whenever a field is removed, renamed
+/// or retyped; added optional fields keep the version, so the schemas accept
+/// properties they do not list.
+pub const ALERT_SCHEMA_VERSION: u32 = 1;
+
+const OPERATIONS: &[&str] = &["create", "read", "write", "delete", "rename", "set_info"];
+const RESPONSE_ACTIONS: &[&str] = &["none", "suspend", "terminate", "quarantine_image"];
+const RESPONSE_OUTCOMES: &[&str] = &["executed", "dry_run", "skipped", "failed"];
+
+/// JSON Schema for each `siem.formats` value, keyed by format name.
+pub fn alert_schemas() -> Value {
+    json!({
+        "schema_version": ALERT_SCHEMA_VERSION,
+        "formats": {
+            "jsonl": jsonl_schema(),
+            "sigma_json": sigma_json_schema(),
+            "cef": cef_schema(),
+            "text": text_schema(),
+        }
+    })
+}
+
+/// One `alerts.jsonl` line: a serialized `Alert`.
+pub fn jsonl_schema() -> Value {
+    let mut schema = object(
+        &[
+            ("schema_version", integer()),
+            ("alert_id", string()),
+            ("ts", timestamp()),
+            ("ts_unix", integer()),
+            ("sensor_version", string()),
+            ("pid", integer()),
+            ("process", string()),
+            ("target", string()),
+            ("data_name", string()),
+            ("event_id", integer()),
+            ("operation", one_of(OPERATIONS)),
+            ("kind", string()),
+            ("note", string()),
+        ],
+        &[
+            ("sensor_id", string()),
+            ("hostname", string()),
+            ("tags", array(string())),
+            ("matched_rules", array(string())),
+            ("related", array(related_hit())),
+            ("parent_image", string()),
+            ("cmdline", string()),
+            ("user", string()),
+            ("ancestry", array(ancestor())),
+            ("trust_unknown", boolean()),
+            ("suppressed_count", integer()),
+            ("first_seen", integer()),
+            ("last_seen", integer()),
+            ("response", response_record()),
+            ("storm", storm_summary()),
+        ],
+    );
+    document(&mut schema, "TITAN Vigil alert (jsonl)");
+    schema
+}
+
+/// One `alerts.sigma.jsonl` line: a serialized `SigmaRecord`.
+pub fn sigma_json_schema() -> Value {
+    let logsource = object(
+        &[
+            ("product", string()),
+            ("service", string()),
+            ("category", string()),
+        ],
+        &[],
+    );
+    let detection = object(
+        &[
+            ("pid", integer()),
+            ("process", string()),
+            ("file_target", string()),
+            ("rule_name", string()),
+            ("matched_rules", array(string())),
+            ("event_id", integer()),
+            ("operation", one_of(OPERATIONS)),
+            ("kind", string()),
+            ("parent_image", nullable(string())),
+            ("cmdline", nullable(string())),
+            ("user", nullable(string())),
+            ("trust_unknown", boolean()),
+        ],
+        &[],
+    );
+    let mut schema = object(
+        &[
+            ("schema_version", integer()),
+            ("alert_id", string()),
+            ("ts", timestamp()),
+            ("ts_unix", integer()),
+            ("sensor_id", string()),
+            ("hostname", string()),
+            ("sensor_version", string()),
+            ("sensor_tags", array(string())),
+            ("title", string()),
+            ("logsource", logsource),
+            ("detection", detection),
+            ("ancestry", array(ancestor())),
+            ("related", array(related_hit())),
+            ("response", nullable(response_record())),
+            ("storm", nullable(storm_summary())),
+            ("suppressed_count", integer()),
+            ("first_seen", nullable(integer())),
+            ("last_seen", nullable(integer())),
+            ("level", one_of(&["high", "critical"])),
+            ("tags", array(string())),
+            ("note", string()),
+        ],
+        &[],
+    );
+    document(&mut schema, "TITAN Vigil alert (sigma_json)");
+    schema
+}
+
+/// The header and extension keys of one `alerts.cef` line, as a flat object.
+pub fn cef_schema() -> Value {
+    let mut schema = object(
+        &[
+            ("deviceVersion", string()),
+            ("signatureId", integer()),
+            ("name", string()),
+            ("severity", integer()),
+            ("externalId", string()),
+            ("rt", integer()),
+            ("cn1", integer()),
+            ("src", integer()),
+            ("suser", string()),
+            ("msg", string()),
+            ("filePath", string()),
+            ("cs1", string()),
+            ("cs2", string()),
+            ("act", one_of(OPERATIONS)),
+        ],
+        &[
+            ("cs3", string()),
+            ("cs4", string()),
+            ("cs5", string()),
+            ("cs6", string()),
+            ("suid", string()),
+            ("dvchost", string()),
+            ("deviceExternalId", string()),
+            ("cat", string()),
+            ("flexString1", string()),
+            ("cnt", integer()),
+            ("start", integer()),
+            ("end", integer()),
+            ("flexString2", string()),
+        ],
+    );
+    document(&mut schema, "TITAN Vigil alert (cef)");
+    let fields = schema["properties"].as_object_mut().expect("object schema");
+    for (key, label) in [
+        ("cn1", "schemaVersion"),
+        ("cs1", "ruleName"),
+        ("cs2", "eventKind"),
+        ("cs3", "relatedRules"),
+        ("cs4", "parentImage"),
+        ("cs5", "commandLine"),
+        ("cs6", "ancestry"),
+        ("flexString1", "trust"),
+        ("flexString2", "response"),
+    ] {
+        fields[key]["description"] = json!(format!("{key}Label={label}"));
+    }
+    schema
+}
+
+/// The `key=value` pairs of one `alerts.log` line, as a flat object. The line opens
+/// with `[<ts>]`, given here as `ts`.
+pub fn text_schema() -> Value {
+    let mut schema = object(
+        &[
+            ("ts", timestamp()),
+            ("schema", integer()),
+            ("id", string()),
+            ("pid", integer()),
+            ("proc", string()),
+            ("event_id", integer()),
+            ("op", one_of(OPERATIONS)),
+            ("kind", string()),
+            ("data", string()),
+            ("target", string()),
+            ("note", string()),
+        ],
+        &[
+            ("rules", string()),
+            ("related", string()),
+            ("parent", string()),
+            ("user", string()),
+            ("trust", one_of(&["unknown"])),
+            ("suppressed", integer()),
+            ("response", string()),
+            ("storm_limited", integer()),
+            ("top_images", string()),
+            ("top_rules", string()),
+            ("host", string()),
+            ("sensor", string()),
+            ("tags", string()),
+            ("cmdline", string()),
+        ],
+    );
+    document(&mut schema, "TITAN Vigil alert (text)");
+    schema
+}
+
+fn document(schema: &mut Value, title: &str) {
+    schema["$schema"] = json!("https://json-schema.org/draft/2020-12/schema");
+    schema["title"] = json!(title);
+    schema["description"] = json!(format!("schema_version {ALERT_SCHEMA_VERSION}"));
+}
+
+fn object(required: &[(&str, Value)], optional: &[(&str, Value)]) -> Value {
+    let properties: Map<String, Value> = required
+        .iter()
+        .chain(optional)
+        .map(|(name, schema)| (name.to_string(), schema.clone()))
+        .collect();
+    let required: Vec<&str> = required.iter().map(|(name, _)| *name).collect();
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": true,
+    })
+}
+
+fn string() -> Value {
+    json!({ "type": "string" })
+}
+
+fn timestamp() -> Value {
+    json!({ "type": "string", "format": "date-time" })
+}
+
+fn integer() -> Value {
+    json!({ "type": "integer", "minimum": 0 })
+}
+
+fn boolean() -> Value {
+    json!({ "type": "boolean" })
+}
+
+fn array(items: Value) -> Value {
+    json!({ "type": "array", "items": items })
+}
+
+fn one_of(values: &[&str]) -> Value {
+    json!({ "type": "string", "enum": values })
+}
+
+fn nullable(schema: Value) -> Value {
+    json!({ "anyOf": [schema, { "type": "null" }] })
+}
+
+fn ancestor() -> Value {
+    object(&[("pid", integer()), ("image", string())], &[])
+}
+
+fn related_hit() -> Value {
+    object(&[("data_name", string()), ("target", string())], &[])
+}
+
+fn response_record() -> Value {
+    object(
+        &[
+            ("action", one_of(RESPONSE_ACTIONS)),
+            ("outcome", one_of(RESPONSE_OUTCOMES)),
+            ("detail", string()),
+            ("ts_unix", integer()),
+        ],
+        &[],
+    )
+}
+
+fn storm_summary() -> Value {
+    let count = object(&[("name", string()), ("count", integer())], &[]);
+    object(
+        &[
+            ("limited", integer()),
+            ("window_ms", integer()),
+            ("top_images", array(count.clone())),
+            ("top_rules", array(count)),
+        ],
+        &[],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        output::alerts::{
+            Alert, Ancestor, RelatedHit, ResponseOutcome, ResponseRecord, StormCount, StormSummary,
+        },
+        support::{config::ResponseKind, identity::SensorIdentity},
+        telemetry::event::FileOperation,
+    };
+
+    /// Every violation of `schema` by `value`, covering the keywords emitted above.
+    fn violations(schema: &Value, value: &Value, path: &str, out: &mut Vec<String>) {
+        if let Some(options) = schema["anyOf"].as_array() {
+            let matches = options.iter().any(|option| {
+                let mut errs = Vec::new();
+                violations(option, value, path, &mut errs);
+                errs.is_empty()
+            });
+            if !matches {
+                out.push(format!("{path}: matches no anyOf branch"));
+            }
+            return;
+        }
+        let type_ok = match schema["type"].as_str() {
+            Some("object") => value.is_object(),
+            Some("array") => value.is_array(),
+            Some("string") => value.is_string(),
+            Some("integer") => value.is_u64(),
+            Some("boolean") => value.is_boolean(),
+            _ => true,
+        };
+        if !type_ok {
+            out.push(format!("{path}: expected {}, got {value}", schema["type"]));
+            return;
+        }
+        if let Some(allowed) = schema["enum"].as_array()
+            && !allowed.contains(value)
+        {
+            out.push(format!("{path}: {value} not in enum"));
+        }
+        if let (Some(items), Some(values)) = (schema.get("items"), value.as_array()) {
+            for (i, item) in values.iter().enumerate() {
+                violations(items, item, &format!("{path}[{i}]"), out);
+            }
+        }
+        if let Some(fields) = value.as_object() {
+            let properties = &schema["properties"];
+            for name in schema["required"].as_array().into_iter().flatten() {
+                let name = name.as_str().unwrap_or_default();
+                if !fields.contains_key(name) {
+                    out.push(format!("{path}.{name}: required field missing"));
+                }
+            }
+            for (name, field) in fields {
+                if let Some(field_schema) = properties.get(name) {
+                    violations(field_schema, field, &format!("{path}.{name}"), out);
+                }
+            }
+        }
+    }
+
+    fn assert_valid(schema: &Value, value: &Value) {
+        let mut out = Vec::new();
+        violations(schema, value, "$", &mut out);
+        assert!(out.is_empty(), "{out:#?}");
+    }
+
+    fn minimal_alert() -> Alert {
+        Alert::new(
+            42,
+            r"C:\Temp\stealer.exe".to_string(),
+            r"C:\Users\bob\Login Data".to_string(),
+            "Chrome Passwords".to_string(),
+            12,
+            "protected_resource_access",
+            "untrusted process",
+        )
+    }
+
+    fn full_alert() -> Alert {
+        let identity = SensorIdentity {
+            sensor_id: "01HZY3M2Q8X4K7N5B6C9D0E1F2".to_string(),
+            hostname: "WS-0142".to_string(),
+        };
+        let mut alert = minimal_alert()
+            .with_operation(FileOperation::SetInfo)
+            .with_matched_rules(vec!["Chrome Passwords".to_string(), "Chrome".to_string()])
+            .with_related(vec![RelatedHit {
+                data_name: "Chrome Cookies".to_string(),
+                target: "Cookies".to_string(),
+            }])
+            .with_lineage(
+                Some("stealer.exe --all".to_string()),
+                vec![Ancestor {
+                    pid: 7,
+                    image: "cmd.exe".to_string(),
+                }],
+            )
+            .with_user(Some(r"CORP\bob".to_string()))
+            .with_trust_unknown(true)
+            .with_suppressed(3, 1_700_000_000, 1_700_000_009)
+            .with_response(Some(ResponseRecord::new(
+                ResponseKind::QuarantineImage,
+                ResponseOutcome::DryRun,
+                "would quarantine_image".to_string(),
+            )))
+            .with_storm(StormSummary {
+                limited: 120,
+                window_ms: 10_000,
+                top_images: vec![StormCount {
+                    name: "backup.exe".to_string(),
+                    count: 120,
+                }],
+                top_rules: vec![StormCount {
+                    name: "Chrome Passwords".to_string(),
+                    count: 120,
+                }],
+            })
+            .with_sensor(&identity, &["finance".to_string()]);
+        alert.parent_image = Some("cmd.exe".to_string());
+        alert
+    }
+
+    /// Key/value pairs of a text or CEF line, header fields included.
+    fn line_fields(line: &str, keys: &[&str]) -> Value {
+        let mut fields = Map::new();
+        for key in keys {
+            let needle = format!(" {key}=");
+            let Some(start) = line.find(&needle).map(|at| at + needle.len()) else {
+                continue;
+            };
+            let rest = &line[start..];
+            let end = keys
+                .iter()
+                .filter_map(|next| rest.find(&format!(" {next}=")))
+                .min()
+                .unwrap_or(rest.len());
+            let raw = rest[..end].to_string();
+            let value = raw
+                .parse::<u64>()
+                .map(Value::from)
+                .unwrap_or(Value::from(raw));
+            fields.insert(key.to_string(), value);
+        }
+        Value::Object(fields)
+    }
+
+    fn schema_keys(schema: &Value) -> Vec<&str> {
+        schema["properties"]
+            .as_object()
+            .map(|p| p.keys().map(String::as_str).collect())
+            .unwrap_or_default()
+    }
+
+    /// `value` carries exactly the schema's properties. The full alert fills every one,
+    /// so neither the schema nor the serializer can drift from the other.
+    fn assert_same_fields(schema: &Value, value: &Value) {
+        let listed = schema_keys(schema);
+        let present: Vec<&str> = value
+            .as_object()
+            .map(|o| o.keys().map(String::as_str).collect())
+            .unwrap_or_default();
+        let unlisted: Vec<_> = present.iter().filter(|k| !listed.contains(k)).collect();
+        let missing: Vec<_> = listed.iter().filter(|k| !present.contains(k)).collect();
+        assert!(
+            unlisted.is_empty() && missing.is_empty(),
+            "not in schema: {unlisted:?}, not serialized: {missing:?}"
+        );
+    }
+
+    #[test]
+    fn jsonl_and_sigma_records_match_their_schemas() {
+        for alert in [minimal_alert(), full_alert()] {
+            let jsonl = serde_json::to_value(&alert).expect("serialize alert");
+            assert_eq!(jsonl["schema_version"], ALERT_SCHEMA_VERSION);
+            assert_valid(&jsonl_schema(), &jsonl);
+            assert_valid(&sigma_json_schema(), &alert.sigma_json());
+        }
+
+        let alert = full_alert();
+        assert_same_fields(
+            &jsonl_schema(),
+            &serde_json::to_value(&alert).expect("serialize alert"),
+        );
+        let (schema, sigma) = (sigma_json_schema(), alert.sigma_json());
+        assert_same_fields(&schema, &sigma);
+        assert_same_fields(&schema["properties"]["detection"], &sigma["detection"]);
+    }
+
+    #[test]
+    fn line_formats_match_their_schemas() {
+        let alert = full_alert();
+
+        let schema = cef_schema();
+        let keys: Vec<&str> = schema_keys(&schema)
+            .into_iter()
+            .chain([
+                "cn1Label",
+                "cs1Label",
+                "cs2Label",
+                "cs3Label",
+                "cs4Label",
+                "cs5Label",
+                "cs6Label",
+                "flexString1Label",
+                "flexString2Label",
+            ])
+            .collect();
+        let cef = alert.cef_line();
+        let header: Vec<&str> = cef.splitn(8, '|').collect();
+        let mut fields = line_fields(&format!(" {}", header[7]), &keys);
+        let fields_map = fields.as_object_mut().expect("fields");
+        fields_map.retain(|key, _| !key.ends_with("Label"));
+        fields_map.insert("deviceVersion".to_string(), json!(header[3]));
+        fields_map.insert(
+            "signatureId".to_string(),
+            json!(header[4].parse::<u64>().ok()),
+        );
+        fields_map.insert("name".to_string(), json!(header[5]));
+        fields_map.insert("severity".to_string(), json!(header[6].parse::<u64>().ok()));
+        assert_valid(&schema, &fields);
+        assert_same_fields(&schema, &fields);
+        assert_eq!(fields["cn1"], ALERT_SCHEMA_VERSION);
+
+        let schema = text_schema();
+        let human = alert.human_line();
+        let (ts, rest) = human
+            .trim_start_matches('[')
+            .split_once(']')
+            .expect("timestamp");
+        let mut fields = line_fields(rest, &schema_keys(&schema));
+        fields["ts"] = json!(ts);
+        assert_valid(&schema, &fields);
+        assert_same_fields(&schema, &fields);
+    }
+
+    #[test]
+    fn published_fields_keep_their_names_and_types() {
+        // Removing or retyping any of these needs a new ALERT_SCHEMA_VERSION.
+        const JSONL: &[(&str, &str)] = &[
+            ("schema_version", "integer"),
+            ("alert_id", "string"),
+            ("ts", "string"),
+            ("ts_unix", "integer"),
+            ("sensor_id", "string"),
+            ("hostname", "string"),
+            ("sensor_version", "string"),
+            ("tags", "array"),
+            ("pid", "integer"),
+            ("process", "string"),
+            ("target", "string"),
+            ("data_name", "string"),
+            ("matched_rules", "array"),
+            ("event_id", "integer"),
+            ("operation", "string"),
+            ("kind", "string"),
+            ("note", "string"),
+            ("related", "array"),
+            ("parent_image", "string"),
+            ("cmdline", "string"),
+            ("ancestry", "array"),
+            ("trust_unknown", "boolean"),
+            ("suppressed_count", "integer"),
+            ("first_seen", "integer"),
+            ("last_seen", "integer"),
+            ("response", "object"),
+            ("storm", "object"),
+        ];
+        const SIGMA: &[(&str, &str)] = &[
+            ("schema_version", "integer"),
+            ("alert_id", "string"),
+            ("ts", "string"),
+            ("ts_unix", "integer"),
+            ("sensor_id", "string"),
+            ("hostname", "string"),
+            ("sensor_version", "string"),
+            ("sensor_tags", "array"),
+            ("title", "string"),
+            ("logsource", "object"),
+            ("detection", "object"),
+            ("ancestry", "array"),
+            ("related", "array"),
+            ("suppressed_count", "integer"),
+            ("level", "string"),
+            ("tags", "array"),
+            ("note", "string"),
+        ];
+        const SIGMA_DETECTION: &[(&str, &str)] = &[
+            ("pid", "integer"),
+            ("process", "string"),
+            ("file_target", "string"),
+            ("rule_name", "string"),
+            ("matched_rules", "array"),
+            ("event_id", "integer"),
+            ("operation", "string"),
+            ("kind", "string"),
+            ("trust_unknown", "boolean"),
+        ];
+
+        let check = |schema: &Value, pinned: &[(&str, &str)]| {
+            for (name, ty) in pinned {
+                assert_eq!(
+                    schema["properties"][name]["type"], *ty,
+                    "field '{name}' was removed or retyped"
+                );
+            }
+        };
+        check(&jsonl_schema(), JSONL);
+        let sigma = sigma_json_schema();
+        check(&sigma, SIGMA);
+        check(&sigma["properties"]["detection"], SIGMA_DETECTION);
+        for nullable in ["response", "storm", "first_seen", "last_seen"] {
+            assert!(sigma["properties"][nullable]["anyOf"].is_array());
+        }
+    }
+}
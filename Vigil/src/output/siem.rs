@@ -1,4 +1,7 @@
-use crate::support::config::Config;
+use crate::{
+    runtime::rules::{VOLUME, glob_to_regex},
+    support::config::{Config, MatchKind},
+};
 use anyhow::{Context, Result};
 use std::{
     fs,
@@ -19,7 +22,7 @@ pub fn generate_sigma_rules(cfg: &Config, log_dir: &Path) -> Result<Option<PathB
 
     let mut content = String::new();
     for rule in &cfg.watch.protected {
-        let rule_id = stable_rule_id(&rule.substring);
+        let rule_id = stable_rule_id(&rule.pattern);
         content.push_str("---\n");
         content.push_str(&format!("title: TITAN Vigil - {}\n", rule.name));
         content.push_str(&format!("id: {}\n", rule_id));
@@ -34,11 +37,36 @@ pub fn generate_sigma_rules(cfg: &Config, log_dir: &Path) -> Result<Option<PathB
             "    data_name: '{}'\n",
             escape_single_quotes(&rule.name)
         ));
+        let (field, value) =
+            sigma_target_selection(rule.match_kind, &rule.pattern, rule.any_volume);
         content.push_str(&format!(
-            "    target|contains: '{}'\n",
-            escape_single_quotes(&rule.substring)
+            "    {field}: '{}'\n",
+            escape_single_quotes(&value)
         ));
-        content.push_str("  condition: selection\n");
+        if !rule.operations.is_empty() {
+            content.push_str("    operation:\n");
+            for op in &rule.operations {
+                content.push_str(&format!("      - '{}'\n", op.as_str()));
+            }
+        }
+        let excludes: Vec<_> = cfg.watch.exclude.iter().chain(&rule.exclude).collect();
+        if excludes.is_empty() {
+            content.push_str("  condition: selection\n");
+        } else {
+            content.push_str("  filter:\n");
+            for exclude in excludes {
+                let (field, value) = sigma_target_selection(
+                    exclude.match_kind,
+                    &exclude.pattern,
+                    exclude.any_volume,
+                );
+                content.push_str(&format!(
+                    "    - {field}: '{}'\n",
+                    escape_single_quotes(&value)
+                ));
+            }
+            content.push_str("  condition: selection and not filter\n");
+        }
         content.push_str("level: high\n");
         content.push_str("tags:\n");
         content.push_str("  - attack.collection\n");
@@ -50,6 +78,28 @@ pub fn generate_sigma_rules(cfg: &Config, log_dir: &Path) -> Result<Option<PathB
     Ok(Some(output_path))
 }
 
+fn sigma_target_selection(
+    kind: MatchKind,
+    pattern: &str,
+    any_volume: bool,
+) -> (&'static str, String) {
+    if any_volume && matches!(kind, MatchKind::Prefix | MatchKind::Exact) {
+        let end = if kind == MatchKind::Exact { "$" } else { "" };
+        return (
+            "target|re",
+            format!("(?i)^{VOLUME}{}{end}", regex::escape(pattern)),
+        );
+    }
+    match kind {
+        MatchKind::Substring => ("target|contains", pattern.to_string()),
+        MatchKind::Exact => ("target", pattern.to_string()),
+        MatchKind::Prefix => ("target|startswith", pattern.to_string()),
+        MatchKind::Suffix => ("target|endswith", pattern.to_string()),
+        MatchKind::Glob => ("target|re", format!("(?i){}", glob_to_regex(pattern))),
+        MatchKind::Regex => ("target|re", format!("(?i){pattern}")),
+    }
+}
+
 fn stable_rule_id(rule: &str) -> String {
     let mut hash: u64 = 0xcbf29ce484222325;
     for b in rule.as_bytes() {
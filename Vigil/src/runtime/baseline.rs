@@ -0,0 +1,461 @@
+use crate::support::config::ProtectedRule;
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const BASELINE_VERSION: u32 = 1;
+const BASELINE_MAX: usize = 100_000;
+
+/// One distinct accessor of a protected rule, as seen in learn mode.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Accessor {
+    pub rule: String,
+    /// Lowercased full image path.
+    pub image: String,
+    /// Lowercased subject of a verified signature.
+    pub signer: Option<String>,
+    pub sha256: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BaselineEntry {
+    #[serde(flatten)]
+    pub accessor: Accessor,
+    pub hits: u64,
+    pub last_seen: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BaselineFile {
+    version: u32,
+    entries: Vec<BaselineEntry>,
+}
+
+#[derive(Debug, Default)]
+struct BaselineState {
+    entries: HashMap<Accessor, (u64, u64)>,
+    dirty: bool,
+}
+
+/// Hit counts per accessor tuple, accumulated across restarts.
+#[derive(Debug, Default)]
+pub struct Baseline {
+    path: Option<PathBuf>,
+    state: Mutex<BaselineState>,
+}
+
+impl Baseline {
+    /// Baseline persisted at `path`, continuing from its current contents. Unlike the
+    /// trust cache an unreadable file is an error, so learned data is never discarded.
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let entries = match read_baseline(&path) {
+            Ok(entries) => entries,
+            Err(e)
+                if e.downcast_ref::<std::io::Error>()
+                    .is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound) =>
+            {
+                Vec::new()
+            }
+            Err(e) => return Err(e),
+        };
+        let entries = entries
+            .into_iter()
+            .map(|e| (e.accessor, (e.hits, e.last_seen)))
+            .collect();
+        Ok(Self {
+            path: Some(path),
+            state: Mutex::new(BaselineState {
+                entries,
+                dirty: false,
+            }),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn record(&self, mut accessor: Accessor) {
+        accessor.image = accessor.image.to_lowercase();
+        let now = unix_now();
+        let mut state = self.state.lock();
+        if state.entries.len() >= BASELINE_MAX && !state.entries.contains_key(&accessor) {
+            return;
+        }
+        let (hits, last_seen) = state.entries.entry(accessor).or_default();
+        *hits += 1;
+        *last_seen = now;
+        state.dirty = true;
+    }
+
+    /// Entries ordered by rule, then image.
+    pub fn entries(&self) -> Vec<BaselineEntry> {
+        let mut entries: Vec<_> = self
+            .state
+            .lock()
+            .entries
+            .iter()
+            .map(|(accessor, &(hits, last_seen))| BaselineEntry {
+                accessor: accessor.clone(),
+                hits,
+                last_seen,
+            })
+            .collect();
+        entries.sort_by(|a, b| a.accessor.cmp(&b.accessor));
+        entries
+    }
+
+    /// Write the baseline if it changed since the last save. A failed write leaves it
+    /// marked changed, so the next save retries.
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        {
+            // Cleared before the snapshot, so hits recorded while writing mark it again.
+            let mut state = self.state.lock();
+            if !state.dirty {
+                return Ok(());
+            }
+            state.dirty = false;
+        }
+        let written = self.write(path);
+        if written.is_err() {
+            self.state.lock().dirty = true;
+        }
+        written
+    }
+
+    fn write(&self, path: &Path) -> Result<()> {
+        let file = BaselineFile {
+            version: BASELINE_VERSION,
+            entries: self.entries(),
+        };
+
+        let text = serde_json::to_string_pretty(&file).context("failed to serialize baseline")?;
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, text)
+            .with_context(|| format!("failed to write baseline {}", tmp.display()))?;
+        fs::rename(&tmp, path)
+            .with_context(|| format!("failed to replace baseline {}", path.display()))?;
+        Ok(())
+    }
+}
+
+pub fn read_baseline(path: &Path) -> Result<Vec<BaselineEntry>> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("failed to read baseline {}", path.display()))?;
+    let file: BaselineFile = serde_json::from_str(&text)
+        .with_context(|| format!("failed to parse baseline {}", path.display()))?;
+    if file.version != BASELINE_VERSION {
+        anyhow::bail!(
+            "baseline {} has version {}, expected {}",
+            path.display(),
+            file.version,
+            BASELINE_VERSION
+        );
+    }
+    Ok(file.entries)
+}
+
+/// Proposed accessor config for each rule of `rules`, from the accessors seen at least
+/// `min_hits` times. A rule whose accessors share one signer is pinned by image name and
+/// that signer; with several signers or any unsigned accessor, an image name list would
+/// let each signer vouch for the others' images, so it is pinned by image name and hash.
+pub fn suggest(
+    entries: &[BaselineEntry],
+    min_hits: u64,
+    rules_written: &[ProtectedRule],
+) -> String {
+    let kept: Vec<&BaselineEntry> = entries.iter().filter(|e| e.hits >= min_hits).collect();
+    let mut rules: BTreeMap<&str, Vec<&BaselineEntry>> = BTreeMap::new();
+    for entry in &kept {
+        rules.entry(&entry.accessor.rule).or_default().push(entry);
+    }
+
+    let total: u64 = kept.iter().map(|e| e.hits).sum();
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "# Suggested from {} accessors ({total} accesses) across {} rules.",
+        kept.len(),
+        rules.len()
+    );
+    if kept.len() < entries.len() {
+        let _ = writeln!(
+            out,
+            "# {} accessors seen fewer than {min_hits} times are omitted.",
+            entries.len() - kept.len()
+        );
+    }
+    out.push_str("# Review before enforcing; trailing comments are baseline hit counts.\n");
+
+    for (rule, accessors) in rules {
+        let hits: u64 = accessors.iter().map(|e| e.hits).sum();
+        let Some(written) = rules_written.iter().find(|r| r.name == rule) else {
+            let _ = writeln!(
+                out,
+                "\n# {rule}: {} accessors, {hits} hits, but no such rule in the config; skipped.",
+                accessors.len()
+            );
+            continue;
+        };
+        let _ = writeln!(
+            out,
+            "\n# {rule}: {} accessors, {hits} hits. Replaces the rule's authorized_* keys.",
+            accessors.len()
+        );
+        out.push_str("[[watch.protected]]\n");
+        let _ = writeln!(out, "name = {}", toml_str(rule));
+        let _ = writeln!(out, "pattern = {}", toml_str(&written.pattern));
+        if let Ok(kind) = toml::Value::try_from(written.match_kind) {
+            let _ = writeln!(out, "match = {kind}");
+        }
+
+        let images = tally(accessors.iter().copied(), |a| {
+            Some(image_file_name(&a.image).to_string())
+        });
+        write_list(&mut out, "authorized_images", &images);
+
+        let signers = tally(accessors.iter().copied(), |a| a.signer.clone());
+        let all_signed = accessors.iter().all(|e| e.accessor.signer.is_some());
+        if all_signed && signers.len() == 1 {
+            write_list(&mut out, "authorized_signers", &signers);
+            continue;
+        }
+        out.push_str("# Pinned by hash: accessors per signer were\n");
+        for (signer, _) in &signers {
+            let images = tally(accessors.iter().copied(), |a| {
+                (a.signer.as_ref() == Some(signer)).then(|| image_file_name(&a.image).to_string())
+            });
+            let _ = writeln!(out, "#   {signer}: {}", names(&images));
+        }
+        if !all_signed {
+            let images = tally(accessors.iter().copied(), |a| {
+                a.signer
+                    .is_none()
+                    .then(|| image_file_name(&a.image).to_string())
+            });
+            let _ = writeln!(out, "#   unsigned: {}", names(&images));
+        }
+        for entry in accessors.iter().filter(|e| e.accessor.sha256.is_none()) {
+            let _ = writeln!(out, "# no hash recorded for {}", entry.accessor.image);
+        }
+        let hashes = tally(accessors.iter().copied(), |a| a.sha256.clone());
+        write_list(&mut out, "authorized_hashes", &hashes);
+    }
+    out
+}
+
+fn names(counts: &[(String, u64)]) -> String {
+    counts
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Hits per key, most frequent first.
+fn tally<'a>(
+    entries: impl Iterator<Item = &'a BaselineEntry>,
+    key: impl Fn(&Accessor) -> Option<String>,
+) -> Vec<(String, u64)> {
+    let mut counts: BTreeMap<String, u64> = BTreeMap::new();
+    for entry in entries {
+        if let Some(k) = key(&entry.accessor) {
+            *counts.entry(k).or_default() += entry.hits;
+        }
+    }
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts
+}
+
+fn write_list(out: &mut String, key: &str, values: &[(String, u64)]) {
+    let _ = writeln!(out, "{key} = [");
+    for (value, hits) in values {
+        let _ = writeln!(out, "    {}, # {hits} hits", toml_str(value));
+    }
+    out.push_str("]\n");
+}
+
+fn toml_str(value: &str) -> String {
+    toml::Value::String(value.to_string()).to_string()
+}
+
+fn image_file_name(image: &str) -> &str {
+    image.rsplit(['\\', '/']).next().unwrap_or(image)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::support::config::MatchKind;
+
+    fn accessor(rule: &str, image: &str, signer: Option<&str>, sha256: &str) -> Accessor {
+        Accessor {
+            rule: rule.to_string(),
+            image: image.to_string(),
+            signer: signer.map(str::to_string),
+            sha256: Some(sha256.repeat(64)),
+        }
+    }
+
+    #[test]
+    fn baseline_counts_hits_and_survives_a_reload() {
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let path = std::env::temp_dir().join(format!("titan-vigil-baseline-{ts}.json"));
+
+        let baseline = Baseline::load(path.clone()).expect("missing file starts empty");
+        let chrome = accessor(
+            "Chrome Passwords",
+            r"C:\Chrome\chrome.exe",
+            Some("google llc"),
+            "a",
+        );
+        baseline.record(chrome.clone());
+        baseline.record(chrome);
+        baseline.save().expect("save");
+
+        let reloaded = Baseline::load(path.clone()).expect("load");
+        let _ = fs::remove_file(&path);
+        let entries = reloaded.entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].hits, 2);
+        assert_eq!(entries[0].accessor.image, r"c:\chrome\chrome.exe");
+    }
+
+    #[test]
+    fn failed_save_is_retried() {
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let dir = std::env::temp_dir().join(format!("titan-vigil-baseline-dir-{ts}"));
+        let path = dir.join("baseline.json");
+
+        let baseline = Baseline::load(path.clone()).expect("missing file starts empty");
+        baseline.record(accessor("KeePass", r"C:\Tools\KeePass.exe", None, "b"));
+        assert!(baseline.save().is_err(), "directory does not exist yet");
+
+        fs::create_dir_all(&dir).expect("create dir");
+        baseline.save().expect("retried save");
+        let reloaded = Baseline::load(path).expect("load");
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(reloaded.len(), 1);
+    }
+
+    fn written(name: &str, pattern: &str, match_kind: MatchKind) -> ProtectedRule {
+        ProtectedRule {
+            pattern: pattern.to_string(),
+            name: name.to_string(),
+            match_kind,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn suggestion_pins_signed_rules_by_signer_and_unsigned_by_hash() {
+        let config = [
+            written(
+                "Chrome Passwords",
+                r"\Google\Chrome\User Data\Default\Login Data",
+                MatchKind::Substring,
+            ),
+            written("KeePass", ".kdbx", MatchKind::Suffix),
+            written(
+                "Firefox",
+                r"\Mozilla\Firefox\Profiles\",
+                MatchKind::Substring,
+            ),
+        ];
+        let baseline = Baseline::default();
+        for _ in 0..3 {
+            baseline.record(accessor(
+                "Chrome Passwords",
+                r"C:\Chrome\chrome.exe",
+                Some("google llc"),
+                "a",
+            ));
+        }
+        baseline.record(accessor(
+            "KeePass",
+            r"C:\Tools\KeePass.exe",
+            Some("dominik reichl"),
+            "b",
+        ));
+        baseline.record(accessor("KeePass", r"C:\Tools\sync.exe", None, "c"));
+        baseline.record(accessor("KeePass", r"C:\Temp\once.exe", None, "d"));
+        baseline.record(accessor(
+            "Firefox",
+            r"C:\Mozilla\firefox.exe",
+            Some("mozilla corporation"),
+            "e",
+        ));
+        baseline.record(accessor(
+            "Firefox",
+            r"C:\Sync\sync.exe",
+            Some("sync vendor"),
+            "f",
+        ));
+        baseline.record(accessor("Gone", r"C:\Tools\x.exe", Some("x"), "0"));
+
+        let text = suggest(&baseline.entries(), 1, &config);
+        assert!(text.contains("\"google llc\", # 3 hits"));
+        assert!(text.contains("\"chrome.exe\", # 3 hits"));
+        assert!(text.contains(&format!("\"{}\", # 1 hits", "c".repeat(64))));
+        assert!(text.contains("#   mozilla corporation: firefox.exe"));
+        assert!(text.contains("no such rule in the config; skipped"));
+
+        let doc: toml::Table = toml::from_str(&text).expect("suggestion should be valid TOML");
+        assert!(doc.get("allowlist").is_none());
+        let rules = doc["watch"]["protected"].as_array().expect("rules");
+        assert_eq!(rules.len(), 3);
+        assert_eq!(
+            rules[0]["authorized_signers"][0].as_str(),
+            Some("google llc")
+        );
+        assert_eq!(
+            rules[0]["pattern"].as_str(),
+            Some(r"\Google\Chrome\User Data\Default\Login Data")
+        );
+        assert_eq!(rules[1]["name"].as_str(), Some("Firefox"));
+        assert!(rules[1].get("authorized_signers").is_none());
+        assert_eq!(
+            rules[1]["authorized_hashes"].as_array().map(Vec::len),
+            Some(2)
+        );
+        assert_eq!(rules[2]["name"].as_str(), Some("KeePass"));
+        assert_eq!(rules[2]["match"].as_str(), Some("suffix"));
+        assert!(rules[2].get("authorized_signers").is_none());
+        assert_eq!(
+            rules[2]["authorized_hashes"].as_array().map(Vec::len),
+            Some(3)
+        );
+
+        let text = suggest(&baseline.entries(), 2, &config);
+        assert!(text.contains("6 accessors seen fewer than 2 times are omitted"));
+        assert!(!text.contains("KeePass"));
+    }
+}
@@ -0,0 +1,208 @@
+use crate::{output::alerts::RelatedHit, support::config::CorrelationConfig};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    time::{Duration, Instant},
+};
+
+const SWEEP_PROCS_MAX: usize = 10_000;
+/// Distinct (rule, target) hits kept per process within the window.
+const SWEEP_TARGETS_MAX: usize = 256;
+/// Hits reported with a sweep; the first hit of every rule is always among them.
+const SWEEP_RELATED_MAX: usize = 32;
+
+#[derive(Debug, Default)]
+struct ProcSweep {
+    hits: VecDeque<(Instant, RelatedHit)>,
+    /// Keys of `hits`, for dedup.
+    seen: HashSet<(String, String)>,
+    /// Hits per rule in `hits`.
+    rules: HashMap<String, usize>,
+    quiet_until: Option<Instant>,
+}
+
+impl ProcSweep {
+    fn push(&mut self, now: Instant, hit: RelatedHit) {
+        self.seen
+            .insert((hit.data_name.clone(), hit.target.clone()));
+        *self.rules.entry(hit.data_name.clone()).or_default() += 1;
+        self.hits.push_back((now, hit));
+    }
+
+    fn pop_front(&mut self) {
+        let Some((_, hit)) = self.hits.pop_front() else {
+            return;
+        };
+        self.seen.remove(&(hit.data_name.clone(), hit.target));
+        if let Some(count) = self.rules.get_mut(&hit.data_name) {
+            *count -= 1;
+            if *count == 0 {
+                self.rules.remove(&hit.data_name);
+            }
+        }
+    }
+
+    /// Hits to report: the first of each rule, then the rest in order up to the cap.
+    fn related(&self) -> Vec<RelatedHit> {
+        let mut firsts = HashSet::new();
+        let mut extra = SWEEP_RELATED_MAX.saturating_sub(self.rules.len());
+        let mut out = Vec::new();
+        for (_, hit) in &self.hits {
+            if firsts.insert(hit.data_name.as_str()) {
+                out.push(hit.clone());
+            } else if extra > 0 {
+                extra -= 1;
+                out.push(hit.clone());
+            }
+        }
+        out
+    }
+}
+
+/// Tracks, per process, the distinct protected rules hit within a sliding window.
+///
+/// A process that reaches the threshold yields one sweep; it can sweep again once a
+/// full window has passed. Each process keeps at most `SWEEP_TARGETS_MAX` distinct
+/// hits; past that, only a hit on a rule not yet seen is kept, in place of the oldest.
+#[derive(Debug)]
+pub struct SweepTracker {
+    enabled: bool,
+    threshold: usize,
+    window: Duration,
+    procs: HashMap<u32, ProcSweep>,
+}
+
+impl SweepTracker {
+    pub fn new(cfg: &CorrelationConfig) -> Self {
+        Self {
+            enabled: cfg.enabled,
+            threshold: cfg.sweep_threshold.max(2),
+            window: Duration::from_millis(cfg.sweep_window_ms),
+            procs: HashMap::new(),
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Record an untrusted hit; returns every hit in the window when it completes a sweep.
+    pub fn record(
+        &mut self,
+        pid: u32,
+        data_name: &str,
+        target: &str,
+        now: Instant,
+    ) -> Option<Vec<RelatedHit>> {
+        if !self.enabled {
+            return None;
+        }
+        let window = self.window;
+        if self.procs.len() > SWEEP_PROCS_MAX {
+            self.procs.retain(|_, p| {
+                p.hits
+                    .back()
+                    .is_some_and(|(t, _)| now.duration_since(*t) <= window)
+            });
+        }
+
+        let proc = self.procs.entry(pid).or_default();
+        while proc
+            .hits
+            .front()
+            .is_some_and(|(t, _)| now.duration_since(*t) > window)
+        {
+            proc.pop_front();
+        }
+        let new_rule = !proc.rules.contains_key(data_name);
+        if !proc
+            .seen
+            .contains(&(data_name.to_string(), target.to_string()))
+            && (proc.hits.len() < SWEEP_TARGETS_MAX || new_rule)
+        {
+            if proc.hits.len() >= SWEEP_TARGETS_MAX {
+                proc.pop_front();
+            }
+            proc.push(
+                now,
+                RelatedHit {
+                    data_name: data_name.to_string(),
+                    target: target.to_string(),
+                },
+            );
+        }
+
+        if proc.quiet_until.is_some_and(|t| now < t) || proc.rules.len() < self.threshold {
+            return None;
+        }
+
+        proc.quiet_until = Some(now + window);
+        Some(proc.related())
+    }
+
+    pub fn forget(&mut self, pid: u32) {
+        self.procs.remove(&pid);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker() -> SweepTracker {
+        SweepTracker::new(&CorrelationConfig {
+            enabled: true,
+            sweep_threshold: 3,
+            sweep_window_ms: 10_000,
+        })
+    }
+
+    #[test]
+    fn sweep_fires_once_on_the_distinct_rule_threshold() {
+        let mut t = tracker();
+        let t0 = Instant::now();
+        assert!(t.record(7, "Chrome Passwords", "a", t0).is_none());
+        assert!(t.record(7, "Chrome Passwords", "b", t0).is_none());
+        assert!(t.record(8, "Edge Passwords", "c", t0).is_none());
+        assert!(t.record(7, "Edge Passwords", "c", t0).is_none());
+
+        let hits = t
+            .record(7, "Discord Token Store", "d", t0 + Duration::from_secs(1))
+            .expect("sweep expected");
+        assert_eq!(hits.len(), 4);
+        assert_eq!(hits[3].data_name, "Discord Token Store");
+
+        assert!(
+            t.record(7, "Firefox Profile Store", "e", t0 + Duration::from_secs(2))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn targets_per_process_are_capped_without_hiding_new_rules() {
+        let mut t = tracker();
+        let t0 = Instant::now();
+        for i in 0..SWEEP_TARGETS_MAX * 2 {
+            assert!(t.record(7, "A", &format!("a{i}"), t0).is_none());
+        }
+        assert_eq!(t.procs[&7].hits.len(), SWEEP_TARGETS_MAX);
+        assert!(t.record(7, "B", "b", t0).is_none());
+
+        let hits = t.record(7, "C", "c", t0).expect("sweep expected");
+        assert_eq!(hits.len(), SWEEP_RELATED_MAX);
+        assert_eq!(hits[0].data_name, "A");
+        assert!(hits.iter().any(|h| h.data_name == "B"));
+        assert_eq!(hits.last().map(|h| h.data_name.as_str()), Some("C"));
+    }
+
+    #[test]
+    fn hits_outside_the_window_do_not_count() {
+        let mut t = tracker();
+        let t0 = Instant::now();
+        t.record(7, "A", "a", t0);
+        t.record(7, "B", "b", t0);
+        assert!(
+            t.record(7, "C", "c", t0 + Duration::from_secs(11))
+                .is_none()
+        );
+    }
+}
@@ -3,100 +3,387 @@ use crate::support::config::TrustApiMode;
 #[cfg(feature = "trust_api")]
 use crate::trust::api;
 use crate::{
-    output::alerts::Alert,
-    support::config::{Config, RevocationMode},
-    telemetry::handles,
-    trust::{process, wintrust},
+    output::alerts::{Alert, Ancestor, ResponseOutcome, ResponseRecord},
+    runtime::{
+        baseline::{Accessor, Baseline},
+        correlation::SweepTracker,
+        host::{self, Host},
+        procs::{ProcKey, ProcMeta, ProcTable},
+        ratelimit::AlertLimiter,
+        response::{DryRunResponder, ResponseAction, ResponseTarget},
+        rules::{self, AccessPolicy, RuleSet},
+        shard::Sharded,
+        trust_pool::{AccessEvent, Parked, TrustJob, TrustPool},
+    },
+    support::{
+        config::{
+            Config, ExcludePattern, MatchKind, MatchMode, ProtectedRule, ResponseKind,
+            RevocationMode, SuppressKey,
+        },
+        identity::SensorIdentity,
+        metrics::{Metrics, StateSizes},
+    },
+    telemetry::{
+        event::{FileOperation, TelemetryEvent},
+        journal::JournalWriter,
+    },
+    trust::{
+        cache::{FileIdentity, TrustCache},
+        verdict::{RevocationPolicy, TrustResult},
+    },
 };
-use crossbeam_channel::Sender;
-use parking_lot::Mutex;
+use anyhow::Context;
+use crossbeam_channel::{Sender, bounded};
+use parking_lot::{Mutex, RwLock};
 use std::{
+    cmp::Reverse,
     collections::{HashMap, HashSet},
-    sync::atomic::{AtomicU64, Ordering},
+    path::Path,
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread,
     time::{Duration, Instant},
 };
 
 const WHITELIST_TTL: Duration = Duration::from_secs(10 * 60);
 const WHITELIST_MAX: usize = 100_000;
+const IMAGE_HASH_MAX: usize = 4096;
+const ANCESTRY_MAX: usize = 8;
+const DEDUPE_MAX: usize = 50_000;
+const TRUST_QUEUE: usize = 1024;
+const RESPONSE_QUEUE: usize = 256;
+/// Image of a process the host could not resolve.
+const UNKNOWN_IMAGE: &str = "unknown";
+
+/// Alert kinds raised against an untrusted process. Only these run a rule's `response`.
+const UNTRUSTED_KINDS: [&str; 3] = [
+    "protected_resource_access",
+    "suspicious_whitelisted_handle_access",
+    "denylisted_image",
+];
 
-#[derive(Debug, Clone)]
-pub struct ProcMeta {
-    pub image: String,
-    pub ts: Instant,
-    pub is_trusted_signed: bool,
+#[derive(Debug)]
+pub struct Engine {
+    policy: RwLock<Arc<Policy>>,
+    alert_tx: Sender<Alert>,
+    host: Arc<dyn Host>,
+    journal: Option<Arc<JournalWriter>>,
+    trust_cache: Option<Arc<TrustCache>>,
+    /// Set in learn mode: accessors are recorded here instead of alerting.
+    baseline: Option<Arc<Baseline>>,
+    /// Queue of the responder thread; without one, `response` actions are only audited.
+    responses: OnceLock<Sender<ResponseJob>>,
+    identity: SensorIdentity,
+    metrics: Arc<Metrics>,
+    trust_pool: OnceLock<TrustPool>,
+    // Independent locks so the ETW callback never waits on unrelated state. Lock
+    // order, where two are held: whitelist shard, then procs; dedupe shard, then limiter.
+    // The process table stays one RwLock rather than sharded: file events only read it,
+    // writes come from process start/exit and trust verdicts, and ancestry walks and
+    // parent links cross pids, so sharding by pid would mean taking several locks.
+    procs: RwLock<ProcTable>,
+    file_keys: Sharded<u64, String>,
+    last_alert: Sharded<u64, Suppression>,
+    whitelist: Sharded<u64, WhitelistedFileObject>,
+    image_hashes: Mutex<HashMap<String, ImageHash>>,
+    sweeps: Mutex<SweepTracker>,
+    clock: EventClock,
+    limiter: Mutex<AlertLimiter>,
+    excluded_hits: AtomicU64,
 }
 
+/// Everything derived from the config, swapped as one unit by [`Engine::reload`].
 #[derive(Debug)]
-pub struct Engine {
+struct Policy {
     cfg: Config,
-    alert_tx: Sender<Alert>,
-    protected_exact_rules: HashMap<String, String>,
-    protected_substring_rules: Vec<(String, String)>,
-    state: Mutex<EngineState>,
-    dropped_alerts: AtomicU64,
+    rule_set: RuleSet,
+    /// Indexed like `rule_set`.
+    rules: Vec<Arc<RuleInfo>>,
+    exclude: RuleSet,
+    /// Some hash list or rule needs image hashes.
+    hash_images: bool,
 }
 
-#[derive(Debug, Clone)]
-struct WhitelistedFileObject {
-    owners: HashSet<u32>,
-    last_seen: Instant,
+/// A compiled policy waiting for [`Engine::install_policy`].
+#[derive(Debug)]
+pub struct PreparedPolicy(Policy);
+
+/// A compiled protected rule. Accesses hold on to their rule, so a reload never
+/// changes the rule a parked access is evaluated against.
+#[derive(Debug)]
+pub struct RuleInfo {
+    name: String,
+    pattern: String,
+    access: Option<AccessPolicy>,
+    exclude: Option<RuleSet>,
+    operations: Vec<FileOperation>,
+    always_alert: Vec<FileOperation>,
+    response: ResponseKind,
+    priority: i32,
+    specificity: usize,
+    suppress_ms: Option<u64>,
+}
+
+impl RuleInfo {
+    /// Compile one configured rule.
+    pub fn new(rule: &ProtectedRule) -> anyhow::Result<Self> {
+        Ok(Self {
+            name: rule.name.clone(),
+            pattern: rule.pattern.clone(),
+            access: AccessPolicy::from_rule(rule),
+            exclude: exclude_set(&rule.exclude, &rule.name)?,
+            operations: rule.operations.clone(),
+            always_alert: rule.always_alert.clone(),
+            response: rule.response,
+            priority: rule.priority,
+            specificity: rules::specificity(rule),
+            suppress_ms: rule.suppress_ms,
+        })
+    }
+
+    /// Whether the rule watches `operation`; an empty `operations` list watches all.
+    fn covers(&self, operation: FileOperation) -> bool {
+        self.operations.is_empty() || self.operations.contains(&operation)
+    }
+}
+
+/// Time for suppression, correlation, rate-limit and file-object windows: the wall
+/// clock live, the recorded `ts_ms` of the current event in a replay.
+#[derive(Debug, Default)]
+struct EventClock {
+    /// Wall-clock instant of the first replayed event, and its `ts_ms`.
+    origin: OnceLock<(Instant, u64)>,
+    ts_ms: AtomicU64,
+}
+
+impl EventClock {
+    fn now(&self) -> Instant {
+        match self.origin.get() {
+            Some((at, first)) => {
+                *at + Duration::from_millis(
+                    self.ts_ms.load(Ordering::Relaxed).saturating_sub(*first),
+                )
+            }
+            None => Instant::now(),
+        }
+    }
+
+    fn advance(&self, ts_ms: u64) {
+        self.origin.get_or_init(|| (Instant::now(), ts_ms));
+        self.ts_ms.fetch_max(ts_ms, Ordering::Relaxed);
+    }
 }
 
+/// Suppression window opened by an emitted alert.
 #[derive(Debug)]
-struct EngineState {
-    proc_cache: HashMap<u32, ProcMeta>,
-    filekey_cache: HashMap<u64, String>,
-    last_alert: HashMap<u64, Instant>,
-    whitelisted_file_objects: HashMap<u64, WhitelistedFileObject>,
+struct Suppression {
+    since: Instant,
+    window: Duration,
+    /// Repeats folded into the window, with the unix seconds of the first and last.
+    count: u64,
+    first_seen: u64,
+    last_seen: u64,
+    /// The latest repeat, reported as the window-close summary.
+    last: Option<Alert>,
 }
 
-impl Engine {
-    pub fn new(cfg: Config, alert_tx: Sender<Alert>) -> Self {
-        let mut protected_exact_rules = HashMap::new();
-        for rule in &cfg.watch.exact_paths {
-            protected_exact_rules.insert(rule.substring.clone(), rule.name.clone());
+impl Suppression {
+    fn open(since: Instant, window: Duration) -> Self {
+        Self {
+            since,
+            window,
+            count: 0,
+            first_seen: 0,
+            last_seen: 0,
+            last: None,
         }
+    }
 
-        let mut protected_substring_rules = Vec::new();
-        for rule in &cfg.watch.protected {
-            protected_substring_rules.push((rule.substring.clone(), rule.name.clone()));
+    fn fold(&mut self, alert: Alert) {
+        if self.last.is_none() {
+            self.first_seen = alert.ts_unix;
         }
+        self.count += 1;
+        self.last_seen = alert.ts_unix;
+        self.last = Some(alert);
+    }
 
-        Self {
-            cfg,
-            alert_tx,
-            protected_exact_rules,
-            protected_substring_rules,
-            state: Mutex::new(EngineState {
-                proc_cache: HashMap::new(),
-                filekey_cache: HashMap::new(),
-                last_alert: HashMap::new(),
-                whitelisted_file_objects: HashMap::new(),
-            }),
-            dropped_alerts: AtomicU64::new(0),
+    fn take_summary(&mut self) -> Option<Alert> {
+        let mut alert = self.last.take()?;
+        alert.note = format!("{} repeated {} alerts suppressed", self.count, alert.kind);
+        alert.kind = "suppressed_summary".to_string();
+        Some(alert.with_suppressed(self.count, self.first_seen, self.last_seen))
+    }
+}
+
+/// Outcome of matching a path against the protected rules.
+enum RuleMatch {
+    Miss,
+    /// Some rule matched, but an exclude covers the path for every one of them.
+    Excluded,
+    /// The governing rule and, in `all` mode, every matching rule.
+    Hit(Arc<RuleInfo>, Vec<Arc<RuleInfo>>),
+}
+
+#[derive(Debug)]
+struct ImageHash {
+    identity: FileIdentity,
+    sha256: Option<String>,
+}
+
+/// Outcome of [`Engine::respond`].
+enum Response {
+    /// Nothing to run; the audit record, if any, goes on the alert as is.
+    Recorded(Option<ResponseRecord>),
+    /// Run on the responder thread against this process.
+    Act(ResponseKind, ResponseTarget),
+}
+
+/// A live action and the alert that reports it, for the responder thread.
+struct ResponseJob {
+    action: ResponseKind,
+    target: ResponseTarget,
+    alert: Alert,
+}
+
+/// Trust decision for a process image, as cached on its [`ProcMeta`].
+#[derive(Debug, Clone, Default)]
+struct ImageVerdict {
+    trusted: bool,
+    /// Lowercased subject of a signature that verified on its own.
+    signer: Option<String>,
+    /// Hashed along with the verdict, so access checks never hash on the event path.
+    sha256: Option<String>,
+}
+
+impl ImageVerdict {
+    fn into_meta(self, image: String) -> ProcMeta {
+        ProcMeta {
+            sha256: self.sha256,
+            ..ProcMeta::new(image, self.trusted, self.signer)
         }
     }
+}
+
+#[derive(Debug, Clone)]
+struct WhitelistedFileObject {
+    owners: HashSet<ProcKey>,
+    last_seen: Instant,
+}
+
+impl Engine {
+    pub fn new(cfg: Config, alert_tx: Sender<Alert>) -> anyhow::Result<Self> {
+        Self::with_host(cfg, alert_tx, host::default_host())
+    }
+
+    /// Fails when the rules cannot be compiled, which `Config::load` already rules out.
+    pub fn with_host(
+        cfg: Config,
+        alert_tx: Sender<Alert>,
+        host: Arc<dyn Host>,
+    ) -> anyhow::Result<Self> {
+        let sweeps = Mutex::new(SweepTracker::new(&cfg.correlation));
+        let limiter = Mutex::new(AlertLimiter::new(&cfg.rate_limit));
+        let shards = cfg.concurrency.state_shards;
+
+        Ok(Self {
+            policy: RwLock::new(Arc::new(Policy::new(cfg)?)),
+            alert_tx,
+            host,
+            journal: None,
+            trust_cache: None,
+            baseline: None,
+            responses: OnceLock::new(),
+            identity: SensorIdentity::default(),
+            metrics: Arc::new(Metrics::default()),
+            trust_pool: OnceLock::new(),
+            procs: RwLock::new(ProcTable::default()),
+            file_keys: Sharded::new(shards),
+            last_alert: Sharded::new(shards),
+            whitelist: Sharded::new(shards),
+            image_hashes: Mutex::new(HashMap::new()),
+            sweeps,
+            clock: EventClock::default(),
+            limiter,
+            excluded_hits: AtomicU64::new(0),
+        })
+    }
+
+    /// Swap in the rules, allowlists and detection settings of `cfg`, which must have
+    /// come from `Config::load`. Process, file-object and dedupe state carry over;
+    /// cached process verdicts are re-checked against the new allowlists.
+    /// `concurrency` and `trust_cache` keep their startup values. On error the active
+    /// policy stays in place.
+    pub fn reload(&self, cfg: Config) -> anyhow::Result<()> {
+        let policy = Self::prepare_policy(cfg)?;
+        self.install_policy(policy);
+        Ok(())
+    }
+
+    /// Compile the policy for `cfg` without applying it, so a reload can prepare every
+    /// part that may fail before any of them goes live.
+    pub fn prepare_policy(cfg: Config) -> anyhow::Result<PreparedPolicy> {
+        Ok(PreparedPolicy(Policy::new(cfg)?))
+    }
+
+    /// Make a policy from [`Engine::prepare_policy`] the active one.
+    pub fn install_policy(&self, policy: PreparedPolicy) {
+        let policy = policy.0;
+        let sweeps = SweepTracker::new(&policy.cfg.correlation);
+        self.limiter.lock().reconfigure(&policy.cfg.rate_limit);
+        *self.policy.write() = Arc::new(policy);
+        *self.sweeps.lock() = sweeps;
+        self.procs.write().invalidate_verdicts();
+    }
+
+    /// Protected rules in the active policy.
+    pub fn rule_count(&self) -> usize {
+        self.policy.read().rules.len()
+    }
+
+    fn policy(&self) -> Arc<Policy> {
+        self.policy.read().clone()
+    }
+
+    /// Journal every event this engine sees (`--record`).
+    pub fn with_journal(mut self, journal: Arc<JournalWriter>) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Reuse signature verdicts across cache expiry and restarts.
+    pub fn with_trust_cache(mut self, cache: Arc<TrustCache>) -> Self {
+        self.trust_cache = Some(cache);
+        self
+    }
+
+    /// Learn mode: record every accessor of a protected path and never alert.
+    pub fn with_baseline(mut self, baseline: Arc<Baseline>) -> Self {
+        self.baseline = Some(baseline);
+        self
+    }
+
+    /// Stamp alerts with this sensor's id and host name.
+    pub fn with_identity(mut self, identity: SensorIdentity) -> Self {
+        self.identity = identity;
+        self
+    }
 
     pub fn preflight_trusted_handles(&self) -> anyhow::Result<()> {
-        let pids = process::enum_process_ids()?;
+        let pids = self.host.enum_process_ids()?;
         let mut trusted_pids = Vec::new();
 
         for pid in pids {
-            let img = match process::get_process_image_path(pid) {
+            let img = match self.host.process_image_path(pid) {
                 Some(p) => p,
                 None => continue,
             };
 
-            let trust = self.trust_for_path(&img);
-            if trust.is_trusted {
-                self.state.lock().proc_cache.insert(
-                    pid,
-                    ProcMeta {
-                        image: img.clone(),
-                        ts: Instant::now(),
-                        is_trusted_signed: true,
-                    },
-                );
+            let verdict = self.trust_for_path(&img);
+            if verdict.trusted {
+                self.cache_process(pid, verdict.into_meta(img));
                 trusted_pids.push(pid);
             }
         }
@@ -105,60 +392,207 @@ impl Engine {
             return Ok(());
         }
 
-        let entries = handles::collect_file_objects_for_pids(&trusted_pids)?;
+        let entries = self.host.file_objects_for_pids(&trusted_pids)?;
         if entries.is_empty() {
             return Ok(());
         }
 
-        let now = Instant::now();
-        let mut state = self.state.lock();
-        let wl = &mut state.whitelisted_file_objects;
+        let now = self.clock.now();
         for (file_object, pids_set) in entries {
-            let entry = wl
+            let owners: Vec<ProcKey> = {
+                let procs = self.procs.read();
+                pids_set
+                    .iter()
+                    .filter_map(|pid| procs.live_key(*pid))
+                    .collect()
+            };
+            let mut shard = self.whitelist.shard(&file_object);
+            let entry = shard
                 .entry(file_object)
                 .or_insert_with(|| WhitelistedFileObject {
                     owners: HashSet::new(),
                     last_seen: now,
                 });
-            entry.owners.extend(pids_set);
+            entry.owners.extend(owners);
             entry.last_seen = now;
         }
 
         Ok(())
     }
 
+    /// Run time windows on recorded event time from now on; a replay calls this with
+    /// each event's `ts_ms` before handing it over.
+    pub fn advance_clock(&self, ts_ms: u64) {
+        self.clock.advance(ts_ms);
+    }
+
+    /// Entry point for every normalized event, whatever its source.
+    pub fn handle_event(&self, event: TelemetryEvent) {
+        if let Some(journal) = &self.journal {
+            journal.record_event(&event);
+        }
+
+        match event {
+            TelemetryEvent::ProcessStart {
+                pid,
+                image,
+                cmdline,
+                parent_pid,
+                start_time,
+                user,
+            } => {
+                let low = image.to_lowercase();
+                if !low.ends_with(".exe") {
+                    return;
+                }
+                // With a trust pool the first protected access verifies the image.
+                let base = if self.trust_pool.get().is_some() {
+                    ProcMeta::unverified(image)
+                } else {
+                    self.trust_for_image(&image).into_meta(image)
+                };
+                self.on_process_start(
+                    pid,
+                    ProcMeta {
+                        parent_pid,
+                        cmdline,
+                        start_time,
+                        user,
+                        ..base
+                    },
+                );
+            }
+            TelemetryEvent::ProcessStop { pid, start_time } => {
+                self.on_process_stop(pid, start_time)
+            }
+            TelemetryEvent::FileNameMapping {
+                file_key,
+                file_name,
+            } => self.on_file_name_mapping(file_key, file_name),
+            TelemetryEvent::FileClose { file_key } => self.clear_file_key(file_key),
+            TelemetryEvent::FileAccess {
+                pid,
+                event_id,
+                operation,
+                file_name,
+                file_key,
+                file_object,
+            } => {
+                let target = file_name.or_else(|| {
+                    if file_key != 0 {
+                        self.resolve_file_key(file_key)
+                    } else {
+                        None
+                    }
+                });
+                let Some(target) = target else {
+                    return;
+                };
+                self.handle_file_access(pid, event_id, operation, target, file_object);
+            }
+        }
+    }
+
     #[inline]
-    pub fn on_process_start(&self, pid: u32, image: String, _cmdline: Option<String>) {
-        let low = image.to_lowercase();
-        if !low.ends_with(".exe") {
-            return;
+    pub fn on_process_start(&self, pid: u32, meta: ProcMeta) {
+        self.procs.write().insert(pid, meta);
+    }
+
+    /// Refresh the cached image and trust for `pid`, keeping the lineage recorded at
+    /// process start. A different image means the pid was recycled behind our back,
+    /// so it becomes a new incarnation; images are compared past the volume, since ETW
+    /// reports device paths and the process APIs drive paths. An image the host could
+    /// not resolve never replaces a live entry.
+    fn cache_process(&self, pid: u32, meta: ProcMeta) {
+        let mut procs = self.procs.write();
+        match procs.live_proc_mut(pid) {
+            Some(_) if meta.image == UNKNOWN_IMAGE => {}
+            Some(cached) if rules::same_image(&cached.image, &meta.image) => {
+                cached.ts = meta.ts;
+                if meta.verified_at.is_some() {
+                    cached.verified_at = meta.verified_at;
+                    cached.is_trusted_signed = meta.is_trusted_signed;
+                    cached.signer = meta.signer;
+                }
+            }
+            _ => {
+                procs.insert(pid, meta);
+            }
         }
+    }
 
-        let (is_trusted, _) = self.trust_for_image(&image);
+    /// Cached ancestors of `pid`, nearest parent first, following recorded parent
+    /// incarnations (exited ones included). The walk stops at the first parent we never
+    /// saw start, after resolving its image from the host if the process now holding its
+    /// pid started no later than the child; a later start means the pid was recycled.
+    pub fn ancestry(&self, pid: u32) -> Vec<Ancestor> {
+        let mut chain = Vec::new();
+        let mut unresolved = None;
+        {
+            let procs = self.procs.read();
+            let mut meta = procs.live_proc(pid);
+            while let Some(child) = meta {
+                if chain.len() >= ANCESTRY_MAX {
+                    break;
+                }
+                let Some(parent) = child.parent else {
+                    unresolved = child
+                        .parent_pid
+                        .filter(|ppid| *ppid != 0 && procs.live_key(*ppid).is_none())
+                        .zip(child.start_time);
+                    break;
+                };
+                if chain.iter().any(|a: &Ancestor| a.pid == parent.pid) {
+                    break;
+                }
+                meta = procs
+                    .get(&parent)
+                    .filter(|found| !started_after(found.start_time, child.start_time));
+                if let Some(found) = meta {
+                    chain.push(Ancestor {
+                        pid: parent.pid,
+                        image: found.image.clone(),
+                    });
+                }
+            }
+        }
+        if let Some((ppid, child_start)) = unresolved
+            && self
+                .host
+                .process_start_time(ppid)
+                .is_some_and(|start| start <= child_start)
+            && let Some(image) = self.host.process_image_path(ppid)
+        {
+            chain.push(Ancestor { pid: ppid, image });
+        }
+        chain
+    }
 
-        self.state.lock().proc_cache.insert(
-            pid,
-            ProcMeta {
-                image,
-                ts: Instant::now(),
-                is_trusted_signed: is_trusted,
-            },
-        );
+    #[inline]
+    pub fn on_process_stop(&self, pid: u32, start_time: Option<u64>) {
+        self.procs.write().exit(pid, start_time);
+        self.sweeps.lock().forget(pid);
     }
 
     #[inline]
     pub fn on_file_name_mapping(&self, file_key: u64, file_name: String) {
-        self.state.lock().filekey_cache.insert(file_key, file_name);
+        // Only protected paths can ever alert, so the rest never reach the cache. A
+        // recycled key must not keep resolving to the name it had before.
+        if !self.is_protected_candidate(&file_name) {
+            self.clear_file_key(file_key);
+            return;
+        }
+        self.file_keys.shard(&file_key).insert(file_key, file_name);
     }
 
     #[inline]
     pub fn clear_file_key(&self, file_key: u64) {
-        self.state.lock().filekey_cache.remove(&file_key);
+        self.file_keys.shard(&file_key).remove(&file_key);
     }
 
     #[inline]
     pub fn resolve_file_key(&self, file_key: u64) -> Option<String> {
-        self.state.lock().filekey_cache.get(&file_key).cloned()
+        self.file_keys.shard(&file_key).get(&file_key).cloned()
     }
 
     #[inline]
@@ -168,46 +602,125 @@ impl Engine {
         }
 
         let ttl = Duration::from_secs(10);
-        if let Some(meta) = self.state.lock().proc_cache.get(&pid).cloned()
+        if let Some(meta) = self.procs.read().live_proc(pid)
             && meta.ts.elapsed() <= ttl
         {
-            return meta.image;
+            return meta.image.clone();
         }
 
-        let img = process::get_process_image_path(pid).unwrap_or_else(|| "unknown".to_string());
-        let (is_trusted, _) = self.trust_for_image(&img);
-
-        self.state.lock().proc_cache.insert(
-            pid,
-            ProcMeta {
-                image: img.clone(),
-                ts: Instant::now(),
-                is_trusted_signed: is_trusted,
-            },
-        );
+        let img = match self.host.process_image_path(pid) {
+            Some(img) => img,
+            None => {
+                // Exited since we cached it: keep reporting what we knew.
+                if let Some(meta) = self.procs.read().live_proc(pid) {
+                    return meta.image.clone();
+                }
+                UNKNOWN_IMAGE.to_string()
+            }
+        };
+        let meta = if self.trust_pool.get().is_some() {
+            ProcMeta::unverified(img.clone())
+        } else {
+            self.trust_for_image(&img).into_meta(img.clone())
+        };
+        self.cache_process(pid, meta);
 
         img
     }
 
     #[inline]
     pub fn match_protected_rule(&self, path: &str) -> Option<(String, String)> {
-        let p = path.to_lowercase();
-        if let Some(name) = self.protected_exact_rules.get(&p) {
-            return Some((name.clone(), p));
+        self.match_rule(path)
+            .map(|rule| (rule.name.clone(), rule.pattern.clone()))
+    }
+
+    fn match_rule(&self, path: &str) -> Option<Arc<RuleInfo>> {
+        match self.match_rules(&self.policy(), path, None) {
+            RuleMatch::Hit(rule, _) => Some(rule),
+            RuleMatch::Miss | RuleMatch::Excluded => None,
+        }
+    }
+
+    /// The rule governing an access to `path` under `watch.match_mode`, and in `all`
+    /// mode every matching rule, governing rule first. Rules whose own `exclude`
+    /// covers `path`, or that do not watch `operation`, are passed over.
+    fn match_rules(
+        &self,
+        policy: &Policy,
+        path: &str,
+        operation: Option<FileOperation>,
+    ) -> RuleMatch {
+        let applies = |rule: &RuleInfo| operation.is_none_or(|op| rule.covers(op));
+        let mode = policy.cfg.watch.match_mode;
+        let hits = match mode {
+            // Only a leading rule that may be passed over needs the others behind it.
+            MatchMode::First => match policy.rule_set.find(path) {
+                Some(idx)
+                    if policy.rules[idx].exclude.is_some() || !applies(&policy.rules[idx]) =>
+                {
+                    policy.rule_set.find_all(path)
+                }
+                hit => hit.into_iter().collect(),
+            },
+            MatchMode::MostSpecific | MatchMode::All => policy.rule_set.find_all(path),
+        };
+        if hits.is_empty() {
+            return RuleMatch::Miss;
+        }
+
+        let matched: Vec<&Arc<RuleInfo>> = if policy.exclude.is_match(path) {
+            Vec::new()
+        } else {
+            hits.iter()
+                .map(|&idx| &policy.rules[idx])
+                .filter(|rule| !rule.exclude.as_ref().is_some_and(|ex| ex.is_match(path)))
+                .collect()
+        };
+        if matched.is_empty() {
+            return RuleMatch::Excluded;
         }
+        let matched: Vec<&Arc<RuleInfo>> =
+            matched.into_iter().filter(|rule| applies(rule)).collect();
+        let Some(&first) = matched.first() else {
+            return RuleMatch::Miss;
+        };
 
-        for (needle, name) in &self.protected_substring_rules {
-            if p.contains(needle) {
-                return Some((name.clone(), needle.clone()));
+        match mode {
+            MatchMode::First => RuleMatch::Hit(first.clone(), Vec::new()),
+            MatchMode::MostSpecific => {
+                // Rules are ordered by priority, so only the leading run competes;
+                // ties keep the earlier rule.
+                let rule = matched
+                    .iter()
+                    .take_while(|rule| rule.priority == first.priority)
+                    .fold(first, |best, &rule| {
+                        if rule.specificity > best.specificity {
+                            rule
+                        } else {
+                            best
+                        }
+                    });
+                RuleMatch::Hit(rule.clone(), Vec::new())
+            }
+            MatchMode::All => {
+                let all = matched.into_iter().cloned().collect();
+                RuleMatch::Hit(first.clone(), all)
             }
         }
-        None
+    }
+
+    /// Cheap pre-filter under a shared policy read: can `path` match any protected rule
+    /// at all?
+    #[inline]
+    pub fn is_protected_candidate(&self, path: &str) -> bool {
+        self.policy.read().rule_set.is_match(path)
     }
 
     #[inline]
     pub fn is_legacy_allowlisted_process_name(&self, proc_path: &str) -> bool {
         let p = proc_path.to_lowercase();
-        self.cfg
+        self.policy()
+            .cfg
             .allowlist
             .process_name_allow
             .iter()
@@ -216,28 +729,37 @@ impl Engine {
 
     #[inline]
     pub fn is_pid_trusted(&self, pid: u32, proc_path: &str) -> bool {
-        if pid == 0 || pid == 4 {
-            return true;
-        }
+        self.process_trust(pid, proc_path).trusted
+    }
 
-        if let Some(meta) = self.state.lock().proc_cache.get(&pid)
-            && meta.ts.elapsed() <= Duration::from_secs(60)
-        {
-            return meta.is_trusted_signed;
+    /// Global trust decision and verified signer for `pid`, cached for a minute.
+    fn process_trust(&self, pid: u32, proc_path: &str) -> ImageVerdict {
+        if let Some(verdict) = self.cached_trust(pid) {
+            return verdict;
         }
 
-        let (is_trusted, _) = self.trust_for_image(proc_path);
-
-        self.state.lock().proc_cache.insert(
-            pid,
-            ProcMeta {
-                image: proc_path.to_string(),
-                ts: Instant::now(),
-                is_trusted_signed: is_trusted,
-            },
-        );
+        let verdict = self.trust_for_image(proc_path);
+        self.cache_process(pid, verdict.clone().into_meta(proc_path.to_string()));
+        verdict
+    }
 
-        is_trusted
+    /// A verdict for `pid` that is still fresh, without verifying anything.
+    fn cached_trust(&self, pid: u32) -> Option<ImageVerdict> {
+        if pid == 0 || pid == 4 {
+            return Some(ImageVerdict {
+                trusted: true,
+                ..Default::default()
+            });
+        }
+        let procs = self.procs.read();
+        let meta = procs.live_proc(pid)?;
+        meta.verified_at
+            .is_some_and(|at| at.elapsed() <= Duration::from_secs(60))
+            .then(|| ImageVerdict {
+                trusted: meta.is_trusted_signed,
+                signer: meta.signer.clone(),
+                sha256: meta.sha256.clone(),
+            })
     }
 
     #[inline]
@@ -245,11 +767,13 @@ impl Engine {
         if file_object == 0 || pid == 0 || pid == 4 {
             return;
         }
-        let now = Instant::now();
-        let mut state = self.state.lock();
-        let wl = &mut state.whitelisted_file_objects;
+        let Some(owner) = self.procs.read().live_key(pid) else {
+            return;
+        };
+        let now = self.clock.now();
+        let mut wl = self.whitelist.shard(&file_object);
 
-        if wl.len() > WHITELIST_MAX {
+        if wl.len() > WHITELIST_MAX / self.whitelist.shard_count() {
             wl.retain(|_, v| now.duration_since(v.last_seen) <= WHITELIST_TTL);
         }
 
@@ -259,164 +783,782 @@ impl Engine {
                 owners: HashSet::new(),
                 last_seen: now,
             });
-        entry.owners.insert(pid);
+        entry.owners.insert(owner);
         entry.last_seen = now;
     }
 
+    /// Live owners of a learned file object. Owners whose process exited, or whose
+    /// pid now belongs to another incarnation, are dropped.
     #[inline]
     pub fn whitelisted_file_object_owner(&self, file_object: u64) -> Option<HashSet<u32>> {
-        let now = Instant::now();
-        let mut state = self.state.lock();
-        let wl = &mut state.whitelisted_file_objects;
-        let entry = wl.get(&file_object)?;
-
-        let expired = now.duration_since(entry.last_seen) > WHITELIST_TTL;
-        let owners = entry.owners.clone();
-        if expired {
+        let now = self.clock.now();
+        let mut wl = self.whitelist.shard(&file_object);
+        let entry = wl.get_mut(&file_object)?;
+
+        {
+            let procs = self.procs.read();
+            entry.owners.retain(|owner| procs.is_live(owner));
+        }
+        if entry.owners.is_empty() || now.duration_since(entry.last_seen) > WHITELIST_TTL {
             wl.remove(&file_object);
             return None;
         }
 
-        Some(owners)
+        Some(entry.owners.iter().map(|owner| owner.pid).collect())
     }
 
-    fn dedupe_key(pid: u32, target: &str) -> u64 {
-        let mut hash: u64 = 0xcbf29ce484222325;
-        for b in pid.to_le_bytes() {
-            hash ^= b as u64;
-            hash = hash.wrapping_mul(0x100000001b3);
+    /// SHA-256 of an image, hashed once per file identity. Only trust verification
+    /// hashes, so with a trust pool this runs on a worker, never on the ETW callback.
+    fn image_sha256(&self, path: &str) -> Option<String> {
+        // Without an identity there is nothing to tell a replaced file apart by.
+        let Some(identity) = FileIdentity::of(Path::new(path)) else {
+            return self.host.image_sha256(path);
+        };
+        let key = path.to_lowercase();
+        if let Some(seen) = self.image_hashes.lock().get(&key)
+            && seen.identity == identity
+        {
+            return seen.sha256.clone();
         }
-        for b in target.as_bytes() {
-            hash ^= *b as u64;
-            hash = hash.wrapping_mul(0x100000001b3);
+        let hash = self.host.image_sha256(path);
+        let mut hashes = self.image_hashes.lock();
+        if hashes.len() >= IMAGE_HASH_MAX {
+            hashes.clear();
         }
+        hashes.insert(
+            key,
+            ImageHash {
+                identity,
+                sha256: hash.clone(),
+            },
+        );
         hash
     }
 
-    #[inline]
-    fn should_suppress(&self, pid: u32, target: &str) -> bool {
-        let key = Self::dedupe_key(pid, target);
-        let now = Instant::now();
-        let suppress = Duration::from_millis(self.cfg.general.suppress_ms);
-
-        let mut state = self.state.lock();
-        let map = &mut state.last_alert;
-        if let Some(prev) = map.get(&key)
-            && now.duration_since(*prev) < suppress
-        {
-            return true;
-        }
-        map.insert(key, now);
+    /// Every key includes the alert kind, so a trusted accessor's repeats never
+    /// swallow an unauthorized one's.
+    fn dedupe_key(key: SuppressKey, access: &AccessEvent, kind: &str) -> u64 {
+        let pid = access.pid.to_le_bytes();
+        let (first, second): (&[u8], &[u8]) = match key {
+            SuppressKey::PidTarget => (&pid, access.target.as_bytes()),
+            SuppressKey::ImageRule => (access.process.as_bytes(), access.rule.name.as_bytes()),
+            SuppressKey::ImageTarget => (access.process.as_bytes(), access.target.as_bytes()),
+            SuppressKey::Rule => (&[], access.rule.name.as_bytes()),
+        };
+        let parts = [kind.as_bytes(), first, second];
 
-        if map.len() > 50_000 {
-            map.retain(|_, t| now.duration_since(*t) < suppress * 8);
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for part in parts {
+            for b in part.iter().chain(&[0xff]) {
+                hash ^= *b as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
         }
-
-        false
+        hash
     }
 
+    /// Emit an alert for `access` unless its kind and `general.suppress_key` alerted
+    /// within the rule's window; alerts carrying a live response always go out.
+    /// Suppressed repeats are counted and reported on the key's next alert, or by
+    /// [`Engine::flush_suppressed`] once the window closes. Every other alert of any
+    /// kind past `[rate_limit]` is held back and reported by [`Engine::flush_storm`];
+    /// only an alert carrying a live response bypasses the limit.
     #[inline]
-    #[allow(clippy::too_many_arguments)]
-    pub fn alert(
+    fn alert(
         &self,
-        pid: u32,
-        process: String,
-        target: String,
-        data_name: String,
-        event_id: u16,
+        policy: &Policy,
+        access: AccessEvent,
+        signer: Option<&str>,
+        trust_unknown: bool,
         kind: &str,
         note: &str,
     ) {
-        if self.should_suppress(pid, &target) {
-            return;
-        }
+        let key = Self::dedupe_key(policy.cfg.general.suppress_key, &access, kind);
+        let window = Duration::from_millis(
+            access
+                .rule
+                .suppress_ms
+                .unwrap_or(policy.cfg.general.suppress_ms),
+        );
 
-        let alert = Alert::new(pid, process, target, data_name, event_id, kind, note);
-        if self.alert_tx.try_send(alert).is_err() {
-            self.dropped_alerts.fetch_add(1, Ordering::Relaxed);
+        // Decide on the response first: a repeat within the window can come from another
+        // process the rule must still act on, and a live action is never folded away.
+        let response = self.respond(policy, &access, signer, trust_unknown, kind);
+        let acting = matches!(response, Response::Act(..));
+
+        let now = self.clock.now();
+        let closed = {
+            let mut map = self.last_alert.shard(&key);
+            if !acting
+                && let Some(open) = map.get_mut(&key)
+                && now.duration_since(open.since) < open.window
+            {
+                open.fold(Self::access_alert(access, trust_unknown, kind, note));
+                return;
+            }
+            if !acting
+                && !self
+                    .limiter
+                    .lock()
+                    .admit(&access.rule.name, &access.process, now)
+            {
+                self.metrics.record_rate_limited_alert();
+                return;
+            }
+            let closed = map.insert(key, Suppression::open(now, window));
+            if map.len() > DEDUPE_MAX / self.last_alert.shard_count() {
+                map.retain(|_, s| s.last.is_some() || now.duration_since(s.since) < s.window * 8);
+            }
+            closed
+        };
+
+        let mut alert = Self::access_alert(access, trust_unknown, kind, note);
+        if let Some(closed) = closed
+            && closed.last.is_some()
+        {
+            alert = alert.with_suppressed(closed.count, closed.first_seen, closed.last_seen);
+        }
+        match response {
+            Response::Recorded(record) => self.send(policy, alert.with_response(record)),
+            Response::Act(action, target) => self.dispatch(policy, action, target, alert),
         }
     }
 
-    pub fn take_dropped_alerts(&self) -> u64 {
-        self.dropped_alerts.swap(0, Ordering::Relaxed)
+    fn access_alert(access: AccessEvent, trust_unknown: bool, kind: &str, note: &str) -> Alert {
+        Alert::new(
+            access.pid,
+            access.process,
+            access.target,
+            access.rule.name.clone(),
+            access.event_id,
+            kind,
+            note,
+        )
+        .with_operation(access.operation)
+        .with_trust_unknown(trust_unknown)
+        .with_matched_rules(access.matched_rules)
     }
 
-    // Execution ring: resolve → rule match → trust → whitelist → alert.
-    pub fn handle_file_access(&self, pid: u32, event_id: u16, target: String, file_object: u64) {
-        let Some((data_name, _)) = self.match_protected_rule(&target) else {
+    /// Emit a `suppressed_summary` alert for every suppression window that closed with
+    /// unreported repeats. `all` closes the open windows too, as at the end of a replay.
+    pub fn flush_suppressed(&self, all: bool) {
+        let now = self.clock.now();
+        let mut summaries = Vec::new();
+        self.last_alert.retain(|_, s| {
+            if !all && now.duration_since(s.since) < s.window {
+                return true;
+            }
+            summaries.extend(s.take_summary());
+            false
+        });
+        let policy = self.policy();
+        for alert in summaries {
+            self.send(&policy, alert);
+        }
+    }
+
+    /// Emit an `alert_storm` summary of the alerts `[rate_limit]` held back, once per
+    /// `rate_limit.storm_summary_ms`. `all` reports a storm in progress straight away.
+    pub fn flush_storm(&self, all: bool) {
+        let Some(storm) = self.limiter.lock().take_storm(self.clock.now(), all) else {
             return;
         };
+        let note = format!(
+            "{} alerts held back by rate limit within {}s",
+            storm.limited,
+            storm.window_ms.div_ceil(1000)
+        );
+        // The top images and rules travel in `storm` only; no single process is behind it.
+        let alert = Alert::new(
+            0,
+            String::new(),
+            String::new(),
+            String::new(),
+            0,
+            "alert_storm",
+            &note,
+        )
+        .with_storm(storm);
+        self.send(&self.policy(), alert);
+    }
+
+    /// Decide on the rule's `response` within the safety rails. Skips and dry runs are
+    /// recorded straight away; a live action goes to [`Engine::dispatch`] with the
+    /// identity of the process it must still be acting on.
+    fn respond(
+        &self,
+        policy: &Policy,
+        access: &AccessEvent,
+        signer: Option<&str>,
+        trust_unknown: bool,
+        kind: &str,
+    ) -> Response {
+        let action = access.rule.response;
+        let cfg = &policy.cfg;
+        if action == ResponseKind::None || !cfg.response.enabled {
+            return Response::Recorded(None);
+        }
 
-        let proc_path = self.resolve_process_image(pid);
+        let pid = access.pid;
+        let target = self.response_target(access);
+        let allowlisted = signer.is_some_and(|s| {
+            cfg.allowlist
+                .signer_subject_allow
+                .iter()
+                .any(|needle| s.contains(needle.as_str()))
+        });
+        let rail = if pid == 0 || pid == 4 {
+            Some("system process")
+        } else if pid == std::process::id() {
+            Some("sensor process")
+        } else if allowlisted {
+            Some("allowlisted signer")
+        } else if !UNTRUSTED_KINDS.contains(&kind) {
+            Some("trusted accessor")
+        } else if trust_unknown {
+            Some("process trust unresolved")
+        } else {
+            None
+        };
 
-        if self.is_pid_trusted(pid, &proc_path) {
-            if file_object != 0 {
-                self.learn_whitelisted_file_object(file_object, pid);
+        let (outcome, detail) = match (rail, target) {
+            (Some(reason), _) => (ResponseOutcome::Skipped, reason.to_string()),
+            (None, None) => (ResponseOutcome::Skipped, "process not tracked".to_string()),
+            (None, Some(target)) if cfg.response.dry_run => {
+                let detail = DryRunResponder.execute(action, &target);
+                (ResponseOutcome::DryRun, detail.unwrap_or_default())
             }
-            return;
-        }
+            (None, Some(target)) => return Response::Act(action, target),
+        };
+        Response::Recorded(Some(ResponseRecord::new(action, outcome, detail)))
+    }
 
-        if file_object != 0
-            && let Some(owners) = self.whitelisted_file_object_owner(file_object)
-            && !owners.is_empty()
-        {
-            self.alert(
-                pid,
-                proc_path,
-                target,
-                data_name,
-                event_id,
-                "suspicious_whitelisted_handle_access",
-                "untrusted process touched protected resource via whitelisted file object",
+    /// The live process behind `access`, pinned by creation time and image.
+    fn response_target(&self, access: &AccessEvent) -> Option<ResponseTarget> {
+        let procs = self.procs.read();
+        let key = procs.live_key(access.pid)?;
+        let start_known = procs
+            .live_proc(access.pid)
+            .is_some_and(|meta| meta.start_time == Some(key.start_time));
+        Some(ResponseTarget {
+            pid: access.pid,
+            image: access.process.clone(),
+            start_time: key.start_time,
+            start_known,
+        })
+    }
+
+    /// Queue a live action for the responder thread, which sends `alert` with the
+    /// audit record once the action has run.
+    fn dispatch(
+        &self,
+        policy: &Policy,
+        action: ResponseKind,
+        target: ResponseTarget,
+        alert: Alert,
+    ) {
+        let alert = self.enrich(policy, alert);
+        let Some(responses) = self.responses.get() else {
+            let record =
+                ResponseRecord::new(action, ResponseOutcome::Skipped, "no responder".to_string());
+            return self.deliver(alert.with_response(Some(record)));
+        };
+        let job = ResponseJob {
+            action,
+            target,
+            alert,
+        };
+        if let Err(e) = responses.try_send(job) {
+            let job = e.into_inner();
+            let record = ResponseRecord::new(
+                action,
+                ResponseOutcome::Failed,
+                "responder queue full".to_string(),
             );
-            return;
+            self.deliver(job.alert.with_response(Some(record)));
         }
+    }
 
-        self.alert(
-            pid,
-            proc_path,
-            target,
-            data_name,
-            event_id,
-            "protected_resource_access",
-            "untrusted process attempted access to protected resource",
-        );
+    /// Run `response` actions on their own thread, so suspending or terminating a
+    /// process never holds up the ETW callback or a trust worker.
+    pub fn start_responder(
+        self: &Arc<Self>,
+        responder: Arc<dyn ResponseAction>,
+    ) -> anyhow::Result<()> {
+        if self.responses.get().is_some() {
+            return Ok(());
+        }
+        let (tx, rx) = bounded::<ResponseJob>(RESPONSE_QUEUE);
+        let engine = Arc::downgrade(self);
+        thread::Builder::new()
+            .name("vigil-responder".to_string())
+            .spawn(move || {
+                while let Ok(job) = rx.recv() {
+                    let (outcome, detail) = match responder.execute(job.action, &job.target) {
+                        Ok(detail) => (ResponseOutcome::Executed, detail),
+                        Err(e) => (ResponseOutcome::Failed, format!("{e:#}")),
+                    };
+                    let Some(engine) = engine.upgrade() else {
+                        break;
+                    };
+                    let record = ResponseRecord::new(job.action, outcome, detail);
+                    engine.deliver(job.alert.with_response(Some(record)));
+                }
+            })?;
+        let _ = self.responses.set(tx);
+        Ok(())
     }
 
-    #[inline]
-    fn trust_for_path(&self, path: &str) -> wintrust::TrustResult {
-        let trust = wintrust::verify_file_signature(path, self.revocation_policy());
+    fn send(&self, policy: &Policy, alert: Alert) {
+        let alert = self.enrich(policy, alert);
+        self.deliver(alert);
+    }
 
-        #[cfg(feature = "trust_api")]
-        {
-            if self.cfg.trust_api.enabled {
-                let api_decision = api::verify(path, &self.cfg.trust_api);
-                match self.cfg.trust_api.mode {
-                    TrustApiMode::ApiOnly => {
-                        if let Ok(decision) = api_decision {
-                            return self.trust_from_api(decision);
-                        }
-                        if let Err(e) = api_decision {
+    /// Add the process lineage, user and sensor identity while the process is tracked.
+    fn enrich(&self, policy: &Policy, alert: Alert) -> Alert {
+        let (cmdline, user) = self
+            .procs
+            .read()
+            .live_proc(alert.pid)
+            .map(|m| (m.cmdline.clone(), m.user.clone()))
+            .unwrap_or_default();
+        let user = user.or_else(|| self.resolve_user(alert.pid));
+        let ancestry = self.ancestry(alert.pid);
+        alert
+            .with_lineage(cmdline, ancestry)
+            .with_user(user)
+            .with_sensor(&self.identity, &policy.cfg.general.tags)
+    }
+
+    fn deliver(&self, alert: Alert) {
+        self.metrics.record_alert(&alert.kind);
+        if self.alert_tx.try_send(alert).is_err() {
+            self.metrics.record_dropped_alert();
+        }
+    }
+
+    /// Look up the account running `pid` and keep it on the live process entry, so only
+    /// alerting processes pay for the lookup.
+    fn resolve_user(&self, pid: u32) -> Option<String> {
+        if pid == 0 || pid == 4 {
+            return None;
+        }
+        let user = self.host.process_user(pid)?;
+        if let Some(meta) = self.procs.write().live_proc_mut(pid) {
+            meta.user = Some(user.clone());
+        }
+        Some(user)
+    }
+
+    /// Feed an unauthorized hit to the sweep tracker and raise `credential_sweep` once a
+    /// process has touched enough distinct rules. Sweeps are never suppressed.
+    fn correlate(&self, policy: &Policy, access: &AccessEvent) {
+        let AccessEvent {
+            pid,
+            event_id,
+            operation,
+            ref target,
+            ref process,
+            ref rule,
+            ..
+        } = *access;
+        let data_name = &rule.name;
+        if pid == 0 || pid == 4 {
+            return;
+        }
+        let (related, window) = {
+            let mut sweeps = self.sweeps.lock();
+            let window = sweeps.window();
+            (
+                sweeps.record(pid, data_name, target, self.clock.now()),
+                window,
+            )
+        };
+        let Some(related) = related else {
+            return;
+        };
+
+        let mut rules: Vec<&str> = Vec::new();
+        for hit in &related {
+            if !rules.contains(&hit.data_name.as_str()) {
+                rules.push(&hit.data_name);
+            }
+        }
+        let note = format!(
+            "process touched {} protected stores within {}s",
+            rules.len(),
+            window.as_secs()
+        );
+        let alert = Alert::new(
+            pid,
+            process.clone(),
+            target.clone(),
+            rules.join(","),
+            event_id,
+            "credential_sweep",
+            &note,
+        )
+        .with_operation(operation)
+        .with_related(related);
+        self.send(policy, alert);
+    }
+
+    /// Counters shared with the ETW session and the alert sinks.
+    pub fn metrics(&self) -> &Arc<Metrics> {
+        &self.metrics
+    }
+
+    /// [`Metrics`] in Prometheus text format, with the current state map sizes.
+    pub fn render_metrics(&self) -> String {
+        let sizes = StateSizes {
+            proc_cache: self.procs.read().len(),
+            filekey_cache: self.file_keys.len(),
+            last_alert: self.last_alert.len(),
+            whitelisted_file_objects: self.whitelist.len(),
+            alert_channel_depth: self.alert_tx.len(),
+            alert_channel_capacity: self.alert_tx.capacity().unwrap_or(0),
+        };
+        self.metrics.render(&sizes)
+    }
+
+    /// Protected-path hits dropped by an `exclude` pattern since the last call.
+    pub fn take_excluded_hits(&self) -> u64 {
+        self.excluded_hits.swap(0, Ordering::Relaxed)
+    }
+
+    // Execution ring: resolve → rule match → trust → whitelist → alert.
+    pub fn handle_file_access(
+        &self,
+        pid: u32,
+        event_id: u16,
+        operation: FileOperation,
+        target: String,
+        file_object: u64,
+    ) {
+        let policy = self.policy();
+        let (rule, all) = match self.match_rules(&policy, &target, Some(operation)) {
+            RuleMatch::Hit(rule, all) => (rule, all),
+            RuleMatch::Miss => return,
+            RuleMatch::Excluded => {
+                self.excluded_hits.fetch_add(1, Ordering::Relaxed);
+                self.metrics.record_excluded_hit();
+                return;
+            }
+        };
+        if all.is_empty() {
+            self.metrics.record_rule_match(&rule.name);
+        }
+        for matched in &all {
+            self.metrics.record_rule_match(&matched.name);
+        }
+        // Every rule applies its own `operations`, so `rule` and `all` only hold rules
+        // that watch this one.
+        let matched_rules = all.iter().map(|r| r.name.clone()).collect();
+
+        let access = AccessEvent {
+            pid,
+            event_id,
+            operation,
+            target,
+            file_object,
+            process: self.resolve_process_image(pid),
+            rule,
+            matched_rules,
+        };
+        let Some(pool) = self.trust_pool.get() else {
+            let verdict = self.process_trust(pid, &access.process);
+            return self.evaluate_access(&policy, access, Some(verdict));
+        };
+        match pool.park(access, || self.cached_trust(pid)) {
+            Parked::Queued => {}
+            Parked::Ready(access, verdict) => self.evaluate_access(&policy, access, Some(verdict)),
+            Parked::Full(access) => self.evaluate_access(&policy, access, None),
+        }
+    }
+
+    /// Whether `verdict` lets the process behind `access` touch the rule's resource.
+    fn is_authorized(access: &AccessEvent, verdict: &ImageVerdict) -> bool {
+        match &access.rule.access {
+            None => verdict.trusted,
+            Some(_) if access.pid == 0 || access.pid == 4 => true,
+            Some(policy) => policy.permits(
+                &access.process,
+                verdict.trusted,
+                verdict.signer.as_deref(),
+                verdict.sha256.as_deref(),
+            ),
+        }
+    }
+
+    /// Decide on one access given the process verdict, or `None` when trust is unknown.
+    /// Only known verdicts feed correlation; see [`Engine::finish_trust`].
+    fn evaluate_access(&self, policy: &Policy, access: AccessEvent, verdict: Option<ImageVerdict>) {
+        let rule = access.rule.clone();
+        let pid = access.pid;
+        let trust_unknown = verdict.is_none();
+        let verdict = verdict.unwrap_or_default();
+        let allowed = Self::is_authorized(&access, &verdict);
+        let ImageVerdict {
+            trusted: is_trusted,
+            signer,
+            sha256,
+        } = verdict;
+
+        if let Some(baseline) = &self.baseline {
+            // Without a verdict the tuple would be wrong; a late verdict records it.
+            if pid != 0 && pid != 4 && !trust_unknown {
+                baseline.record(Accessor {
+                    rule: rule.name.clone(),
+                    sha256,
+                    image: access.process,
+                    signer,
+                });
+            }
+            return;
+        }
+
+        if pid != 0 && pid != 4 && policy.is_denylisted_hash(sha256.as_deref()) {
+            if !trust_unknown {
+                self.correlate(policy, &access);
+            }
+            self.alert(
+                policy,
+                access,
+                None,
+                false,
+                "denylisted_image",
+                "process image hash is on security.denylisted_image_sha256",
+            );
+            return;
+        }
+
+        if allowed {
+            if access.file_object != 0 {
+                self.learn_whitelisted_file_object(access.file_object, pid);
+            }
+            if pid != 0 && pid != 4 && rule.always_alert.contains(&access.operation) {
+                self.alert(
+                    policy,
+                    access,
+                    signer.as_deref(),
+                    trust_unknown,
+                    "sensitive_operation",
+                    "trusted process performed a sensitive operation on protected resource",
+                );
+            }
+            return;
+        }
+
+        if !trust_unknown {
+            self.correlate(policy, &access);
+        }
+
+        if is_trusted {
+            self.alert(
+                policy,
+                access,
+                signer.as_deref(),
+                trust_unknown,
+                "unauthorized_accessor",
+                "trusted process is not an authorized accessor for this protected resource",
+            );
+            return;
+        }
+
+        if access.file_object != 0
+            && let Some(owners) = self.whitelisted_file_object_owner(access.file_object)
+            && !owners.is_empty()
+        {
+            self.alert(
+                policy,
+                access,
+                signer.as_deref(),
+                trust_unknown,
+                "suspicious_whitelisted_handle_access",
+                "untrusted process touched protected resource via whitelisted file object",
+            );
+            return;
+        }
+
+        let note = if trust_unknown {
+            "process trust was not resolved before the deadline; evaluated as untrusted"
+        } else {
+            "untrusted process attempted access to protected resource"
+        };
+        self.alert(
+            policy,
+            access,
+            signer.as_deref(),
+            trust_unknown,
+            "protected_resource_access",
+            note,
+        );
+    }
+
+    /// Move trust verification for protected accesses onto `concurrency.trust_workers`
+    /// threads. Accesses wait per pid for a verdict until `trust_deadline_ms`.
+    pub fn start_trust_pool(self: &Arc<Self>) -> anyhow::Result<()> {
+        let cfg = &self.policy().cfg;
+        let workers = cfg.concurrency.trust_workers;
+        if workers == 0 || self.trust_pool.get().is_some() {
+            return Ok(());
+        }
+        let deadline = Duration::from_millis(cfg.concurrency.trust_deadline_ms);
+        let (tx, rx) = bounded::<TrustJob>(TRUST_QUEUE);
+
+        for idx in 0..workers {
+            let rx = rx.clone();
+            let engine = Arc::downgrade(self);
+            thread::Builder::new()
+                .name(format!("vigil-trust-{idx}"))
+                .spawn(move || {
+                    while let Ok(job) = rx.recv() {
+                        let Some(engine) = engine.upgrade() else {
+                            break;
+                        };
+                        engine.finish_trust(job);
+                    }
+                })?;
+        }
+
+        let engine = Arc::downgrade(self);
+        let tick = (deadline / 4).max(Duration::from_millis(10));
+        thread::Builder::new()
+            .name("vigil-trust-deadline".to_string())
+            .spawn(move || {
+                loop {
+                    thread::sleep(tick);
+                    let Some(engine) = engine.upgrade() else {
+                        break;
+                    };
+                    engine.expire_pending();
+                }
+            })?;
+
+        let _ = self.trust_pool.set(TrustPool::new(tx, deadline));
+        Ok(())
+    }
+
+    /// Evaluate the accesses parked for the job's pid. Those already alerted on at the
+    /// deadline, as trust unknown, join correlation now that the verdict is known; in
+    /// learn mode, which skipped them, they are recorded instead.
+    fn finish_trust(&self, job: TrustJob) {
+        let verdict = self.process_trust(job.pid, &job.process);
+        let Some(pool) = self.trust_pool.get() else {
+            return;
+        };
+        let (parked, late) = pool.take(job.pid);
+        let policy = self.policy();
+        for access in parked {
+            self.evaluate_access(&policy, access, Some(verdict.clone()));
+        }
+        if self.baseline.is_some() {
+            // Learn mode skipped these at the deadline; record them with the verdict.
+            for access in late {
+                self.evaluate_access(&policy, access, Some(verdict.clone()));
+            }
+            return;
+        }
+        let denylisted = policy.is_denylisted_hash(verdict.sha256.as_deref());
+        for access in late {
+            if denylisted || !Self::is_authorized(&access, &verdict) {
+                self.correlate(&policy, &access);
+            }
+        }
+    }
+
+    fn expire_pending(&self) {
+        let Some(pool) = self.trust_pool.get() else {
+            return;
+        };
+        let policy = self.policy();
+        for access in pool.expired(Instant::now()) {
+            self.evaluate_access(&policy, access, None);
+        }
+    }
+
+    /// Trust decision for `path`, with the lowercased subject of its signature when that
+    /// signature verified on its own (before the global signer allowlist) and the image
+    /// hash when anything needs it. A denylisted image hash overrides every other source;
+    /// an allowlisted one trusts unsigned images.
+    fn trust_for_path(&self, path: &str) -> ImageVerdict {
+        let policy = self.policy();
+        let cfg = &policy.cfg;
+        let sha256 = if policy.hash_images || self.baseline.is_some() || self.trust_cache.is_some()
+        {
+            self.image_sha256(path)
+        } else {
+            None
+        };
+        if policy.is_denylisted_hash(sha256.as_deref()) {
+            return ImageVerdict {
+                trusted: false,
+                signer: None,
+                sha256,
+            };
+        }
+
+        let (trust, signer) = self.signature_trust(cfg, path, sha256.as_deref());
+        let trusted = trust.is_trusted
+            || sha256
+                .as_ref()
+                .is_some_and(|h| cfg.allowlist.image_sha256.contains(h));
+        ImageVerdict {
+            trusted,
+            signer,
+            sha256,
+        }
+    }
+
+    #[inline]
+    fn signature_trust(
+        &self,
+        cfg: &Config,
+        path: &str,
+        sha256: Option<&str>,
+    ) -> (TrustResult, Option<String>) {
+        let revocation = revocation_policy(cfg);
+        // The cache is keyed by content, so an image that cannot be hashed is verified.
+        let trust = match (&self.trust_cache, sha256) {
+            (Some(cache), Some(sha256)) => cache.get_or_verify(sha256, revocation, || {
+                self.host.verify_signature(path, revocation)
+            }),
+            _ => self.host.verify_signature(path, revocation),
+        };
+
+        #[cfg(feature = "trust_api")]
+        {
+            if cfg.trust_api.enabled {
+                let api_decision = api::verify(path, &cfg.trust_api);
+                match cfg.trust_api.mode {
+                    TrustApiMode::ApiOnly => {
+                        if let Ok(decision) = api_decision {
+                            return verified(self.trust_from_api(decision));
+                        }
+                        if let Err(e) = api_decision {
                             eprintln!("[TRUST_API] {:?}", e);
-                            return wintrust::TrustResult {
-                                is_signed: false,
-                                is_trusted: false,
-                                signer_subject: None,
-                                signer_thumbprint: None,
-                            };
+                            return (
+                                TrustResult {
+                                    is_signed: false,
+                                    is_trusted: false,
+                                    signer_subject: None,
+                                    signer_thumbprint: None,
+                                },
+                                None,
+                            );
                         }
                     }
                     TrustApiMode::PreferApi => {
                         if let Ok(decision) = api_decision {
-                            return self.trust_from_api(decision);
+                            return verified(self.trust_from_api(decision));
                         }
                     }
                     TrustApiMode::PreferWintrust => {
                         if trust.is_trusted {
-                            return trust;
+                            return verified(trust);
                         }
                         if let Ok(decision) = api_decision {
-                            return self.trust_from_api(decision);
+                            return verified(self.trust_from_api(decision));
                         }
                     }
                     TrustApiMode::WintrustOnly => {}
@@ -428,97 +1570,1519 @@ impl Engine {
             }
         }
 
-        if self.cfg.security.require_signature && !trust.is_signed {
-            return wintrust::TrustResult {
-                is_signed: false,
-                is_trusted: false,
-                signer_subject: None,
-                signer_thumbprint: None,
-            };
+        if cfg.security.require_signature && !trust.is_signed {
+            return (
+                TrustResult {
+                    is_signed: false,
+                    is_trusted: false,
+                    signer_subject: None,
+                    signer_thumbprint: None,
+                },
+                None,
+            );
         }
 
         if let Some(thumbprint) = &trust.signer_thumbprint {
-            let is_denylisted = self
-                .cfg
+            let is_denylisted = cfg
                 .security
                 .denylisted_cert_thumbprints
                 .iter()
                 .any(|blocked| blocked == thumbprint);
             if is_denylisted {
-                return wintrust::TrustResult {
-                    is_signed: trust.is_signed,
-                    is_trusted: false,
-                    signer_subject: trust.signer_subject,
-                    signer_thumbprint: trust.signer_thumbprint,
-                };
+                return (
+                    TrustResult {
+                        is_signed: trust.is_signed,
+                        is_trusted: false,
+                        signer_subject: trust.signer_subject,
+                        signer_thumbprint: trust.signer_thumbprint,
+                    },
+                    None,
+                );
             }
         }
 
-        if !self.cfg.allowlist.signer_subject_allow.is_empty()
-            && self.cfg.security.require_signer_allowlist
-        {
+        let signer = verified_signer(&trust);
+
+        if !cfg.allowlist.signer_subject_allow.is_empty() && cfg.security.require_signer_allowlist {
             let subj = trust
                 .signer_subject
                 .clone()
                 .unwrap_or_default()
                 .to_lowercase();
 
-            let ok = self
-                .cfg
+            let ok = cfg
                 .allowlist
                 .signer_subject_allow
                 .iter()
                 .any(|needle| subj.contains(needle));
 
-            return wintrust::TrustResult {
+            return (
+                TrustResult {
+                    is_signed: trust.is_signed,
+                    is_trusted: ok && trust.is_trusted,
+                    signer_subject: trust.signer_subject,
+                    signer_thumbprint: trust.signer_thumbprint,
+                },
+                signer,
+            );
+        }
+
+        (
+            TrustResult {
                 is_signed: trust.is_signed,
-                is_trusted: ok && trust.is_trusted,
+                is_trusted: trust.is_trusted,
                 signer_subject: trust.signer_subject,
                 signer_thumbprint: trust.signer_thumbprint,
+            },
+            signer,
+        )
+    }
+
+    #[inline]
+    fn trust_for_image(&self, path: &str) -> ImageVerdict {
+        if path == UNKNOWN_IMAGE || path == "SYSTEM" || path.is_empty() {
+            return ImageVerdict::default();
+        }
+
+        let started = Instant::now();
+        let mut verdict = self.trust_for_path(path);
+        self.metrics
+            .record_trust_lookup(verdict.trusted, started.elapsed());
+
+        // A denylisted image stays untrusted whatever its name.
+        let policy = self.policy();
+        if !verdict.trusted
+            && policy.cfg.security.allow_legacy_process_name_fallback
+            && !policy.is_denylisted_hash(verdict.sha256.as_deref())
+        {
+            verdict.trusted = self.is_legacy_allowlisted_process_name(path);
+        }
+        verdict
+    }
+
+    #[cfg(feature = "trust_api")]
+    fn trust_from_api(&self, decision: api::ApiDecision) -> TrustResult {
+        TrustResult {
+            is_signed: decision.is_signed,
+            is_trusted: decision.is_trusted,
+            signer_subject: decision.signer_subject,
+            signer_thumbprint: decision.signer_thumbprint,
+        }
+    }
+}
+
+impl Policy {
+    fn new(cfg: Config) -> anyhow::Result<Self> {
+        // Higher priority first, then exact rules over every other kind, then config
+        // order (the sort is stable).
+        let mut rules: Vec<_> = cfg
+            .watch
+            .exact_paths
+            .iter()
+            .chain(&cfg.watch.protected)
+            .collect();
+        rules.sort_by_key(|rule| (Reverse(rule.priority), rule.match_kind != MatchKind::Exact));
+        let rule_set = RuleSet::new(rules.iter().copied())
+            .context("failed to build the protected rule set")?;
+        let rules = rules
+            .iter()
+            .map(|rule| RuleInfo::new(rule).map(Arc::new))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let exclude = exclude_set(&cfg.watch.exclude, "watch.exclude")?.unwrap_or_default();
+        let hash_images = !cfg.allowlist.image_sha256.is_empty()
+            || !cfg.security.denylisted_image_sha256.is_empty()
+            || rules
+                .iter()
+                .any(|rule| rule.access.as_ref().is_some_and(AccessPolicy::wants_hash));
+
+        Ok(Self {
+            cfg,
+            rule_set,
+            rules,
+            exclude,
+            hash_images,
+        })
+    }
+
+    fn is_denylisted_hash(&self, sha256: Option<&str>) -> bool {
+        sha256.is_some_and(|h| {
+            self.cfg
+                .security
+                .denylisted_image_sha256
+                .iter()
+                .any(|d| d == h)
+        })
+    }
+}
+
+fn exclude_set(excludes: &[ExcludePattern], owner: &str) -> anyhow::Result<Option<RuleSet>> {
+    if excludes.is_empty() {
+        return Ok(None);
+    }
+    let rules: Vec<_> = excludes.iter().map(|ex| ex.to_rule(owner)).collect();
+    let set =
+        RuleSet::new(&rules).with_context(|| format!("invalid exclude pattern in {owner}"))?;
+    Ok(Some(set))
+}
+
+/// Whether a parent started after its child, which only a recycled pid explains.
+fn started_after(parent: Option<u64>, child: Option<u64>) -> bool {
+    matches!((parent, child), (Some(parent), Some(child)) if parent > child)
+}
+
+fn revocation_policy(cfg: &Config) -> RevocationPolicy {
+    match cfg.security.revocation_mode {
+        RevocationMode::None => RevocationPolicy::None,
+        RevocationMode::Chain => RevocationPolicy::WholeChain,
+    }
+}
+
+fn verified_signer(trust: &TrustResult) -> Option<String> {
+    if !trust.is_trusted {
+        return None;
+    }
+    trust.signer_subject.as_deref().map(str::to_lowercase)
+}
+
+#[cfg(feature = "trust_api")]
+fn verified(trust: TrustResult) -> (TrustResult, Option<String>) {
+    let signer = verified_signer(&trust);
+    (trust, signer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::{
+        event::FileOperation,
+        source::{EventSource, IterSource},
+    };
+    use crossbeam_channel::{Receiver, bounded};
+
+    #[derive(Debug, Default)]
+    struct FakeHost {
+        images: HashMap<u32, String>,
+        signed: HashMap<String, String>,
+        hashes: HashMap<String, String>,
+        /// Images whose verification blocks, like a slow revocation check.
+        slow: HashMap<String, Duration>,
+        /// Name of the thread each image hash ran on.
+        hashed_on: Mutex<Vec<String>>,
+        /// Name of the thread each signature verification ran on.
+        verified_on: Mutex<Vec<String>>,
+        users: HashMap<u32, String>,
+        starts: HashMap<u32, u64>,
+    }
+
+    impl Host for FakeHost {
+        fn verify_signature(&self, path: &str, _revocation: RevocationPolicy) -> TrustResult {
+            let thread = std::thread::current()
+                .name()
+                .unwrap_or_default()
+                .to_string();
+            self.verified_on.lock().push(thread);
+            if let Some(delay) = self.slow.get(path) {
+                std::thread::sleep(*delay);
+            }
+            let signer = self.signed.get(path).cloned();
+            TrustResult {
+                is_signed: signer.is_some(),
+                is_trusted: signer.is_some(),
+                signer_subject: signer,
+                signer_thumbprint: None,
+            }
+        }
+
+        fn process_image_path(&self, pid: u32) -> Option<String> {
+            self.images.get(&pid).cloned()
+        }
+
+        fn image_sha256(&self, path: &str) -> Option<String> {
+            let thread = std::thread::current()
+                .name()
+                .unwrap_or_default()
+                .to_string();
+            self.hashed_on.lock().push(thread);
+            self.hashes.get(path).cloned()
+        }
+
+        fn enum_process_ids(&self) -> anyhow::Result<Vec<u32>> {
+            Ok(self.images.keys().copied().collect())
+        }
+
+        fn file_objects_for_pids(
+            &self,
+            _pids: &[u32],
+        ) -> anyhow::Result<HashMap<u64, HashSet<u32>>> {
+            Ok(HashMap::new())
+        }
+
+        fn process_user(&self, pid: u32) -> Option<String> {
+            self.users.get(&pid).cloned()
+        }
+
+        fn process_start_time(&self, pid: u32) -> Option<u64> {
+            self.starts.get(&pid).copied()
+        }
+    }
+
+    const CONFIG: &str = r#"
+[general]
+suppress_ms = 60000
+
+[allowlist]
+signer_subject_allow = ["Google LLC"]
+
+[[watch.protected]]
+pattern = "\\Google\\Chrome\\User Data\\Default\\Login Data"
+name = "Chrome Passwords"
+
+[[watch.protected]]
+pattern = ".KDBX"
+match = "suffix"
+name = "KeePass"
+
+[[watch.exact_paths]]
+pattern = "C:\\Vault\\secret.kdbx"
+name = "Vault"
+"#;
+
+    const CHROME: &str = r"C:\Program Files\Google\Chrome\Application\chrome.exe";
+    const STEALER: &str = r"C:\Users\bob\AppData\Local\Temp\stealer.exe";
+    const LOGIN_DATA: &str =
+        r"C:\Users\bob\AppData\Local\Google\Chrome\User Data\Default\Login Data";
+
+    fn test_engine() -> (Engine, Receiver<Alert>) {
+        let cfg = Config::from_toml(CONFIG).expect("config should parse");
+        let mut host = FakeHost::default();
+        host.images.insert(100, CHROME.to_string());
+        host.images.insert(200, STEALER.to_string());
+        host.signed
+            .insert(CHROME.to_string(), "Google LLC".to_string());
+        let (tx, rx) = bounded(16);
+        (
+            Engine::with_host(cfg, tx, Arc::new(host)).expect("engine"),
+            rx,
+        )
+    }
+
+    #[test]
+    fn match_protected_rule_handles_exact_and_substring_rules() {
+        let (engine, _rx) = test_engine();
+        let (name, _) = engine
+            .match_protected_rule(r"C:\VAULT\Secret.kdbx")
+            .expect("exact rule should match");
+        assert_eq!(name, "Vault");
+
+        let (name, needle) = engine
+            .match_protected_rule(LOGIN_DATA)
+            .expect("substring rule should match");
+        assert_eq!(name, "Chrome Passwords");
+        assert_eq!(needle, r"\google\chrome\user data\default\login data");
+
+        let (name, _) = engine
+            .match_protected_rule(r"D:\Backup\Passwords.kdbx")
+            .expect("suffix rule should match");
+        assert_eq!(name, "KeePass");
+
+        assert!(
+            engine
+                .match_protected_rule(r"C:\Windows\notepad.exe")
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn untrusted_access_raises_alert() {
+        let (engine, rx) = test_engine();
+        engine.handle_file_access(200, 12, FileOperation::Create, LOGIN_DATA.to_string(), 0);
+
+        let alert = rx.try_recv().expect("alert expected");
+        assert_eq!(alert.pid, 200);
+        assert_eq!(alert.process, STEALER);
+        assert_eq!(alert.data_name, "Chrome Passwords");
+        assert_eq!(alert.kind, "protected_resource_access");
+    }
+
+    #[test]
+    fn trusted_access_is_silent_and_taints_file_object_for_others() {
+        let (engine, rx) = test_engine();
+        engine.handle_file_access(
+            100,
+            12,
+            FileOperation::Create,
+            LOGIN_DATA.to_string(),
+            0xdead,
+        );
+        assert!(rx.try_recv().is_err());
+
+        engine.handle_file_access(
+            200,
+            12,
+            FileOperation::Create,
+            LOGIN_DATA.to_string(),
+            0xdead,
+        );
+        let alert = rx.try_recv().expect("alert expected");
+        assert_eq!(alert.kind, "suspicious_whitelisted_handle_access");
+    }
+
+    #[test]
+    fn restricted_rules_alert_on_signed_but_unauthorized_accessors() {
+        const RESTRICTED: &str = r#"
+[allowlist]
+signer_subject_allow = ["Microsoft Corporation"]
+
+[[watch.protected]]
+pattern = "\\Mozilla\\Firefox\\Profiles"
+name = "Firefox Profile Store"
+authorized_images = ["firefox.exe"]
+authorized_signers = ["Mozilla Corporation"]
+
+[[watch.protected]]
+pattern = ".kdbx"
+match = "suffix"
+name = "KeePass"
+authorized_hashes = ["abababababababababababababababababababababababababababababababab"]
+
+[[watch.protected]]
+pattern = ".psafe3"
+match = "suffix"
+name = "Password Safe"
+authorized_images = ["pwsafe.exe"]
+"#;
+        let cfg = Config::from_toml(RESTRICTED).expect("config should parse");
+
+        const RUNDLL: &str = r"C:\Windows\System32\rundll32.exe";
+        const FIREFOX: &str = r"C:\Program Files\Mozilla Firefox\firefox.exe";
+        const KEEPASS: &str = r"C:\Tools\KeePass.exe";
+        const PWSAFE: &str = r"\Device\HarddiskVolume2\Program Files\Password Safe\pwsafe.exe";
+        const LOOKALIKE: &str = r"C:\Users\bob\AppData\Local\Temp\pwsafe.exe";
+        let mut host = FakeHost::default();
+        host.images.insert(600, PWSAFE.to_string());
+        host.images.insert(700, LOOKALIKE.to_string());
+        host.signed
+            .insert(PWSAFE.to_string(), "Microsoft Corporation".to_string());
+        host.images.insert(200, STEALER.to_string());
+        host.images.insert(300, RUNDLL.to_string());
+        host.images.insert(400, FIREFOX.to_string());
+        host.images.insert(500, KEEPASS.to_string());
+        host.signed
+            .insert(RUNDLL.to_string(), "Microsoft Corporation".to_string());
+        host.signed
+            .insert(FIREFOX.to_string(), "Mozilla Corporation".to_string());
+        host.hashes.insert(
+            KEEPASS.to_string(),
+            "ABABABABABABABABABABABABABABABABABABABABABABABABABABABABABABABAB".to_string(),
+        );
+        let (tx, rx) = bounded(16);
+        let engine = Engine::with_host(cfg, tx, Arc::new(host)).expect("engine");
+
+        let profile = r"C:\Users\bob\AppData\Roaming\Mozilla\Firefox\Profiles\x\logins.json";
+        engine.handle_file_access(400, 12, FileOperation::Create, profile.to_string(), 0);
+        engine.handle_file_access(500, 12, FileOperation::Create, r"D:\db.kdbx".to_string(), 0);
+        assert!(rx.try_recv().is_err());
+
+        engine.handle_file_access(300, 12, FileOperation::Create, profile.to_string(), 0);
+        let alert = rx.try_recv().expect("alert expected");
+        assert_eq!(alert.kind, "unauthorized_accessor");
+        assert_eq!(alert.pid, 300);
+
+        engine.handle_file_access(200, 12, FileOperation::Create, r"D:\db.kdbx".to_string(), 0);
+        let alert = rx.try_recv().expect("alert expected");
+        assert_eq!(alert.kind, "protected_resource_access");
+
+        // An image name alone never authorizes an unsigned look-alike.
+        let safe = r"D:\vault.psafe3".to_string();
+        engine.handle_file_access(600, 12, FileOperation::Read, safe.clone(), 0);
+        assert!(rx.try_recv().is_err());
+        engine.handle_file_access(700, 12, FileOperation::Read, safe, 0);
+        let alert = rx.try_recv().expect("alert expected");
+        assert_eq!(
+            (alert.pid, alert.kind.as_str()),
+            (700, "protected_resource_access")
+        );
+    }
+
+    #[test]
+    fn a_broad_rule_exclude_leaves_narrower_rules_in_force() {
+        const NESTED: &str = r#"
+[[watch.protected]]
+pattern = "\\Google\\Chrome\\User Data"
+name = "Chrome Data"
+exclude = [{ pattern = "**\\User Data\\Default\\**", match = "glob" }]
+
+[[watch.protected]]
+pattern = "\\Google\\Chrome\\User Data\\Default\\Login Data"
+name = "Chrome Passwords"
+"#;
+        let cfg = Config::from_toml(NESTED).expect("config should parse");
+        let (tx, rx) = bounded(16);
+        let engine = Engine::with_host(cfg, tx, Arc::new(FakeHost::default())).expect("engine");
+
+        let (name, _) = engine
+            .match_protected_rule(LOGIN_DATA)
+            .expect("narrower rule should match");
+        assert_eq!(name, "Chrome Passwords");
+        let (name, _) = engine
+            .match_protected_rule(r"C:\Users\bob\AppData\Local\Google\Chrome\User Data\Local State")
+            .expect("broad rule should match outside its exclude");
+        assert_eq!(name, "Chrome Data");
+        assert!(
+            engine
+                .match_protected_rule(
+                    r"C:\Users\bob\AppData\Local\Google\Chrome\User Data\Default\History"
+                )
+                .is_none()
+        );
+
+        engine.handle_file_access(200, 12, FileOperation::Create, LOGIN_DATA.to_string(), 0);
+        let alert = rx.try_recv().expect("alert expected");
+        assert_eq!(alert.data_name, "Chrome Passwords");
+        assert_eq!(engine.take_excluded_hits(), 0);
+    }
+
+    #[test]
+    fn excluded_hits_are_dropped_and_counted() {
+        const EXCLUDING: &str = r#"
+[watch]
+exclude = [{ pattern = "**\\Cache\\**", match = "glob" }]
+
+[[watch.protected]]
+pattern = "\\Google\\Chrome\\User Data\\Default"
+name = "Chrome Profile"
+exclude = ["Cookies-journal"]
+"#;
+        let cfg = Config::from_toml(EXCLUDING).expect("config should parse");
+        let (tx, rx) = bounded(16);
+        let engine = Engine::with_host(cfg, tx, Arc::new(FakeHost::default())).expect("engine");
+
+        let profile = r"C:\Users\bob\AppData\Local\Google\Chrome\User Data\Default";
+        for target in [
+            format!(r"{profile}\Network\Cookies-journal"),
+            format!(r"{profile}\Cache\Cache_Data\f_000001"),
+        ] {
+            engine.handle_file_access(200, 12, FileOperation::Create, target, 0);
+        }
+        assert!(rx.try_recv().is_err());
+        assert!(
+            engine
+                .match_protected_rule(&format!(r"{profile}\Network\Cookies-journal"))
+                .is_none()
+        );
+        assert_eq!(engine.take_excluded_hits(), 2);
+        assert_eq!(engine.take_excluded_hits(), 0);
+
+        engine.handle_file_access(
+            200,
+            12,
+            FileOperation::Create,
+            format!(r"{profile}\Login Data"),
+            0,
+        );
+        assert!(rx.try_recv().is_ok());
+    }
+
+    #[test]
+    fn rules_filter_operations_and_flag_sensitive_ones_from_trusted_processes() {
+        const WALLET: &str = r#"
+[allowlist]
+signer_subject_allow = ["Google LLC"]
+
+[[watch.protected]]
+pattern = "\\wallet.dat"
+match = "suffix"
+name = "Wallet"
+operations = ["create", "delete", "rename"]
+always_alert = ["delete", "rename"]
+"#;
+        let cfg = Config::from_toml(WALLET).expect("config should parse");
+        let mut host = FakeHost::default();
+        host.images.insert(100, CHROME.to_string());
+        host.images.insert(200, STEALER.to_string());
+        host.signed
+            .insert(CHROME.to_string(), "Google LLC".to_string());
+        let (tx, rx) = bounded(16);
+        let engine = Engine::with_host(cfg, tx, Arc::new(host)).expect("engine");
+        let wallet = r"C:\Users\bob\AppData\Roaming\Bitcoin\wallet.dat";
+
+        engine.handle_file_access(200, 15, FileOperation::Read, wallet.to_string(), 0);
+        engine.handle_file_access(100, 12, FileOperation::Create, wallet.to_string(), 0);
+        assert!(rx.try_recv().is_err());
+
+        engine.handle_file_access(100, 26, FileOperation::Delete, wallet.to_string(), 0);
+        let alert = rx.try_recv().expect("alert expected");
+        assert_eq!(alert.kind, "sensitive_operation");
+        assert_eq!(alert.operation, FileOperation::Delete);
+
+        engine.handle_file_access(200, 19, FileOperation::Rename, wallet.to_string(), 0);
+        let alert = rx.try_recv().expect("alert expected");
+        assert_eq!(alert.kind, "protected_resource_access");
+        assert_eq!(alert.operation, FileOperation::Rename);
+    }
+
+    #[test]
+    fn unprotected_file_name_mappings_are_not_cached() {
+        let (engine, _rx) = test_engine();
+        engine.handle_event(TelemetryEvent::FileNameMapping {
+            file_key: 8,
+            file_name: r"C:\Windows\System32\kernel32.dll".to_string(),
+        });
+        assert!(engine.resolve_file_key(8).is_none());
+
+        engine.handle_event(TelemetryEvent::FileNameMapping {
+            file_key: 9,
+            file_name: LOGIN_DATA.to_string(),
+        });
+        engine.handle_event(TelemetryEvent::FileNameMapping {
+            file_key: 9,
+            file_name: r"C:\Windows\System32\kernel32.dll".to_string(),
+        });
+        assert!(
+            engine.resolve_file_key(9).is_none(),
+            "recycled key is dropped"
+        );
+        assert!(!engine.is_protected_candidate(r"C:\Windows\System32\kernel32.dll"));
+        assert!(engine.is_protected_candidate(LOGIN_DATA));
+    }
+
+    #[test]
+    fn iter_source_resolves_file_key_mappings() {
+        let (engine, rx) = test_engine();
+        let engine = Arc::new(engine);
+        let events = vec![
+            TelemetryEvent::FileNameMapping {
+                file_key: 7,
+                file_name: LOGIN_DATA.to_string(),
+            },
+            TelemetryEvent::FileAccess {
+                pid: 200,
+                event_id: 12,
+                operation: FileOperation::Create,
+                file_name: None,
+                file_key: 7,
+                file_object: 0,
+            },
+            TelemetryEvent::FileClose { file_key: 7 },
+            TelemetryEvent::FileAccess {
+                pid: 200,
+                event_id: 12,
+                operation: FileOperation::Create,
+                file_name: None,
+                file_key: 7,
+                file_object: 0,
+            },
+        ];
+        IterSource(events)
+            .start(engine.clone())
+            .expect("iter source");
+
+        let alert = rx.try_recv().expect("alert expected");
+        assert_eq!(alert.target, LOGIN_DATA);
+        assert!(engine.resolve_file_key(7).is_none());
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn process_start_caches_image_until_stop() {
+        let (engine, _rx) = test_engine();
+        engine.handle_event(TelemetryEvent::ProcessStart {
+            pid: 300,
+            image: CHROME.to_string(),
+            cmdline: None,
+            parent_pid: None,
+            start_time: None,
+            user: None,
+        });
+        assert_eq!(engine.resolve_process_image(300), CHROME);
+
+        engine.handle_event(TelemetryEvent::ProcessStop {
+            pid: 300,
+            start_time: None,
+        });
+        assert_eq!(engine.resolve_process_image(300), "unknown");
+    }
+
+    #[test]
+    fn touching_three_protected_rules_raises_one_credential_sweep() {
+        let (engine, rx) = test_engine();
+        for target in [
+            LOGIN_DATA,
+            r"D:\Backup\passwords.kdbx",
+            r"C:\Vault\secret.kdbx",
+        ] {
+            engine.handle_file_access(200, 12, FileOperation::Read, target.to_string(), 0);
+        }
+        engine.handle_file_access(200, 12, FileOperation::Read, LOGIN_DATA.to_string(), 0);
+
+        let sweeps: Vec<Alert> = rx
+            .try_iter()
+            .filter(|a| a.kind == "credential_sweep")
+            .collect();
+        assert_eq!(sweeps.len(), 1);
+        assert_eq!(sweeps[0].data_name, "Chrome Passwords,KeePass,Vault");
+        assert_eq!(sweeps[0].related.len(), 3);
+        assert_eq!(sweeps[0].related[2].target, r"C:\Vault\secret.kdbx");
+    }
+
+    #[test]
+    fn alerts_carry_command_line_and_ancestry() {
+        let (engine, rx) = test_engine();
+        let start =
+            |pid, parent_pid, image: &str, cmdline: Option<&str>| TelemetryEvent::ProcessStart {
+                pid,
+                image: image.to_string(),
+                cmdline: cmdline.map(str::to_string),
+                parent_pid,
+                start_time: Some(1),
+                user: Some(r"CORP\bob".to_string()),
             };
+        engine.handle_event(start(40, None, r"C:\Windows\explorer.exe", None));
+        engine.handle_event(start(50, Some(40), r"C:\Office\WINWORD.EXE", None));
+        engine.handle_event(start(
+            60,
+            Some(50),
+            r"C:\Windows\powershell.exe",
+            Some("powershell.exe -enc AAAA"),
+        ));
+        engine.handle_file_access(60, 15, FileOperation::Read, LOGIN_DATA.to_string(), 0);
+
+        let alert = rx.try_recv().expect("alert expected");
+        assert_eq!(alert.process, r"C:\Windows\powershell.exe");
+        assert_eq!(alert.cmdline.as_deref(), Some("powershell.exe -enc AAAA"));
+        assert_eq!(
+            alert.parent_image.as_deref(),
+            Some(r"C:\Office\WINWORD.EXE")
+        );
+        let pids: Vec<u32> = alert.ancestry.iter().map(|a| a.pid).collect();
+        assert_eq!(pids, vec![50, 40]);
+        assert_eq!(alert.user.as_deref(), Some(r"CORP\bob"));
+    }
+
+    #[test]
+    fn unseen_parents_resolve_only_when_started_before_the_child() {
+        let cfg = Config::from_toml(CONFIG).expect("config should parse");
+        let mut host = FakeHost::default();
+        host.images
+            .insert(70, r"C:\Windows\svchost.exe".to_string());
+        host.starts.insert(70, 5);
+        host.users.insert(80, r"CORP\alice".to_string());
+        let (tx, rx) = bounded(16);
+        let engine = Engine::with_host(cfg, tx, Arc::new(host)).expect("engine");
+        let start = |pid, start_time| TelemetryEvent::ProcessStart {
+            pid,
+            image: STEALER.to_string(),
+            cmdline: None,
+            parent_pid: Some(70),
+            start_time: Some(start_time),
+            user: None,
+        };
+
+        engine.handle_event(start(80, 10));
+        engine.handle_file_access(80, 12, FileOperation::Create, LOGIN_DATA.to_string(), 0);
+        let alert = rx.try_recv().expect("alert expected");
+        assert_eq!(
+            alert.parent_image.as_deref(),
+            Some(r"C:\Windows\svchost.exe")
+        );
+        assert_eq!(alert.user.as_deref(), Some(r"CORP\alice"));
+
+        // Pid 70 now belongs to a process younger than this child.
+        engine.handle_event(start(90, 3));
+        assert!(engine.ancestry(90).is_empty());
+    }
+
+    #[test]
+    fn recycled_pid_does_not_inherit_trust_or_file_objects() {
+        let (engine, rx) = test_engine();
+        let start = |image: &str, start_time| TelemetryEvent::ProcessStart {
+            pid: 300,
+            image: image.to_string(),
+            cmdline: None,
+            parent_pid: None,
+            start_time: Some(start_time),
+            user: None,
+        };
+
+        engine.handle_event(start(CHROME, 1));
+        engine.handle_file_access(
+            300,
+            12,
+            FileOperation::Create,
+            LOGIN_DATA.to_string(),
+            0xbeef,
+        );
+        assert!(rx.try_recv().is_err());
+        assert!(engine.whitelisted_file_object_owner(0xbeef).is_some());
+
+        // A stop for some older incarnation leaves the current one alone.
+        engine.handle_event(TelemetryEvent::ProcessStop {
+            pid: 300,
+            start_time: Some(0),
+        });
+        assert!(engine.whitelisted_file_object_owner(0xbeef).is_some());
+
+        // The start of the next incarnation is seen without a stop for chrome.
+        engine.handle_event(start(STEALER, 2));
+        engine.handle_file_access(
+            300,
+            12,
+            FileOperation::Create,
+            LOGIN_DATA.to_string(),
+            0xbeef,
+        );
+        let alert = rx.try_recv().expect("alert expected");
+        assert_eq!(alert.process, STEALER);
+        assert_eq!(alert.kind, "protected_resource_access");
+        assert!(engine.whitelisted_file_object_owner(0xbeef).is_none());
+    }
+
+    #[test]
+    fn re_resolving_a_device_path_as_a_drive_path_keeps_lineage() {
+        let cfg = Config::from_toml(CONFIG).expect("config should parse");
+        let mut host = FakeHost::default();
+        host.images.insert(60, STEALER.to_string());
+        let (tx, _rx) = bounded(16);
+        let engine = Engine::with_host(cfg, tx, Arc::new(host)).expect("engine");
+        let start = |pid, parent_pid, image: &str| TelemetryEvent::ProcessStart {
+            pid,
+            image: image.to_string(),
+            cmdline: Some(format!("{image} --flag")),
+            parent_pid,
+            start_time: Some(pid.into()),
+            user: None,
+        };
+        let device = r"\Device\HarddiskVolume3\Users\bob\AppData\Local\Temp\STEALER.exe";
+        engine.handle_event(start(
+            50,
+            None,
+            r"\Device\HarddiskVolume3\Office\WINWORD.EXE",
+        ));
+        engine.handle_event(start(60, Some(50), device));
+        // pid 61 exits before the cached entry expires, so the host cannot name it.
+        engine.handle_event(start(61, Some(50), device));
+
+        let expire = |pid| {
+            let mut procs = engine.procs.write();
+            let meta = procs.live_proc_mut(pid).expect("cached");
+            meta.ts = Instant::now()
+                .checked_sub(Duration::from_secs(11))
+                .expect("uptime");
+        };
+        expire(60);
+        expire(61);
+        assert_eq!(engine.resolve_process_image(60), STEALER);
+        assert_eq!(engine.resolve_process_image(61), device);
+        assert!(!engine.is_pid_trusted(60, STEALER));
+
+        for pid in [60, 61] {
+            let procs = engine.procs.read();
+            let meta = procs.live_proc(pid).expect("still live");
+            assert_eq!(meta.image, device);
+            assert_eq!(
+                meta.cmdline.as_deref(),
+                Some(format!("{device} --flag").as_str())
+            );
+            assert_eq!(meta.parent_pid, Some(50));
+            drop(procs);
+            assert_eq!(engine.ancestry(pid).len(), 1);
         }
+    }
 
-        wintrust::TrustResult {
-            is_signed: trust.is_signed,
-            is_trusted: trust.is_trusted,
-            signer_subject: trust.signer_subject,
-            signer_thumbprint: trust.signer_thumbprint,
+    #[test]
+    fn exited_parents_stay_in_ancestry() {
+        let (engine, _rx) = test_engine();
+        engine.handle_event(TelemetryEvent::ProcessStart {
+            pid: 50,
+            image: r"C:\Office\WINWORD.EXE".to_string(),
+            cmdline: None,
+            parent_pid: None,
+            start_time: Some(1),
+            user: None,
+        });
+        engine.handle_event(TelemetryEvent::ProcessStart {
+            pid: 60,
+            image: STEALER.to_string(),
+            cmdline: None,
+            parent_pid: Some(50),
+            start_time: Some(2),
+            user: None,
+        });
+        engine.handle_event(TelemetryEvent::ProcessStop {
+            pid: 50,
+            start_time: Some(1),
+        });
+
+        let ancestry = engine.ancestry(60);
+        assert_eq!(ancestry.len(), 1);
+        assert_eq!(ancestry[0].image, r"C:\Office\WINWORD.EXE");
+    }
+
+    fn pooled_engine(
+        slow: Option<(&str, Duration)>,
+    ) -> (Arc<Engine>, Arc<FakeHost>, Receiver<Alert>) {
+        let toml = format!("{CONFIG}\n[concurrency]\ntrust_workers = 1\ntrust_deadline_ms = 50\n");
+        let cfg = Config::from_toml(&toml).expect("config should parse");
+        let mut host = FakeHost::default();
+        host.images.insert(100, CHROME.to_string());
+        host.images.insert(200, STEALER.to_string());
+        host.signed
+            .insert(CHROME.to_string(), "Google LLC".to_string());
+        if let Some((image, delay)) = slow {
+            host.slow.insert(image.to_string(), delay);
         }
+        let host = Arc::new(host);
+        let (tx, rx) = bounded(16);
+        let engine = Arc::new(Engine::with_host(cfg, tx, host.clone()).expect("engine"));
+        engine.start_trust_pool().expect("trust pool");
+        (engine, host, rx)
     }
 
-    #[inline]
-    fn trust_for_image(&self, path: &str) -> (bool, Option<String>) {
-        if path == "unknown" || path == "SYSTEM" || path.is_empty() {
-            return (false, None);
+    #[test]
+    fn trust_pool_resolves_verdicts_off_the_calling_thread() {
+        let (engine, host, rx) = pooled_engine(None);
+        engine.handle_file_access(100, 12, FileOperation::Create, LOGIN_DATA.to_string(), 0);
+        engine.handle_file_access(200, 12, FileOperation::Create, LOGIN_DATA.to_string(), 0);
+
+        let alert = rx
+            .recv_timeout(Duration::from_secs(2))
+            .expect("alert expected");
+        assert_eq!(alert.pid, 200);
+        assert!(!alert.trust_unknown);
+        assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+        let verified_on = host.verified_on.lock().clone();
+        assert_eq!(verified_on, vec!["vigil-trust-0".to_string(); 2]);
+    }
+
+    #[test]
+    fn image_hashes_run_with_the_verdict_on_a_trust_worker() {
+        let toml = format!(
+            "{CONFIG}\n[security]\ndenylisted_image_sha256 = [\"{}\"]\n\n[concurrency]\ntrust_workers = 1\n",
+            "cd".repeat(32)
+        );
+        let cfg = Config::from_toml(&toml).expect("config should parse");
+        let mut host = FakeHost::default();
+        host.images.insert(200, STEALER.to_string());
+        host.hashes.insert(STEALER.to_string(), "CD".repeat(32));
+        let host = Arc::new(host);
+        let (tx, rx) = bounded(16);
+        let engine = Arc::new(Engine::with_host(cfg, tx, host.clone()).expect("engine"));
+        engine.start_trust_pool().expect("trust pool");
+
+        engine.handle_file_access(200, 12, FileOperation::Create, LOGIN_DATA.to_string(), 0);
+        let alert = rx
+            .recv_timeout(Duration::from_secs(2))
+            .expect("alert expected");
+        assert_eq!(alert.kind, "denylisted_image");
+
+        // The cached verdict carries the hash; the second access hashes nothing.
+        engine.handle_file_access(200, 19, FileOperation::Delete, LOGIN_DATA.to_string(), 0);
+        let hashed_on = host.hashed_on.lock().clone();
+        assert_eq!(hashed_on, vec!["vigil-trust-0".to_string()]);
+    }
+
+    #[test]
+    fn verdicts_past_the_deadline_are_evaluated_as_trust_unknown() {
+        let (engine, _host, rx) = pooled_engine(Some((CHROME, Duration::from_millis(500))));
+        let start = Instant::now();
+        engine.handle_file_access(100, 12, FileOperation::Create, LOGIN_DATA.to_string(), 0);
+        assert!(start.elapsed() < Duration::from_millis(250));
+
+        let alert = rx
+            .recv_timeout(Duration::from_secs(2))
+            .expect("alert expected");
+        assert_eq!(alert.pid, 100);
+        assert_eq!(alert.kind, "protected_resource_access");
+        assert!(alert.trust_unknown);
+    }
+
+    #[test]
+    fn hits_released_at_the_deadline_correlate_once_the_verdict_arrives() {
+        let (engine, _host, rx) = pooled_engine(Some((STEALER, Duration::from_millis(300))));
+        for target in [
+            LOGIN_DATA,
+            r"D:\Backup\passwords.kdbx",
+            r"C:\Vault\secret.kdbx",
+        ] {
+            engine.handle_file_access(200, 12, FileOperation::Read, target.to_string(), 0);
         }
 
-        let trust = self.trust_for_path(path);
-        let is_trusted = trust.is_trusted;
-        let signer_subject = trust.signer_subject;
+        let unknown: Vec<Alert> = (0..3)
+            .map(|_| {
+                rx.recv_timeout(Duration::from_secs(2))
+                    .expect("alert expected")
+            })
+            .collect();
+        assert!(unknown.iter().all(|a| a.trust_unknown));
+        assert!(unknown.iter().all(|a| a.kind != "credential_sweep"));
+
+        let sweep = rx
+            .recv_timeout(Duration::from_secs(2))
+            .expect("sweep after the verdict");
+        assert_eq!(sweep.kind, "credential_sweep");
+        assert_eq!(sweep.related.len(), 3);
+    }
+
+    #[test]
+    fn repeated_alerts_are_suppressed_within_window() {
+        let (engine, rx) = test_engine();
+        engine.handle_file_access(200, 12, FileOperation::Create, LOGIN_DATA.to_string(), 0);
+        engine.handle_file_access(200, 12, FileOperation::Create, LOGIN_DATA.to_string(), 0);
+        assert!(rx.try_recv().is_ok());
+        assert!(rx.try_recv().is_err());
+    }
 
-        let is_trusted = if self.cfg.security.allow_legacy_process_name_fallback {
-            is_trusted || self.is_legacy_allowlisted_process_name(path)
-        } else {
-            is_trusted
+    #[test]
+    fn suppression_key_spans_respawns_and_reports_counts() {
+        let toml = CONFIG
+            .replace(
+                "suppress_ms = 60000",
+                "suppress_ms = 60000\nsuppress_key = \"image+rule\"",
+            )
+            .replace(
+                "name = \"Chrome Passwords\"",
+                "name = \"Chrome Passwords\"\nsuppress_ms = 50",
+            );
+        let mut host = FakeHost::default();
+        for pid in [200, 201, 202] {
+            host.images.insert(pid, STEALER.to_string());
+        }
+        let (tx, rx) = bounded(16);
+        let cfg = Config::from_toml(&toml).expect("config should parse");
+        let engine = Engine::with_host(cfg, tx, Arc::new(host)).expect("engine");
+        let touch = |pid| {
+            engine.handle_file_access(pid, 12, FileOperation::Read, LOGIN_DATA.to_string(), 0)
         };
 
-        (is_trusted, signer_subject)
+        touch(200);
+        touch(201);
+        touch(201);
+        let first = rx.try_recv().expect("alert expected");
+        assert_eq!(first.suppressed_count, 0);
+        assert!(rx.try_recv().is_err());
+        engine.flush_suppressed(false);
+        assert!(rx.try_recv().is_err());
+
+        thread::sleep(Duration::from_millis(60));
+        touch(201);
+        let next = rx.try_recv().expect("alert after the window closes");
+        assert_eq!(next.pid, 201);
+        assert_eq!(next.suppressed_count, 2);
+        assert!(next.first_seen.is_some_and(|t| t <= next.ts_unix));
+        assert!(next.human_line().contains("suppressed=2"));
+
+        touch(202);
+        assert!(rx.try_recv().is_err());
+        engine.flush_suppressed(true);
+        let summary = rx.try_recv().expect("summary expected");
+        assert_eq!(summary.kind, "suppressed_summary");
+        assert_eq!(summary.pid, 202);
+        assert_eq!(summary.suppressed_count, 1);
+        assert!(summary.cef_line().contains(" cnt=1 start="));
+        engine.flush_suppressed(true);
+        assert!(rx.try_recv().is_err());
     }
 
-    fn revocation_policy(&self) -> wintrust::RevocationPolicy {
-        match self.cfg.security.revocation_mode {
-            RevocationMode::None => wintrust::RevocationPolicy::None,
-            RevocationMode::Chain => wintrust::RevocationPolicy::WholeChain,
+    #[test]
+    fn alert_flood_is_rate_limited_into_a_storm_summary() {
+        let toml = format!(
+            "{}\n[rate_limit]\nenabled = true\nglobal_per_sec = 1\nglobal_burst = 100\nrule_per_sec = 1\nrule_burst = 3\n",
+            CONFIG.replace(
+                "name = \"KeePass\"",
+                "name = \"KeePass\"\nalways_alert = [\"delete\"]"
+            )
+        );
+        let mut host = FakeHost::default();
+        host.images.insert(100, CHROME.to_string());
+        host.images.insert(200, STEALER.to_string());
+        host.signed
+            .insert(CHROME.to_string(), "Google LLC".to_string());
+        let (tx, rx) = bounded(32);
+        let cfg = Config::from_toml(&toml).expect("config should parse");
+        let engine = Engine::with_host(cfg, tx, Arc::new(host)).expect("engine");
+
+        for i in 0..10 {
+            let db = format!(r"C:\Db\{i}.kdbx");
+            engine.handle_file_access(100, 19, FileOperation::Delete, db, 0);
         }
+        assert_eq!(rx.try_iter().count(), 3);
+        engine.flush_storm(false);
+        assert!(rx.try_recv().is_err(), "summary waits for storm_summary_ms");
+
+        engine.flush_storm(true);
+        let storm = rx.try_recv().expect("alert_storm expected");
+        assert_eq!(storm.kind, "alert_storm");
+        assert_eq!((storm.pid, storm.process.as_str()), (0, ""));
+        assert_eq!(storm.data_name, "");
+        let summary = storm.storm.as_ref().expect("storm summary");
+        assert_eq!(summary.limited, 7);
+        assert_eq!(summary.top_images[0].name, CHROME);
+        assert_eq!(summary.top_rules[0].count, 7);
+        assert!(storm.human_line().contains("storm_limited=7"));
+        assert!(storm.cef_line().contains(" cnt=7"));
+        engine.flush_storm(true);
+        assert!(rx.try_recv().is_err());
     }
 
-    #[cfg(feature = "trust_api")]
-    fn trust_from_api(&self, decision: api::ApiDecision) -> wintrust::TrustResult {
-        wintrust::TrustResult {
-            is_signed: decision.is_signed,
-            is_trusted: decision.is_trusted,
-            signer_subject: decision.signer_subject,
-            signer_thumbprint: decision.signer_thumbprint,
+    #[test]
+    fn untrusted_floods_are_rate_limited_but_live_responses_are_not() {
+        let toml = CONFIG.replace(
+            "name = \"Chrome Passwords\"",
+            "name = \"Chrome Passwords\"\nresponse = \"terminate\"",
+        ) + "\n[response]\nenabled = true\ndry_run = false\n\n[rate_limit]\nenabled = true\nglobal_per_sec = 1\nglobal_burst = 3\nrule_per_sec = 1\nrule_burst = 100\n";
+        let mut host = FakeHost::default();
+        host.images.insert(200, STEALER.to_string());
+        let (tx, rx) = bounded(32);
+        let cfg = Config::from_toml(&toml).expect("config should parse");
+        let engine = Arc::new(Engine::with_host(cfg, tx, Arc::new(host)).expect("engine"));
+        engine
+            .start_responder(Arc::new(FakeResponder::default()))
+            .expect("responder");
+
+        // An unsigned agent sweeping vaults is held to the global bucket.
+        for i in 0..10 {
+            let db = format!(r"C:\Backup\{i}.kdbx");
+            engine.handle_file_access(200, 12, FileOperation::Read, db, 0);
+        }
+        assert_eq!(rx.try_iter().count(), 3);
+
+        // Alerts that carry a live response are never held back.
+        for _ in 0..2 {
+            engine.handle_file_access(200, 12, FileOperation::Read, LOGIN_DATA.to_string(), 0);
+            let alert = rx
+                .recv_timeout(Duration::from_secs(2))
+                .expect("alert expected");
+            assert_eq!(alert.kind, "protected_resource_access");
+            assert!(alert.response.is_some());
+        }
+
+        engine.flush_storm(true);
+        let storm = rx.try_recv().expect("alert_storm expected");
+        assert_eq!(storm.kind, "alert_storm");
+        let summary = storm.storm.as_ref().expect("storm summary");
+        assert_eq!(summary.limited, 7);
+        assert_eq!(summary.top_images[0].name, STEALER);
+        assert_eq!(summary.top_rules[0].name, "KeePass");
+    }
+
+    #[test]
+    fn metrics_cover_rule_matches_alerts_and_state_sizes() {
+        let (engine, _rx) = test_engine();
+        engine.on_file_name_mapping(9, LOGIN_DATA.to_string());
+        engine.handle_file_access(200, 12, FileOperation::Read, LOGIN_DATA.to_string(), 0);
+        engine.handle_file_access(100, 12, FileOperation::Read, LOGIN_DATA.to_string(), 0);
+        engine.handle_file_access(
+            200,
+            12,
+            FileOperation::Read,
+            r"C:\Temp\x.txt".to_string(),
+            0,
+        );
+
+        let text = engine.render_metrics();
+        for line in [
+            "vigil_rule_matches_total{rule=\"Chrome Passwords\"} 2",
+            "vigil_alerts_total{kind=\"protected_resource_access\"} 1",
+            "vigil_trust_lookups_total{result=\"trusted\"} 1",
+            "vigil_trust_lookups_total{result=\"untrusted\"} 1",
+            "vigil_trust_lookup_seconds_count 2",
+            "vigil_cache_entries{cache=\"filekey_cache\"} 1",
+            "vigil_cache_entries{cache=\"last_alert\"} 1",
+            "vigil_cache_entries{cache=\"proc_cache\"} 2",
+            "vigil_alert_channel_depth 1",
+            "vigil_alert_channel_capacity 16",
+        ] {
+            assert!(
+                text.lines().any(|l| l == line),
+                "missing {line:?} in\n{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn image_hash_lists_override_signatures() {
+        const TOOL: &str = r"C:\Tools\backup.exe";
+        let (chrome_hash, tool_hash) = ("A".repeat(64), "B".repeat(64));
+        let toml = CONFIG.replace(
+            "signer_subject_allow = [\"Google LLC\"]",
+            &format!(
+                "signer_subject_allow = [\"Google LLC\"]\nimage_sha256 = [\"{tool_hash}\"]\n\
+                 process_name_allow = [\"chrome.exe\"]"
+            ),
+        ) + &format!(
+            "\n[security]\nallow_legacy_process_name_fallback = true\n\
+             denylisted_image_sha256 = [\"{}\"]\n",
+            chrome_hash.to_lowercase()
+        );
+        let mut host = FakeHost::default();
+        host.images.insert(100, CHROME.to_string());
+        host.images.insert(300, TOOL.to_string());
+        host.signed
+            .insert(CHROME.to_string(), "Google LLC".to_string());
+        host.hashes.insert(CHROME.to_string(), chrome_hash);
+        host.hashes.insert(TOOL.to_string(), tool_hash);
+        let (tx, rx) = bounded(16);
+        let cfg = Config::from_toml(&toml).expect("config should parse");
+        let engine = Engine::with_host(cfg, tx, Arc::new(host)).expect("engine");
+
+        engine.handle_file_access(300, 12, FileOperation::Read, LOGIN_DATA.to_string(), 0);
+        assert!(rx.try_recv().is_err());
+        assert!(engine.is_pid_trusted(300, TOOL));
+
+        engine.handle_file_access(100, 12, FileOperation::Read, LOGIN_DATA.to_string(), 0);
+        let alert = rx.try_recv().expect("alert expected");
+        assert_eq!(alert.kind, "denylisted_image");
+        assert!(!engine.is_pid_trusted(100, CHROME));
+    }
+
+    #[derive(Debug, Default)]
+    struct FakeResponder {
+        /// Action, target and the thread it ran on.
+        actions: Mutex<Vec<(ResponseKind, ResponseTarget, String)>>,
+    }
+
+    impl ResponseAction for FakeResponder {
+        fn execute(&self, action: ResponseKind, target: &ResponseTarget) -> anyhow::Result<String> {
+            let thread = std::thread::current()
+                .name()
+                .unwrap_or_default()
+                .to_string();
+            self.actions.lock().push((action, target.clone(), thread));
+            Ok("done".to_string())
+        }
+    }
+
+    #[test]
+    fn responses_run_within_safety_rails_and_are_audited() {
+        let toml = CONFIG
+            .replace(
+                "name = \"Chrome Passwords\"",
+                "name = \"Chrome Passwords\"\nresponse = \"terminate\"\nalways_alert = [\"delete\"]",
+            )
+            .replace(
+                "name = \"KeePass\"",
+                "name = \"KeePass\"\nresponse = \"suspend\"\nauthorized_images = [\"keepass.exe\"]",
+            );
+        // Signed images outside the signer allowlist still count as trusted here.
+        let enforce = format!(
+            "{toml}\n[security]\nrequire_signer_allowlist = false\n\n[response]\nenabled = true\ndry_run = false\n"
+        );
+        let cfg = Config::from_toml(&enforce).expect("config should parse");
+        let mut host = FakeHost::default();
+        host.images.insert(100, CHROME.to_string());
+        host.images.insert(200, STEALER.to_string());
+        host.images.insert(300, r"C:\Tools\backup.exe".to_string());
+        host.signed
+            .insert(CHROME.to_string(), "Google LLC".to_string());
+        host.signed.insert(
+            r"C:\Tools\backup.exe".to_string(),
+            "Backup Corp".to_string(),
+        );
+        let (tx, rx) = bounded(16);
+        let engine = Arc::new(Engine::with_host(cfg, tx, Arc::new(host)).expect("engine"));
+        let responder = Arc::new(FakeResponder::default());
+        engine
+            .start_responder(responder.clone())
+            .expect("responder");
+
+        engine.handle_file_access(200, 12, FileOperation::Read, LOGIN_DATA.to_string(), 0);
+        let alert = rx
+            .recv_timeout(Duration::from_secs(2))
+            .expect("alert expected");
+        let record = alert.response.expect("audit record");
+        assert_eq!(record.action, ResponseKind::Terminate);
+        assert_eq!(record.outcome, ResponseOutcome::Executed);
+
+        // A trusted process's sensitive operation is reported, never acted on.
+        engine.handle_file_access(300, 19, FileOperation::Delete, LOGIN_DATA.to_string(), 0);
+        let alert = rx.try_recv().expect("alert expected");
+        assert_eq!(alert.kind, "sensitive_operation");
+        let record = alert.response.expect("audit record");
+        assert_eq!(record.outcome, ResponseOutcome::Skipped);
+        assert_eq!(record.detail, "trusted accessor");
+
+        let vault = r"C:\Users\bob\vault.kdbx".to_string();
+        engine.handle_file_access(100, 12, FileOperation::Read, vault.clone(), 0);
+        let alert = rx.try_recv().expect("alert expected");
+        assert_eq!(alert.kind, "unauthorized_accessor");
+        let record = alert.response.expect("audit record");
+        assert_eq!(record.outcome, ResponseOutcome::Skipped);
+        assert_eq!(record.detail, "allowlisted signer");
+
+        engine
+            .reload(
+                Config::from_toml(&format!("{toml}\n[response]\nenabled = true\n"))
+                    .expect("config"),
+            )
+            .expect("reload");
+        engine.handle_file_access(200, 12, FileOperation::Read, vault, 0);
+        let record = rx
+            .try_recv()
+            .expect("alert expected")
+            .response
+            .expect("audit record");
+        assert_eq!(record.outcome, ResponseOutcome::DryRun);
+
+        let actions = responder.actions.lock();
+        assert_eq!(actions.len(), 1);
+        let (action, target, thread) = &actions[0];
+        assert_eq!(*action, ResponseKind::Terminate);
+        assert_eq!((target.pid, target.image.as_str()), (200, STEALER));
+        assert!(!target.start_known, "start was never observed");
+        assert_eq!(thread, "vigil-responder");
+    }
+
+    #[test]
+    fn suppression_keeps_kinds_apart_and_never_swallows_a_response() {
+        let toml = CONFIG
+            .replace("suppress_ms = 60000", "suppress_ms = 60000\nsuppress_key = \"rule\"")
+            .replace(
+                "name = \"Chrome Passwords\"",
+                "name = \"Chrome Passwords\"\nresponse = \"terminate\"\nalways_alert = [\"delete\"]",
+            )
+            + "\n[response]\nenabled = true\ndry_run = false\n";
+        let mut host = FakeHost::default();
+        host.images.insert(100, CHROME.to_string());
+        host.images.insert(200, STEALER.to_string());
+        host.images.insert(201, STEALER.to_string());
+        host.signed
+            .insert(CHROME.to_string(), "Google LLC".to_string());
+        let (tx, rx) = bounded(16);
+        let cfg = Config::from_toml(&toml).expect("config should parse");
+        let engine = Arc::new(Engine::with_host(cfg, tx, Arc::new(host)).expect("engine"));
+        let responder = Arc::new(FakeResponder::default());
+        engine
+            .start_responder(responder.clone())
+            .expect("responder");
+        let recv = || {
+            rx.recv_timeout(Duration::from_secs(2))
+                .expect("alert expected")
+        };
+
+        engine.handle_file_access(200, 12, FileOperation::Read, LOGIN_DATA.to_string(), 0);
+        assert_eq!(recv().kind, "protected_resource_access");
+        engine.handle_file_access(100, 19, FileOperation::Delete, LOGIN_DATA.to_string(), 0);
+        assert_eq!(recv().kind, "sensitive_operation");
+        engine.handle_file_access(201, 12, FileOperation::Read, LOGIN_DATA.to_string(), 0);
+        let alert = recv();
+        assert_eq!(alert.pid, 201);
+        let record = alert.response.expect("audit record");
+        assert_eq!(record.outcome, ResponseOutcome::Executed);
+
+        let pids: Vec<u32> = responder
+            .actions
+            .lock()
+            .iter()
+            .map(|(_, target, _)| target.pid)
+            .collect();
+        assert_eq!(pids, [200, 201]);
+    }
+
+    #[test]
+    fn learn_mode_records_accessors_instead_of_alerting() {
+        let (engine, rx) = test_engine();
+        let baseline = Arc::new(Baseline::default());
+        let engine = engine.with_baseline(baseline.clone());
+        for pid in [100, 200, 200] {
+            engine.handle_file_access(pid, 12, FileOperation::Read, LOGIN_DATA.to_string(), 0);
+        }
+        assert!(rx.try_recv().is_err());
+
+        let entries = baseline.entries();
+        assert_eq!(entries.len(), 2);
+        let chrome = &entries[0];
+        assert_eq!(chrome.accessor.rule, "Chrome Passwords");
+        assert_eq!(chrome.accessor.image, CHROME.to_lowercase());
+        assert_eq!(chrome.accessor.signer.as_deref(), Some("google llc"));
+        assert_eq!(chrome.hits, 1);
+        assert_eq!(entries[1].accessor.signer, None);
+        assert_eq!(entries[1].hits, 2);
+    }
+
+    #[test]
+    fn learn_mode_records_slow_verdicts_once_they_arrive() {
+        let toml = format!("{CONFIG}\n[concurrency]\ntrust_workers = 1\ntrust_deadline_ms = 50\n");
+        let cfg = Config::from_toml(&toml).expect("config should parse");
+        let mut host = FakeHost::default();
+        host.images.insert(100, CHROME.to_string());
+        host.signed
+            .insert(CHROME.to_string(), "Google LLC".to_string());
+        host.slow
+            .insert(CHROME.to_string(), Duration::from_millis(300));
+        let (tx, rx) = bounded(16);
+        let baseline = Arc::new(Baseline::default());
+        let engine = Arc::new(
+            Engine::with_host(cfg, tx, Arc::new(host))
+                .expect("engine")
+                .with_baseline(baseline.clone()),
+        );
+        engine.start_trust_pool().expect("trust pool");
+
+        engine.handle_file_access(100, 12, FileOperation::Read, LOGIN_DATA.to_string(), 0);
+        std::thread::sleep(Duration::from_millis(150));
+        assert!(baseline.is_empty(), "nothing recorded at the deadline");
+
+        let until = Instant::now() + Duration::from_secs(2);
+        while baseline.is_empty() && Instant::now() < until {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let entries = baseline.entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].accessor.signer.as_deref(), Some("google llc"));
+        assert_eq!(entries[0].hits, 1);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn reload_swaps_rules_and_allowlists_but_keeps_learned_state() {
+        const COOKIES: &str =
+            r"C:\Users\bob\AppData\Local\Google\Chrome\User Data\Default\Network\Cookies";
+        let (engine, rx) = test_engine();
+        engine.handle_file_access(
+            100,
+            12,
+            FileOperation::Create,
+            LOGIN_DATA.to_string(),
+            0xdead,
+        );
+        engine.handle_file_access(200, 12, FileOperation::Create, COOKIES.to_string(), 0);
+        assert!(rx.try_recv().is_err());
+
+        let with_cookies = format!(
+            "{CONFIG}\n[[watch.protected]]\npattern = '\\Network\\Cookies'\nname = 'Chrome Cookies'\n"
+        );
+        engine
+            .reload(Config::from_toml(&with_cookies).expect("config should parse"))
+            .expect("reload");
+        assert_eq!(engine.rule_count(), 4);
+
+        engine.handle_file_access(200, 12, FileOperation::Create, COOKIES.to_string(), 0);
+        assert_eq!(
+            rx.try_recv().expect("alert expected").data_name,
+            "Chrome Cookies"
+        );
+        engine.handle_file_access(
+            200,
+            12,
+            FileOperation::Create,
+            LOGIN_DATA.to_string(),
+            0xdead,
+        );
+        let alert = rx.try_recv().expect("alert expected");
+        assert_eq!(alert.kind, "suspicious_whitelisted_handle_access");
+
+        let microsoft_only = with_cookies.replace("Google LLC", "Microsoft Corporation");
+        engine
+            .reload(Config::from_toml(&microsoft_only).expect("config should parse"))
+            .expect("reload");
+        engine.handle_file_access(100, 12, FileOperation::Create, COOKIES.to_string(), 0);
+        let alert = rx.try_recv().expect("alert expected");
+        assert_eq!(alert.pid, 100);
+        assert_eq!(alert.kind, "protected_resource_access");
+
+        // A rule set that cannot be built leaves the active policy in place.
+        let mut oversized = Config::from_toml(&microsoft_only).expect("config should parse");
+        oversized
+            .watch
+            .protected
+            .extend((0..4).map(|i| ProtectedRule {
+                pattern: format!(r"\\(?-u:\w){{1,20000}}\\s{i}"),
+                name: format!("r{i}"),
+                match_kind: MatchKind::Regex,
+                ..Default::default()
+            }));
+        let rules = engine.rule_count();
+        assert!(engine.reload(oversized).is_err());
+        assert_eq!(engine.rule_count(), rules);
+        engine.handle_file_access(100, 12, FileOperation::Read, LOGIN_DATA.to_string(), 0);
+        let alert = rx.try_recv().expect("old rules still alert");
+        assert_eq!(alert.data_name, "Chrome Passwords");
+    }
+
+    #[test]
+    fn match_mode_and_priority_pick_between_overlapping_rules() {
+        let engine_for = |watch: &str, rules: &str| {
+            let toml = format!(
+                "[watch]\n{watch}\n[[watch.protected]]\npattern = '\\Google\\Chrome\\User Data'\nname = 'Chrome Profile'\n{rules}"
+            );
+            let (tx, rx) = bounded(16);
+            let cfg = Config::from_toml(&toml).expect("config should parse");
+            let mut host = FakeHost::default();
+            host.images.insert(200, STEALER.to_string());
+            (
+                Engine::with_host(cfg, tx, Arc::new(host)).expect("engine"),
+                rx,
+            )
+        };
+        let rule_for = |engine: &Engine| {
+            engine
+                .match_protected_rule(LOGIN_DATA)
+                .map(|(name, _)| name)
+        };
+
+        let (engine, _rx) = engine_for("", CONFIG);
+        assert_eq!(rule_for(&engine).as_deref(), Some("Chrome Profile"));
+
+        let (engine, _rx) = engine_for("match_mode = 'most_specific'", CONFIG);
+        assert_eq!(rule_for(&engine).as_deref(), Some("Chrome Passwords"));
+
+        let prioritized = CONFIG.replace(
+            "name = \"Chrome Passwords\"",
+            "name = \"Chrome Passwords\"\npriority = 10",
+        );
+        let (engine, _rx) = engine_for("", &prioritized);
+        assert_eq!(rule_for(&engine).as_deref(), Some("Chrome Passwords"));
+
+        let (engine, rx) = engine_for("match_mode = 'all'", CONFIG);
+        engine.handle_file_access(200, 12, FileOperation::Read, LOGIN_DATA.to_string(), 0);
+        let alert = rx.try_recv().expect("alert expected");
+        assert_eq!(alert.data_name, "Chrome Profile");
+        assert_eq!(alert.matched_rules, ["Chrome Profile", "Chrome Passwords"]);
+        assert!(
+            alert
+                .cef_line()
+                .contains("cs1=Chrome Profile,Chrome Passwords")
+        );
+
+        // Each rule keeps its own operation filter in `all` mode.
+        let (engine, rx) = engine_for(
+            "match_mode = 'all'",
+            &format!("operations = ['delete']\n{CONFIG}"),
+        );
+        engine.handle_file_access(200, 12, FileOperation::Read, LOGIN_DATA.to_string(), 0);
+        let alert = rx.try_recv().expect("alert expected");
+        assert_eq!(alert.data_name, "Chrome Passwords");
+        assert_eq!(alert.matched_rules, ["Chrome Passwords"]);
+    }
+
+    #[test]
+    fn a_narrow_rule_operation_filter_leaves_broader_rules_in_force() {
+        const OVERLAP: &str = r#"
+[[watch.protected]]
+pattern = "\\Google\\Chrome\\User Data\\Default\\Login Data"
+name = "Chrome Passwords"
+operations = ["delete", "rename"]
+
+[[watch.protected]]
+pattern = "\\Google\\Chrome\\User Data"
+name = "Chrome Profile"
+"#;
+        for mode in ["first", "most_specific"] {
+            let toml = format!("[watch]\nmatch_mode = '{mode}'\n{OVERLAP}");
+            let cfg = Config::from_toml(&toml).expect("config should parse");
+            let mut host = FakeHost::default();
+            host.images.insert(200, STEALER.to_string());
+            host.images.insert(201, STEALER.to_string());
+            let (tx, rx) = bounded(16);
+            let engine = Engine::with_host(cfg, tx, Arc::new(host)).expect("engine");
+
+            engine.handle_file_access(200, 12, FileOperation::Read, LOGIN_DATA.to_string(), 0);
+            let alert = rx.try_recv().expect("broad rule should alert on a read");
+            assert_eq!(alert.data_name, "Chrome Profile", "{mode}");
+
+            engine.handle_file_access(201, 26, FileOperation::Delete, LOGIN_DATA.to_string(), 0);
+            let alert = rx.try_recv().expect("narrow rule should alert on a delete");
+            assert_eq!(alert.data_name, "Chrome Passwords", "{mode}");
         }
     }
 }
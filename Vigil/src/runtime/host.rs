@@ -0,0 +1,115 @@
+use crate::trust::verdict::{RevocationPolicy, TrustResult};
+use anyhow::Result;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    sync::Arc,
+};
+
+/// OS services the engine depends on for trust and process resolution.
+///
+/// Keeping these behind a trait lets the detection policy run (and be tested)
+/// without WinTrust, process queries or the system handle table.
+pub trait Host: Send + Sync + fmt::Debug {
+    fn verify_signature(&self, path: &str, revocation: RevocationPolicy) -> TrustResult;
+
+    fn process_image_path(&self, pid: u32) -> Option<String>;
+
+    /// Uppercase hex SHA-256 of an image, or `None` when it cannot be read.
+    fn image_sha256(&self, path: &str) -> Option<String>;
+
+    fn enum_process_ids(&self) -> Result<Vec<u32>>;
+
+    fn file_objects_for_pids(&self, pids: &[u32]) -> Result<HashMap<u64, HashSet<u32>>>;
+
+    /// `DOMAIN\user` owning the token of the process now holding `pid`.
+    fn process_user(&self, _pid: u32) -> Option<String> {
+        None
+    }
+
+    /// Creation time of the process now holding `pid`, as a Windows FILETIME.
+    fn process_start_time(&self, _pid: u32) -> Option<u64> {
+        None
+    }
+}
+
+/// Live Windows host backed by WinTrust, process APIs and the handle table.
+#[cfg(windows)]
+#[derive(Debug, Default)]
+pub struct WindowsHost;
+
+#[cfg(windows)]
+impl Host for WindowsHost {
+    fn verify_signature(&self, path: &str, revocation: RevocationPolicy) -> TrustResult {
+        crate::trust::wintrust::verify_file_signature(path, revocation)
+    }
+
+    fn process_image_path(&self, pid: u32) -> Option<String> {
+        crate::trust::process::get_process_image_path(pid)
+    }
+
+    fn image_sha256(&self, path: &str) -> Option<String> {
+        crate::trust::hash::sha256_file(std::path::Path::new(path)).ok()
+    }
+
+    fn enum_process_ids(&self) -> Result<Vec<u32>> {
+        crate::trust::process::enum_process_ids()
+    }
+
+    fn file_objects_for_pids(&self, pids: &[u32]) -> Result<HashMap<u64, HashSet<u32>>> {
+        crate::telemetry::handles::collect_file_objects_for_pids(pids)
+    }
+
+    fn process_user(&self, pid: u32) -> Option<String> {
+        crate::trust::process::get_process_user(pid)
+    }
+
+    fn process_start_time(&self, pid: u32) -> Option<u64> {
+        crate::trust::process::get_process_start_time(pid)
+    }
+}
+
+/// Host with no live system behind it: nothing is signed and no processes are visible.
+#[derive(Debug, Default)]
+pub struct NullHost;
+
+impl Host for NullHost {
+    fn verify_signature(&self, _path: &str, _revocation: RevocationPolicy) -> TrustResult {
+        TrustResult {
+            is_signed: false,
+            is_trusted: false,
+            signer_subject: None,
+            signer_thumbprint: None,
+        }
+    }
+
+    fn process_image_path(&self, pid: u32) -> Option<String> {
+        if pid == 0 || pid == 4 {
+            return Some("SYSTEM".to_string());
+        }
+        None
+    }
+
+    fn image_sha256(&self, _path: &str) -> Option<String> {
+        None
+    }
+
+    fn enum_process_ids(&self) -> Result<Vec<u32>> {
+        Ok(Vec::new())
+    }
+
+    fn file_objects_for_pids(&self, _pids: &[u32]) -> Result<HashMap<u64, HashSet<u32>>> {
+        Ok(HashMap::new())
+    }
+}
+
+pub fn default_host() -> Arc<dyn Host> {
+    #[cfg(windows)]
+    {
+        Arc::new(WindowsHost)
+    }
+    #[cfg(not(windows))]
+    {
+        Arc::new(NullHost)
+    }
+}
@@ -1 +1,10 @@
+pub mod baseline;
+pub mod correlation;
 pub mod engine;
+pub mod host;
+pub mod procs;
+pub mod ratelimit;
+pub mod response;
+pub mod rules;
+pub mod shard;
+pub mod trust_pool;
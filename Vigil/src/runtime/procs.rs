@@ -0,0 +1,228 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+const PROC_MAX: usize = 50_000;
+/// A full table is pruned down to this size, so pruning runs once per
+/// `PROC_MAX - PROC_LOW_WATER` inserts rather than on every one.
+const PROC_LOW_WATER: usize = PROC_MAX * 9 / 10;
+/// How long an exited process stays resolvable for ancestry.
+const TOMBSTONE_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Identity of one process incarnation; PIDs alone are recycled by the OS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ProcKey {
+    pub pid: u32,
+    /// Creation FILETIME, or the time we first saw the pid when its start was missed.
+    pub start_time: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProcMeta {
+    pub image: String,
+    pub ts: Instant,
+    /// When `is_trusted_signed` was decided; `None` until the image is verified.
+    pub verified_at: Option<Instant>,
+    pub is_trusted_signed: bool,
+    /// Lowercased subject of a verified signature, if any.
+    pub signer: Option<String>,
+    /// Image SHA-256, hashed alongside the verdict when a hash list needs it.
+    pub sha256: Option<String>,
+    pub parent_pid: Option<u32>,
+    /// The parent incarnation, when it was live at our start.
+    pub parent: Option<ProcKey>,
+    pub cmdline: Option<String>,
+    /// Creation time as a Windows FILETIME.
+    pub start_time: Option<u64>,
+    pub user: Option<String>,
+    /// Set when a stop event tombstones the entry.
+    pub exited: Option<Instant>,
+}
+
+impl ProcMeta {
+    pub fn new(image: String, is_trusted_signed: bool, signer: Option<String>) -> Self {
+        let now = Instant::now();
+        Self {
+            image,
+            ts: now,
+            verified_at: Some(now),
+            is_trusted_signed,
+            signer,
+            sha256: None,
+            parent_pid: None,
+            parent: None,
+            cmdline: None,
+            start_time: None,
+            user: None,
+            exited: None,
+        }
+    }
+
+    /// An image whose trust has not been verified yet.
+    pub fn unverified(image: String) -> Self {
+        Self {
+            verified_at: None,
+            ..Self::new(image, false, None)
+        }
+    }
+}
+
+/// Known process incarnations and the live one for each pid.
+#[derive(Debug, Default)]
+pub struct ProcTable {
+    /// Every known incarnation, including tombstones of exited ones.
+    procs: HashMap<ProcKey, ProcMeta>,
+    /// The current incarnation of each live pid.
+    live: HashMap<u32, ProcKey>,
+}
+
+impl ProcTable {
+    /// Known incarnations, tombstones included.
+    pub fn len(&self) -> usize {
+        self.procs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.procs.is_empty()
+    }
+
+    pub fn live_key(&self, pid: u32) -> Option<ProcKey> {
+        self.live.get(&pid).copied()
+    }
+
+    pub fn live_proc(&self, pid: u32) -> Option<&ProcMeta> {
+        self.procs.get(self.live.get(&pid)?)
+    }
+
+    pub fn live_proc_mut(&mut self, pid: u32) -> Option<&mut ProcMeta> {
+        self.procs.get_mut(self.live.get(&pid)?)
+    }
+
+    pub fn is_live(&self, key: &ProcKey) -> bool {
+        self.live.get(&key.pid) == Some(key)
+    }
+
+    /// Any incarnation, live or tombstoned.
+    pub fn get(&self, key: &ProcKey) -> Option<&ProcMeta> {
+        self.procs.get(key)
+    }
+
+    /// Make `meta` the live incarnation of `pid`, tombstoning whatever it replaces.
+    pub fn insert(&mut self, pid: u32, mut meta: ProcMeta) -> ProcKey {
+        let key = ProcKey {
+            pid,
+            start_time: meta.start_time.unwrap_or_else(filetime_now),
+        };
+        if meta.parent.is_none()
+            && let Some(ppid) = meta.parent_pid
+            && let Some(parent) = self.live.get(&ppid)
+            && parent.start_time <= key.start_time
+        {
+            meta.parent = Some(*parent);
+        }
+
+        if self.procs.len() >= PROC_MAX {
+            self.prune(Instant::now());
+        }
+        if let Some(old) = self.live.insert(pid, key)
+            && old != key
+        {
+            self.tombstone(old);
+        }
+        self.procs.insert(key, meta);
+        key
+    }
+
+    pub fn exit(&mut self, pid: u32, start_time: Option<u64>) {
+        let Some(key) = self.live_key(pid) else {
+            return;
+        };
+        // A stop for an older incarnation must not retire the current one.
+        let current_start = self.procs.get(&key).and_then(|m| m.start_time);
+        if let (Some(stopped), Some(current)) = (start_time, current_start)
+            && stopped != current
+        {
+            return;
+        }
+        self.live.remove(&pid);
+        self.tombstone(key);
+    }
+
+    /// Force every live process to be verified again on its next access.
+    pub fn invalidate_verdicts(&mut self) {
+        for meta in self.procs.values_mut() {
+            meta.verified_at = None;
+        }
+    }
+
+    fn tombstone(&mut self, key: ProcKey) {
+        if let Some(meta) = self.procs.get_mut(&key) {
+            meta.exited.get_or_insert_with(Instant::now);
+        }
+    }
+
+    fn prune(&mut self, now: Instant) {
+        self.procs.retain(|_, meta| {
+            meta.exited
+                .is_none_or(|at| now.duration_since(at) <= TOMBSTONE_TTL)
+        });
+        if self.procs.len() > PROC_LOW_WATER {
+            self.procs.retain(|_, meta| meta.exited.is_none());
+        }
+        let excess = self.procs.len().saturating_sub(PROC_LOW_WATER);
+        if excess == 0 {
+            return;
+        }
+        // Only lost stop events leave this many live entries: evict those seen least
+        // recently. They are resolved again, without lineage, on their next access.
+        let mut seen: Vec<(Instant, ProcKey)> = self
+            .procs
+            .iter()
+            .map(|(key, meta)| (meta.ts, *key))
+            .collect();
+        seen.select_nth_unstable_by_key(excess - 1, |(ts, _)| *ts);
+        for (_, key) in &seen[..excess] {
+            self.procs.remove(key);
+            if self.is_live(key) {
+                self.live.remove(&key.pid);
+            }
+        }
+    }
+}
+
+/// Current time as a Windows FILETIME (100 ns ticks since 1601-01-01).
+fn filetime_now() -> u64 {
+    const UNIX_EPOCH_AS_FILETIME: u64 = 116_444_736_000_000_000;
+    let since_unix = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    UNIX_EPOCH_AS_FILETIME + (since_unix.as_nanos() / 100) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_full_table_evicts_the_stalest_live_entries() {
+        let mut table = ProcTable::default();
+        let mut stale = ProcMeta::unverified("stale.exe".to_string());
+        stale.ts -= Duration::from_secs(3600);
+        table.insert(1, stale);
+        for pid in 2..=PROC_MAX as u32 {
+            table.insert(pid, ProcMeta::unverified("p.exe".to_string()));
+        }
+        assert_eq!(table.len(), PROC_MAX);
+
+        let newest = table.insert(u32::MAX, ProcMeta::unverified("new.exe".to_string()));
+        assert_eq!(table.len(), PROC_LOW_WATER + 1);
+        assert_eq!(table.live.len(), table.len());
+        assert!(table.live_key(1).is_none(), "stalest entry goes first");
+        assert!(table.is_live(&newest));
+
+        // The next prune is a full PROC_MAX - PROC_LOW_WATER inserts away.
+        table.insert(u32::MAX - 1, ProcMeta::unverified("new.exe".to_string()));
+        assert_eq!(table.len(), PROC_LOW_WATER + 2);
+    }
+}
@@ -0,0 +1,227 @@
+use crate::{
+    output::alerts::{StormCount, StormSummary},
+    support::config::RateLimitConfig,
+};
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+const STORM_TOP: usize = 5;
+const STORM_IMAGES_MAX: usize = 4096;
+
+/// Refills at `rate` tokens per second up to `burst`; one token per alert.
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    /// `None` when `per_sec` is 0, which leaves that scope unlimited.
+    fn new(per_sec: u32, burst: u32, now: Instant) -> Option<Self> {
+        if per_sec == 0 {
+            return None;
+        }
+        let burst = f64::from(burst.max(1));
+        Some(Self {
+            rate: f64::from(per_sec),
+            burst,
+            tokens: burst,
+            last: now,
+        })
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.last = now;
+    }
+
+    fn has_token(&self) -> bool {
+        self.tokens >= 1.0
+    }
+}
+
+#[derive(Debug)]
+struct Storm {
+    since: Instant,
+    limited: u64,
+    images: HashMap<String, u64>,
+    rules: HashMap<String, u64>,
+}
+
+/// Global and per-rule token buckets over emitted alerts. Alerts held back are tallied
+/// into a storm, reported by [`AlertLimiter::take_storm`].
+#[derive(Debug)]
+pub struct AlertLimiter {
+    enabled: bool,
+    global: Option<TokenBucket>,
+    rule_rate: (u32, u32),
+    rules: HashMap<String, Option<TokenBucket>>,
+    summary_every: Duration,
+    storm: Option<Storm>,
+}
+
+impl AlertLimiter {
+    pub fn new(cfg: &RateLimitConfig) -> Self {
+        Self {
+            enabled: cfg.enabled,
+            global: TokenBucket::new(cfg.global_per_sec, cfg.global_burst, Instant::now()),
+            rule_rate: (cfg.rule_per_sec, cfg.rule_burst),
+            rules: HashMap::new(),
+            summary_every: Duration::from_millis(cfg.storm_summary_ms),
+            storm: None,
+        }
+    }
+
+    /// Apply new limits with full buckets; a storm in progress keeps its tally.
+    pub fn reconfigure(&mut self, cfg: &RateLimitConfig) {
+        let storm = self.storm.take();
+        *self = Self::new(cfg);
+        self.storm = storm;
+    }
+
+    /// Take a token from the global and the rule's bucket, or count the alert into the
+    /// storm when either is empty.
+    pub fn admit(&mut self, rule: &str, image: &str, now: Instant) -> bool {
+        if !self.enabled {
+            return true;
+        }
+        let (per_sec, burst) = self.rule_rate;
+        let rule_bucket = self
+            .rules
+            .entry(rule.to_string())
+            .or_insert_with(|| TokenBucket::new(per_sec, burst, now));
+
+        let mut admitted = true;
+        for bucket in [self.global.as_mut(), rule_bucket.as_mut()]
+            .into_iter()
+            .flatten()
+        {
+            bucket.refill(now);
+            admitted &= bucket.has_token();
+        }
+        if admitted {
+            for bucket in [self.global.as_mut(), rule_bucket.as_mut()]
+                .into_iter()
+                .flatten()
+            {
+                bucket.tokens -= 1.0;
+            }
+            return true;
+        }
+
+        let storm = self.storm.get_or_insert_with(|| Storm {
+            since: now,
+            limited: 0,
+            images: HashMap::new(),
+            rules: HashMap::new(),
+        });
+        storm.limited += 1;
+        *storm.rules.entry(rule.to_string()).or_default() += 1;
+        if let Some(count) = storm.images.get_mut(image) {
+            *count += 1;
+        } else if storm.images.len() < STORM_IMAGES_MAX {
+            storm.images.insert(image.to_string(), 1);
+        }
+        false
+    }
+
+    /// Summary of the storm once `rate_limit.storm_summary_ms` has passed since it
+    /// began, or straight away with `all`. The next held-back alert starts a new one.
+    pub fn take_storm(&mut self, now: Instant, all: bool) -> Option<StormSummary> {
+        let since = self.storm.as_ref()?.since;
+        let window = now.saturating_duration_since(since);
+        if !all && window < self.summary_every {
+            return None;
+        }
+        let storm = self.storm.take()?;
+        Some(StormSummary {
+            limited: storm.limited,
+            window_ms: window.as_millis() as u64,
+            top_images: top(storm.images),
+            top_rules: top(storm.rules),
+        })
+    }
+}
+
+fn top(counts: HashMap<String, u64>) -> Vec<StormCount> {
+    let mut counts: Vec<(String, u64)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| (Reverse(a.1), &a.0).cmp(&(Reverse(b.1), &b.0)));
+    counts
+        .into_iter()
+        .take(STORM_TOP)
+        .map(|(name, count)| StormCount { name, count })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter() -> AlertLimiter {
+        AlertLimiter::new(&RateLimitConfig {
+            enabled: true,
+            global_per_sec: 10,
+            global_burst: 5,
+            rule_per_sec: 2,
+            rule_burst: 3,
+            storm_summary_ms: 1000,
+        })
+    }
+
+    #[test]
+    fn buckets_hold_back_alerts_past_the_burst_and_refill() {
+        let mut l = limiter();
+        let t0 = Instant::now();
+        let admitted = (0..5).filter(|_| l.admit("A", "a.exe", t0)).count();
+        assert_eq!(admitted, 3, "rule burst");
+        assert!(l.admit("B", "b.exe", t0));
+        assert!(l.admit("B", "b.exe", t0));
+        assert!(!l.admit("C", "c.exe", t0), "global burst spent");
+
+        // Half a second refills one token per rule and five globally.
+        let t1 = t0 + Duration::from_millis(500);
+        assert!(l.admit("A", "a.exe", t1));
+        assert!(!l.admit("A", "a.exe", t1));
+    }
+
+    #[test]
+    fn storm_reports_top_images_and_rules_after_the_summary_interval() {
+        let mut l = limiter();
+        let t0 = Instant::now();
+        for _ in 0..10 {
+            l.admit("Chrome Passwords", "backup.exe", t0);
+        }
+        l.admit("Chrome Cookies", "other.exe", t0);
+        assert!(
+            l.take_storm(t0 + Duration::from_millis(500), false)
+                .is_none()
+        );
+
+        let storm = l
+            .take_storm(t0 + Duration::from_secs(1), false)
+            .expect("storm summary");
+        assert_eq!(storm.limited, 7);
+        assert_eq!(storm.window_ms, 1000);
+        assert_eq!(storm.top_images[0].name, "backup.exe");
+        assert_eq!(storm.top_images[0].count, 7);
+        assert_eq!(storm.top_rules.len(), 1);
+        assert!(l.take_storm(t0 + Duration::from_secs(5), true).is_none());
+    }
+
+    #[test]
+    fn disabled_limiter_admits_everything() {
+        let mut l = AlertLimiter::new(&RateLimitConfig {
+            enabled: false,
+            ..Default::default()
+        });
+        let t0 = Instant::now();
+        assert!((0..1000).all(|_| l.admit("A", "a.exe", t0)));
+        assert!(l.take_storm(t0, true).is_none());
+    }
+}
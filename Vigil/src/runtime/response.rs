@@ -0,0 +1,191 @@
+use crate::{runtime::rules::same_image, support::config::ResponseKind};
+use anyhow::{Context, Result};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Carries out a rule's `response` against the offending process.
+///
+/// The engine applies the safety rails and writes the audit record; implementations
+/// only perform the action and describe what they did.
+pub trait ResponseAction: Send + Sync + fmt::Debug {
+    fn execute(&self, action: ResponseKind, target: &ResponseTarget) -> Result<String>;
+}
+
+/// The process a response acts on, as the engine knew it when the alert fired.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseTarget {
+    pub pid: u32,
+    pub image: String,
+    /// Creation FILETIME, or when the pid was first seen if its start was missed.
+    pub start_time: u64,
+    pub start_known: bool,
+}
+
+impl ResponseTarget {
+    /// Whether a process created at `created` and running `image` is still this one
+    /// rather than a later process that reused the pid.
+    pub fn matches(&self, created: u64, image: &str) -> bool {
+        let same_start = if self.start_known {
+            created == self.start_time
+        } else {
+            created <= self.start_time
+        };
+        same_start && same_image(&self.image, image)
+    }
+}
+
+/// Live responder: suspends or terminates through the process APIs and moves images
+/// into the quarantine directory.
+#[derive(Debug)]
+pub struct PlatformResponder {
+    quarantine_dir: PathBuf,
+}
+
+impl PlatformResponder {
+    pub fn new(quarantine_dir: PathBuf) -> Self {
+        Self { quarantine_dir }
+    }
+}
+
+impl ResponseAction for PlatformResponder {
+    fn execute(&self, action: ResponseKind, target: &ResponseTarget) -> Result<String> {
+        match action {
+            ResponseKind::None => Ok("no action".to_string()),
+            ResponseKind::Suspend => suspend(target),
+            ResponseKind::Terminate => terminate(target),
+            ResponseKind::QuarantineImage => {
+                let image = quarantine_source(target)?;
+                let dest = quarantine_file(Path::new(&image), &self.quarantine_dir)?;
+                Ok(format!("moved {image} to {}", dest.display()))
+            }
+        }
+    }
+}
+
+/// Logs what would be done and touches nothing.
+#[derive(Debug, Default)]
+pub struct DryRunResponder;
+
+impl ResponseAction for DryRunResponder {
+    fn execute(&self, action: ResponseKind, target: &ResponseTarget) -> Result<String> {
+        let detail = format!(
+            "would {} pid {} ({})",
+            action.as_str(),
+            target.pid,
+            target.image
+        );
+        eprintln!("[RESPONSE] dry run: {detail}");
+        Ok(detail)
+    }
+}
+
+#[cfg(windows)]
+fn suspend(target: &ResponseTarget) -> Result<String> {
+    let threads = crate::trust::process::suspend_process(target)?;
+    Ok(format!("suspended {threads} threads"))
+}
+
+#[cfg(windows)]
+fn terminate(target: &ResponseTarget) -> Result<String> {
+    crate::trust::process::terminate_process(target)?;
+    Ok("terminated".to_string())
+}
+
+/// The image as a Win32 path, read from the process itself once it is confirmed to be
+/// the target; ETW reports `\Device\...` paths that the file APIs cannot open.
+#[cfg(windows)]
+fn quarantine_source(target: &ResponseTarget) -> Result<String> {
+    crate::trust::process::target_image_path(target)
+}
+
+#[cfg(not(windows))]
+fn quarantine_source(target: &ResponseTarget) -> Result<String> {
+    Ok(target.image.clone())
+}
+
+#[cfg(not(windows))]
+fn suspend(_target: &ResponseTarget) -> Result<String> {
+    anyhow::bail!("process suspension is only supported on Windows")
+}
+
+#[cfg(not(windows))]
+fn terminate(_target: &ResponseTarget) -> Result<String> {
+    anyhow::bail!("process termination is only supported on Windows")
+}
+
+/// Move `image` into `dir` as `<unix time>-<file name>.quarantine`. A running image can
+/// be renamed on its own volume but not copied off it, so there is no copy fallback.
+fn quarantine_file(image: &Path, dir: &Path) -> Result<PathBuf> {
+    let name = image
+        .file_name()
+        .with_context(|| format!("{} has no file name", image.display()))?;
+    fs::create_dir_all(dir)
+        .with_context(|| format!("failed to create quarantine dir {}", dir.display()))?;
+    let ts = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let dest = dir.join(format!("{ts}-{}.quarantine", name.to_string_lossy()));
+    fs::rename(image, &dest)
+        .with_context(|| format!("failed to move {} to {}", image.display(), dest.display()))?;
+    Ok(dest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quarantine_moves_the_image_out_of_place() {
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let root = std::env::temp_dir().join(format!("titan-vigil-response-tests-{ts}"));
+        fs::create_dir_all(&root).expect("create temp dir");
+        let image = root.join("stealer.exe");
+        fs::write(&image, b"MZ").expect("write image");
+
+        let dest = quarantine_file(&image, &root.join("quarantine")).expect("quarantine");
+
+        assert!(!image.exists());
+        let moved: Vec<_> = fs::read_dir(root.join("quarantine"))
+            .expect("quarantine dir")
+            .flatten()
+            .collect();
+        assert_eq!(moved.len(), 1);
+        assert!(
+            moved[0]
+                .file_name()
+                .to_string_lossy()
+                .ends_with("-stealer.exe.quarantine")
+        );
+        assert_eq!(moved[0].path(), dest);
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn targets_match_only_the_process_that_alerted() {
+        let target = ResponseTarget {
+            pid: 4321,
+            image: r"\Device\HarddiskVolume3\Temp\stealer.exe".to_string(),
+            start_time: 1_000,
+            start_known: true,
+        };
+        assert!(target.matches(1_000, r"C:\Temp\STEALER.EXE"));
+        assert!(!target.matches(1_001, r"C:\Temp\stealer.exe"), "pid reused");
+        assert!(!target.matches(1_000, r"C:\Windows\notepad.exe"));
+
+        // With a missed start the process must predate the first sighting of its pid.
+        let seen = ResponseTarget {
+            start_known: false,
+            ..target
+        };
+        assert!(seen.matches(900, r"C:\Temp\stealer.exe"));
+        assert!(!seen.matches(1_100, r"C:\Temp\stealer.exe"));
+    }
+}
@@ -0,0 +1,875 @@
+use crate::support::config::{MatchKind, ProtectedRule};
+use aho_corasick::AhoCorasick;
+use anyhow::{Context, Result};
+use regex::{Regex, RegexBuilder, RegexSet, RegexSetBuilder};
+use std::collections::HashSet;
+
+/// Matches a drive letter as well as the `\Device\HarddiskVolumeN` form Kernel-File reports.
+pub const VOLUME: &str = r"(?:[a-z]:|\\device\\[^\\]+)";
+
+/// Shortest required literal worth a prefilter entry.
+const MIN_PREFILTER_LITERAL: usize = 3;
+
+/// Compiled form of a [`ProtectedRule`] pattern.
+///
+/// Literal kinds expect the pattern already lowercased by `Config::load` and are
+/// matched against a lowercased path; regex and glob compile case-insensitive.
+#[derive(Debug, Clone)]
+pub enum PathMatcher {
+    Substring(String),
+    Exact(String),
+    Prefix(String),
+    Suffix(String),
+    /// A `prefix` or `exact` path below the volume root, after any volume prefix.
+    VolumePrefix(String),
+    VolumeExact(String),
+    Regex(Regex),
+}
+
+impl PathMatcher {
+    pub fn compile(rule: &ProtectedRule) -> Result<Self> {
+        let pattern = rule.pattern.clone();
+        Ok(match rule.match_kind {
+            MatchKind::Prefix if rule.any_volume => Self::VolumePrefix(pattern),
+            MatchKind::Exact if rule.any_volume => Self::VolumeExact(pattern),
+            MatchKind::Substring => Self::Substring(pattern),
+            MatchKind::Exact => Self::Exact(pattern),
+            MatchKind::Prefix => Self::Prefix(pattern),
+            MatchKind::Suffix => Self::Suffix(pattern),
+            MatchKind::Glob => Self::Regex(
+                build_regex(&glob_to_regex(&pattern))
+                    .with_context(|| format!("invalid glob '{pattern}'"))?,
+            ),
+            MatchKind::Regex => Self::Regex(
+                build_regex(&pattern).with_context(|| format!("invalid regex '{pattern}'"))?,
+            ),
+        })
+    }
+
+    #[inline]
+    pub fn is_match(&self, path_lower: &str) -> bool {
+        match self {
+            Self::Substring(needle) => path_lower.contains(needle.as_str()),
+            Self::Exact(value) => path_lower == value,
+            Self::Prefix(prefix) => path_lower.starts_with(prefix.as_str()),
+            Self::Suffix(suffix) => path_lower.ends_with(suffix.as_str()),
+            Self::VolumePrefix(prefix) => volume_len(path_lower)
+                .is_some_and(|len| path_lower[len..].starts_with(prefix.as_str())),
+            Self::VolumeExact(value) => {
+                volume_len(path_lower).is_some_and(|len| path_lower[len..] == *value)
+            }
+            Self::Regex(re) => re.is_match(path_lower),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Anchor {
+    Anywhere,
+    Start,
+    End,
+    Whole,
+    VolumeStart,
+    VolumeWhole,
+}
+
+impl Anchor {
+    fn for_rule(rule: &ProtectedRule) -> Self {
+        match (rule.match_kind, rule.any_volume) {
+            (MatchKind::Exact, true) => Self::VolumeWhole,
+            (MatchKind::Prefix, true) => Self::VolumeStart,
+            (MatchKind::Exact, false) => Self::Whole,
+            (MatchKind::Prefix, false) => Self::Start,
+            (MatchKind::Suffix, _) => Self::End,
+            _ => Self::Anywhere,
+        }
+    }
+
+    #[inline]
+    fn accepts(self, path: &str, start: usize, end: usize) -> bool {
+        match self {
+            Self::Anywhere => true,
+            Self::Start => start == 0,
+            Self::End => end == path.len(),
+            Self::Whole => start == 0 && end == path.len(),
+            Self::VolumeStart => volume_len(path) == Some(start),
+            Self::VolumeWhole => volume_len(path) == Some(start) && end == path.len(),
+        }
+    }
+}
+
+/// Length of the leading drive letter or `\Device\<volume>` component, as [`VOLUME`]
+/// matches it ahead of a path.
+pub fn volume_len(path: &str) -> Option<usize> {
+    const DEVICE: &[u8] = br"\device\";
+    let bytes = path.as_bytes();
+    if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+        return Some(2);
+    }
+    let rest = bytes.get(DEVICE.len()..)?;
+    if !bytes[..DEVICE.len()].eq_ignore_ascii_case(DEVICE) {
+        return None;
+    }
+    match rest.iter().position(|&b| b == b'\\') {
+        Some(0) => None,
+        Some(len) => Some(DEVICE.len() + len),
+        None => None,
+    }
+}
+
+/// Whether `a` and `b` name the same image past their drive letter or
+/// `\Device\<volume>`, ignoring case, since ETW and the process APIs name the same
+/// image in different forms.
+pub fn same_image(a: &str, b: &str) -> bool {
+    fn volume_relative(path: &str) -> &str {
+        volume_len(path).map_or(path, |len| &path[len..])
+    }
+    volume_relative(a).eq_ignore_ascii_case(volume_relative(b))
+}
+
+/// Regex and glob rules compiled into one [`RegexSet`]. With a prefilter, the set only
+/// runs on paths holding one of its rules' required literals; non-ASCII paths skip the
+/// prefilter, since case folding can turn them into ASCII.
+#[derive(Debug)]
+struct RegexGroup {
+    set: RegexSet,
+    rules: Vec<usize>,
+    prefilter: Option<AhoCorasick>,
+}
+
+impl RegexGroup {
+    fn new(regexes: &[(usize, Regex)], literals: Option<Vec<String>>) -> Result<Self> {
+        let set = RegexSetBuilder::new(regexes.iter().map(|(_, re)| re.as_str()))
+            .case_insensitive(true)
+            .build()
+            .context("failed to build protected rule regex set")?;
+        let prefilter = literals
+            .map(|literals| {
+                AhoCorasick::builder()
+                    .ascii_case_insensitive(true)
+                    .build(literals)
+                    .context("failed to build protected rule regex prefilter")
+            })
+            .transpose()?;
+        Ok(Self {
+            set,
+            rules: regexes.iter().map(|(idx, _)| *idx).collect(),
+            prefilter,
+        })
+    }
+
+    #[inline]
+    fn may_match(&self, path: &str) -> bool {
+        self.prefilter
+            .as_ref()
+            .is_none_or(|ac| !path.is_ascii() || ac.is_match(path))
+    }
+
+    fn is_match(&self, path: &str) -> bool {
+        self.may_match(path) && self.set.is_match(path)
+    }
+
+    /// Matching rule indices in ascending order.
+    fn matches(&self, path: &str) -> impl Iterator<Item = usize> + '_ {
+        let hits = if self.may_match(path) {
+            Some(self.set.matches(path))
+        } else {
+            None
+        };
+        hits.into_iter()
+            .flat_map(|hits| hits.into_iter())
+            .map(|slot| self.rules[slot])
+    }
+}
+
+/// All protected rules compiled for the ETW hot path.
+///
+/// ASCII literal patterns share one ASCII-case-insensitive Aho-Corasick automaton, so
+/// a path is scanned once without being lowercased. Regex/glob rules run after it as
+/// regex sets, behind a prefilter on their required literals where they have one, and
+/// the rare non-ASCII literal falls back to Unicode lowercasing. The lowest rule index
+/// wins, so callers order rules by precedence.
+#[derive(Debug, Default)]
+pub struct RuleSet {
+    automaton: Option<AhoCorasick>,
+    literals: Vec<(usize, Anchor)>,
+    regexes: Vec<RegexGroup>,
+    folded: Vec<(usize, PathMatcher)>,
+}
+
+impl RuleSet {
+    pub fn new<'a>(rules: impl IntoIterator<Item = &'a ProtectedRule>) -> Result<Self> {
+        let mut patterns = Vec::new();
+        let mut literals = Vec::new();
+        let mut filtered = Vec::new();
+        let mut required = Vec::new();
+        let mut unfiltered = Vec::new();
+        let mut folded = Vec::new();
+
+        for (idx, rule) in rules.into_iter().enumerate() {
+            let matcher = PathMatcher::compile(rule)
+                .with_context(|| format!("invalid pattern for protected rule '{}'", rule.name))?;
+            match matcher {
+                PathMatcher::Regex(re) => match required_literal(re.as_str()) {
+                    Some(literal) => {
+                        required.push(literal);
+                        filtered.push((idx, re));
+                    }
+                    None => unfiltered.push((idx, re)),
+                },
+                _ if rule.pattern.is_ascii() => {
+                    patterns.push(rule.pattern.clone());
+                    literals.push((idx, Anchor::for_rule(rule)));
+                }
+                other => folded.push((idx, other)),
+            }
+        }
+
+        let automaton = if patterns.is_empty() {
+            None
+        } else {
+            Some(
+                AhoCorasick::builder()
+                    .ascii_case_insensitive(true)
+                    .build(&patterns)
+                    .context("failed to build protected rule automaton")?,
+            )
+        };
+
+        let mut regexes = Vec::new();
+        if !filtered.is_empty() {
+            regexes.push(RegexGroup::new(&filtered, Some(required))?);
+        }
+        if !unfiltered.is_empty() {
+            regexes.push(RegexGroup::new(&unfiltered, None)?);
+        }
+
+        Ok(Self {
+            automaton,
+            literals,
+            regexes,
+            folded,
+        })
+    }
+
+    /// Index of the highest-precedence rule matching `path`, if any.
+    pub fn find(&self, path: &str) -> Option<usize> {
+        let mut best: Option<usize> = None;
+
+        if let Some(ac) = &self.automaton {
+            for m in ac.find_overlapping_iter(path) {
+                let (idx, anchor) = self.literals[m.pattern().as_usize()];
+                if best.is_some_and(|b| b <= idx) {
+                    continue;
+                }
+                if anchor.accepts(path, m.start(), m.end()) {
+                    best = Some(idx);
+                }
+            }
+        }
+
+        for group in &self.regexes {
+            if let Some(idx) = group.matches(path).next()
+                && best.is_none_or(|b| idx < b)
+            {
+                best = Some(idx);
+            }
+        }
+
+        if let Some((first, _)) = self.folded.first()
+            && best.is_none_or(|b| b > *first)
+        {
+            let lower = path.to_lowercase();
+            for (idx, matcher) in &self.folded {
+                if best.is_some_and(|b| b <= *idx) {
+                    break;
+                }
+                if matcher.is_match(&lower) {
+                    best = Some(*idx);
+                    break;
+                }
+            }
+        }
+
+        best
+    }
+
+    /// Indices of every rule matching `path`, in precedence order.
+    pub fn find_all(&self, path: &str) -> Vec<usize> {
+        let mut hits = Vec::new();
+
+        if let Some(ac) = &self.automaton {
+            for m in ac.find_overlapping_iter(path) {
+                let (idx, anchor) = self.literals[m.pattern().as_usize()];
+                if anchor.accepts(path, m.start(), m.end()) {
+                    hits.push(idx);
+                }
+            }
+        }
+
+        for group in &self.regexes {
+            hits.extend(group.matches(path));
+        }
+
+        if !self.folded.is_empty() {
+            let lower = path.to_lowercase();
+            for (idx, matcher) in &self.folded {
+                if matcher.is_match(&lower) {
+                    hits.push(*idx);
+                }
+            }
+        }
+
+        hits.sort_unstable();
+        hits.dedup();
+        hits
+    }
+
+    /// Whether any rule matches `path`, without the precedence work of [`RuleSet::find`].
+    /// A path no literal can hit is rejected by the automaton in one pass.
+    pub fn is_match(&self, path: &str) -> bool {
+        if let Some(ac) = &self.automaton
+            && ac.is_match(path)
+            && ac.find_overlapping_iter(path).any(|m| {
+                let (_, anchor) = self.literals[m.pattern().as_usize()];
+                anchor.accepts(path, m.start(), m.end())
+            })
+        {
+            return true;
+        }
+        if self.regexes.iter().any(|group| group.is_match(path)) {
+            return true;
+        }
+        if self.folded.is_empty() {
+            return false;
+        }
+        let lower = path.to_lowercase();
+        self.folded
+            .iter()
+            .any(|(_, matcher)| matcher.is_match(&lower))
+    }
+}
+
+/// How narrowly `rule` pins a path, for `match_mode = "most_specific"`: exact paths
+/// first, then by the length of the pattern's literal text.
+pub fn specificity(rule: &ProtectedRule) -> usize {
+    match rule.match_kind {
+        MatchKind::Exact => usize::MAX,
+        MatchKind::Glob => rule
+            .pattern
+            .chars()
+            .filter(|c| !matches!(c, '*' | '?'))
+            .count(),
+        // Templated rules arrive here too, so syntax such as the volume group and
+        // `[^\\]+` must not count.
+        MatchKind::Regex => regex_literal_len(&rule.pattern).unwrap_or(0),
+        _ => rule.pattern.chars().count(),
+    }
+}
+
+/// Literal characters every match of `pattern` must contain. Groups, classes and
+/// optional atoms count for nothing; a top-level alternation counts its shortest
+/// branch.
+fn regex_literal_len(pattern: &str) -> Option<usize> {
+    let mut shortest = usize::MAX;
+    let mut count = 0usize;
+    let mut last_literal = false;
+    let mut chars = pattern.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let literal = match c {
+            '\\' => {
+                let escaped = chars.next()?;
+                escaped.is_ascii_punctuation() || {
+                    skip_escape(escaped, &mut chars)?;
+                    false
+                }
+            }
+            '(' => {
+                skip_group(&mut chars)?;
+                false
+            }
+            '[' => {
+                skip_class(&mut chars)?;
+                false
+            }
+            '*' | '?' | '{' => {
+                if last_literal {
+                    count -= 1;
+                }
+                if c == '{' {
+                    chars.find(|&c| c == '}')?;
+                }
+                false
+            }
+            '|' => {
+                shortest = shortest.min(count);
+                count = 0;
+                false
+            }
+            '+' | '.' | '^' | '$' | ')' => false,
+            _ => true,
+        };
+        count += usize::from(literal);
+        last_literal = literal;
+    }
+    Some(shortest.min(count))
+}
+
+/// Per-rule accessor restriction built from the `authorized_*` lists.
+#[derive(Debug, Clone)]
+pub struct AccessPolicy {
+    images: Vec<String>,
+    signers: Vec<String>,
+    hashes: HashSet<String>,
+}
+
+impl AccessPolicy {
+    /// `None` when the rule leaves access to global trust.
+    pub fn from_rule(rule: &ProtectedRule) -> Option<Self> {
+        rule.restricts_accessors().then(|| Self {
+            images: rule.authorized_images.clone(),
+            signers: rule.authorized_signers.clone(),
+            hashes: rule.authorized_hashes.iter().cloned().collect(),
+        })
+    }
+
+    pub fn wants_hash(&self) -> bool {
+        !self.hashes.is_empty()
+    }
+
+    /// Entries containing a separator match the image path after its volume, so drive
+    /// letters and `\Device\HarddiskVolumeN` agree; others match the file name.
+    /// `signer` is the lowercased subject of a verified signature. A file name proves
+    /// nothing, so a rule listing only images also needs the process to be `trusted`.
+    pub fn permits(
+        &self,
+        image: &str,
+        trusted: bool,
+        signer: Option<&str>,
+        sha256: Option<&str>,
+    ) -> bool {
+        let image = image.to_lowercase();
+        let file_name = image.rsplit(['\\', '/']).next().unwrap_or(&image);
+        let after_volume = |path: &str| path[volume_len(path).unwrap_or(0)..].to_string();
+
+        let image_ok = self.images.is_empty()
+            || self.images.iter().any(|allowed| {
+                if allowed.contains(['\\', '/']) {
+                    after_volume(allowed) == after_volume(&image)
+                } else {
+                    allowed == file_name
+                }
+            });
+        let signer_ok = self.signers.is_empty()
+            || signer.is_some_and(|s| {
+                self.signers
+                    .iter()
+                    .any(|allowed| s.contains(allowed.as_str()))
+            });
+        let hash_ok = self.hashes.is_empty() || sha256.is_some_and(|h| self.hashes.contains(h));
+
+        let proven = trusted || !self.signers.is_empty() || !self.hashes.is_empty();
+
+        image_ok && signer_ok && hash_ok && proven
+    }
+}
+
+/// The longest ASCII literal run every match of `pattern` must contain, if one is long
+/// enough to prefilter on. Conservative: alternation, verbose mode or anything it does
+/// not recognise leaves the regex without a prefilter.
+fn required_literal(pattern: &str) -> Option<String> {
+    let mut best = String::new();
+    let mut run = String::new();
+    let mut chars = pattern.chars().peekable();
+
+    fn close(run: &mut String, best: &mut String) {
+        if run.len() > best.len() {
+            *best = std::mem::take(run);
+        }
+        run.clear();
+    }
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                let escaped = chars.next()?;
+                if escaped.is_ascii_punctuation() {
+                    run.push(escaped);
+                    continue;
+                }
+                close(&mut run, &mut best);
+                skip_escape(escaped, &mut chars)?;
+            }
+            '(' => {
+                if chars.peek() == Some(&'?') {
+                    let flags: String = chars
+                        .clone()
+                        .take_while(|&c| c != ')' && c != ':')
+                        .collect();
+                    if flags.contains('x') {
+                        return None;
+                    }
+                }
+                close(&mut run, &mut best);
+                skip_group(&mut chars)?;
+            }
+            '[' => {
+                close(&mut run, &mut best);
+                skip_class(&mut chars)?;
+            }
+            '*' | '?' | '+' | '{' => {
+                // The quantifier makes the atom before it optional or repeated.
+                run.pop();
+                close(&mut run, &mut best);
+                if c == '{' {
+                    chars.find(|&c| c == '}')?;
+                }
+            }
+            '|' | ')' => return None,
+            '.' | '^' | '$' => close(&mut run, &mut best),
+            c if c.is_ascii() => run.push(c),
+            _ => close(&mut run, &mut best),
+        }
+    }
+    close(&mut run, &mut best);
+    (best.len() >= MIN_PREFILTER_LITERAL).then_some(best)
+}
+
+/// Consume the payload of a `\x41`, `\u{..}` or `\pL` style escape after its letter.
+fn skip_escape(escaped: char, chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> Option<()> {
+    match escaped {
+        'x' | 'u' | 'U' | 'p' | 'P' if chars.peek() == Some(&'{') => {
+            chars.find(|&c| c == '}')?;
+        }
+        'x' => {
+            chars.nth(1)?;
+        }
+        'u' => {
+            chars.nth(3)?;
+        }
+        'U' => {
+            chars.nth(7)?;
+        }
+        'p' | 'P' => {
+            chars.next()?;
+        }
+        _ => {}
+    }
+    Some(())
+}
+
+/// Consume a group body after its `(`, including nested groups and classes.
+fn skip_group(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> Option<()> {
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next()?;
+            }
+            '[' => skip_class(chars)?,
+            '(' => skip_group(chars)?,
+            ')' => return Some(()),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Consume a character class after its `[`, including nested classes.
+fn skip_class(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> Option<()> {
+    if chars.peek() == Some(&'^') {
+        chars.next();
+    }
+    if chars.peek() == Some(&']') {
+        chars.next();
+    }
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next()?;
+            }
+            '[' => {
+                if chars.peek() == Some(&':') {
+                    chars.find(|&c| c == ']')?;
+                } else {
+                    skip_class(chars)?;
+                }
+            }
+            ']' => return Some(()),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn build_regex(pattern: &str) -> Result<Regex> {
+    Ok(RegexBuilder::new(pattern).case_insensitive(true).build()?)
+}
+
+/// Translate a path glob into an anchored regex.
+///
+/// `**` spans directories, `*` and `?` stay within one path component, and `/` is
+/// accepted as a separator alias for `\`.
+pub fn glob_to_regex(glob: &str) -> String {
+    format!("^{}$", glob_body(glob))
+}
+
+/// Unanchored regex for a glob fragment, for callers that splice globs together.
+pub fn glob_body(glob: &str) -> String {
+    let mut out = String::with_capacity(glob.len() * 2);
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                out.push_str(".*");
+            }
+            '*' => out.push_str(r"[^\\]*"),
+            '?' => out.push_str(r"[^\\]"),
+            '\\' | '/' => out.push_str(r"\\"),
+            _ => out.push_str(&regex::escape(c.encode_utf8(&mut [0u8; 4]))),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(pattern: &str, match_kind: MatchKind) -> PathMatcher {
+        PathMatcher::compile(&ProtectedRule {
+            pattern: pattern.to_string(),
+            name: "test".to_string(),
+            match_kind,
+            ..Default::default()
+        })
+        .expect("pattern should compile")
+    }
+
+    #[test]
+    fn literal_kinds_match_expected_positions() {
+        let path = r"c:\users\bob\vault\db.kdbx";
+        assert!(matcher(r"\vault\", MatchKind::Substring).is_match(path));
+        assert!(matcher(path, MatchKind::Exact).is_match(path));
+        assert!(!matcher(r"c:\users", MatchKind::Exact).is_match(path));
+        assert!(matcher(r"c:\users\", MatchKind::Prefix).is_match(path));
+        assert!(!matcher(r"\users\", MatchKind::Prefix).is_match(path));
+        assert!(matcher(".kdbx", MatchKind::Suffix).is_match(path));
+    }
+
+    #[test]
+    fn glob_spans_profiles_but_not_components() {
+        let m = matcher(r"**\user data\*\login data", MatchKind::Glob);
+        assert!(
+            m.is_match(r"c:\users\bob\appdata\local\google\chrome\user data\default\login data")
+        );
+        assert!(
+            m.is_match(r"c:\users\bob\appdata\local\microsoft\edge\user data\profile 2\login data")
+        );
+        assert!(!m.is_match(r"c:\x\user data\default\nested\login data"));
+        assert!(!m.is_match(r"c:\x\user data\default\login data-journal"));
+
+        let kdbx = matcher("**.kdbx", MatchKind::Glob);
+        assert!(kdbx.is_match(r"d:\vault\passwords.kdbx"));
+        assert!(!kdbx.is_match(r"d:\vault\passwords.kdbx.bak"));
+    }
+
+    #[test]
+    fn regex_is_case_insensitive() {
+        let m = matcher(
+            r"\\discord\\local storage\\leveldb\\.+\.LDB$",
+            MatchKind::Regex,
+        );
+        assert!(
+            m.is_match(r"c:\users\bob\appdata\roaming\discord\local storage\leveldb\000005.ldb")
+        );
+    }
+
+    fn rule(pattern: &str, match_kind: MatchKind) -> ProtectedRule {
+        ProtectedRule {
+            pattern: pattern.to_string(),
+            name: pattern.to_string(),
+            match_kind,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn rule_set_honors_anchors_without_lowercasing() {
+        let rules = [
+            rule(r"c:\vault\db.kdbx", MatchKind::Exact),
+            rule(r"c:\users\", MatchKind::Prefix),
+            rule(".ldb", MatchKind::Suffix),
+            rule(r"\cookies", MatchKind::Substring),
+        ];
+        let set = RuleSet::new(&rules).expect("rule set");
+
+        assert_eq!(set.find(r"C:\Vault\DB.KDBX"), Some(0));
+        assert_eq!(set.find(r"D:\Backup\C:\vault\db.kdbx"), None);
+        assert_eq!(set.find(r"C:\Users\bob\notes.txt"), Some(1));
+        assert_eq!(set.find(r"D:\leveldb\000005.LDB"), Some(2));
+        assert_eq!(set.find(r"D:\x.ldb.tmp"), None);
+        assert_eq!(set.find(r"D:\Profile\Network\Cookies"), Some(3));
+        assert!(!set.is_match(r"D:\Windows\notepad.exe"));
+        assert!(!set.is_match(r"D:\x.ldb.tmp"), "anchor rejects a raw hit");
+        assert!(set.is_match(r"D:\leveldb\000005.LDB"));
+    }
+
+    #[test]
+    fn rule_set_prefers_lowest_index_across_kinds() {
+        let rules = [
+            rule(r"**\user data\**", MatchKind::Glob),
+            rule(r"\login data", MatchKind::Substring),
+        ];
+        let set = RuleSet::new(&rules).expect("rule set");
+        assert_eq!(set.find(r"C:\x\User Data\Default\Login Data"), Some(0));
+        assert_eq!(set.find(r"C:\x\Login Data"), Some(1));
+
+        let reversed = [rules[1].clone(), rules[0].clone()];
+        let set = RuleSet::new(&reversed).expect("rule set");
+        assert_eq!(set.find(r"C:\x\User Data\Default\Login Data"), Some(0));
+    }
+
+    #[test]
+    fn rule_set_finds_every_overlapping_rule() {
+        let rules = [
+            rule(r"**\user data\**", MatchKind::Glob),
+            rule(r"\login data", MatchKind::Substring),
+            rule(r"\jörg\", MatchKind::Substring),
+            rule(r"\user data\", MatchKind::Substring),
+        ];
+        let set = RuleSet::new(&rules).expect("rule set");
+        assert_eq!(
+            set.find_all(r"C:\Users\Jörg\User Data\Default\Login Data"),
+            vec![0, 1, 2, 3]
+        );
+        assert_eq!(set.find_all(r"C:\x\Login Data"), vec![1]);
+        assert!(set.find_all(r"C:\x\notes.txt").is_empty());
+
+        assert_eq!(specificity(&rules[0]), r"\user data\".len());
+        assert_eq!(specificity(&rule("a", MatchKind::Exact)), usize::MAX);
+        assert_eq!(
+            specificity(&rule(
+                &format!(r"^{VOLUME}\\users\\[^\\]+\\vault\.kdbx$"),
+                MatchKind::Regex
+            )),
+            r"\users\\vault.kdbx".len()
+        );
+        assert_eq!(
+            specificity(&rule(r"\\cookies?\\(a|b)|\\x\d{2}", MatchKind::Regex)),
+            r"\x".len()
+        );
+    }
+
+    #[test]
+    fn required_literals_are_conservative() {
+        assert_eq!(
+            required_literal(&glob_to_regex(r"**\user data\*\login data")).as_deref(),
+            Some(r"\user data\")
+        );
+        assert_eq!(
+            required_literal(r"\\discord\\local storage\\leveldb\\.+\.LDB$").as_deref(),
+            Some(r"\discord\local storage\leveldb\")
+        );
+        assert_eq!(
+            required_literal(r"\\cookies?\\x").as_deref(),
+            Some(r"\cookie")
+        );
+        assert_eq!(required_literal(r"abcd|efgh"), None);
+        assert_eq!(required_literal(r"(?x) a b c d"), None);
+        assert_eq!(required_literal(r"\x41\x42"), None);
+        assert_eq!(required_literal(r"[abcd]+\d{2,4}"), None);
+    }
+
+    #[test]
+    fn regex_set_prefilter_agrees_with_the_regexes() {
+        let rules = [
+            rule(r"**\user data\*\login data", MatchKind::Glob),
+            rule(r"\\(firefox|thunderbird)\\profiles\\", MatchKind::Regex),
+            rule(r"^[a-z]:\\[^\\]+\.kdbx$", MatchKind::Regex),
+        ];
+        let set = RuleSet::new(&rules).expect("rule set");
+        assert_eq!(set.find(r"C:\x\User Data\Default\Login Data"), Some(0));
+        assert_eq!(set.find(r"C:\x\Thunderbird\Profiles\a"), Some(1));
+        assert_eq!(set.find(r"C:\Vault.KDBX"), Some(2));
+        assert_eq!(set.find(r"C:\x\ÜSER DATA\Default\Login Data"), None);
+        assert_eq!(set.find(r"C:\x\notes.txt"), None);
+        assert!(!set.is_match(r"C:\x\User Data\Login Data"));
+    }
+
+    #[test]
+    fn volume_anchors_accept_drive_letters_and_device_paths() {
+        let rules = [ProtectedRule {
+            any_volume: true,
+            ..rule(r"\users\alice\vault", MatchKind::Prefix)
+        }];
+        let set = RuleSet::new(&rules).expect("rule set");
+        assert_eq!(set.find(r"C:\Users\Alice\Vault\db"), Some(0));
+        assert_eq!(
+            set.find(r"\Device\HarddiskVolume2\Users\alice\vault"),
+            Some(0)
+        );
+        assert_eq!(set.find(r"C:\old\Users\alice\vault"), None);
+        assert_eq!(set.find(r"\Users\alice\vault"), None);
+
+        let m = PathMatcher::compile(&ProtectedRule {
+            any_volume: true,
+            ..rule(r"\users\alice\vault", MatchKind::Exact)
+        })
+        .expect("compile");
+        assert!(m.is_match(r"\device\harddiskvolume2\users\alice\vault"));
+        assert!(!m.is_match(r"c:\users\alice\vault\db"));
+    }
+
+    #[test]
+    fn rule_set_folds_non_ascii_patterns() {
+        let rules = [rule(r"\jörg\secrets", MatchKind::Substring)];
+        let set = RuleSet::new(&rules).expect("rule set");
+        assert_eq!(set.find(r"C:\Users\JÖRG\Secrets\a.txt"), Some(0));
+        assert!(set.is_match(r"C:\Users\JÖRG\Secrets\a.txt"));
+    }
+
+    #[test]
+    fn access_policy_requires_every_listed_criterion() {
+        let policy = AccessPolicy::from_rule(&ProtectedRule {
+            pattern: r"\mozilla\firefox\profiles".to_string(),
+            name: "Firefox".to_string(),
+            authorized_images: vec!["firefox.exe".to_string()],
+            authorized_signers: vec!["mozilla corporation".to_string()],
+            ..Default::default()
+        })
+        .expect("restricted rule");
+        let firefox = r"C:\Program Files\Mozilla Firefox\Firefox.exe";
+        let signer = Some("cn=mozilla corporation, o=mozilla corporation");
+
+        assert!(policy.permits(firefox, false, signer, None));
+        assert!(!policy.permits(firefox, true, None, None));
+        assert!(!policy.permits(r"C:\Windows\System32\rundll32.exe", true, signer, None));
+        assert!(!policy.permits(r"C:\Temp\firefox.exe.bak", true, signer, None));
+        assert!(AccessPolicy::from_rule(&ProtectedRule::default()).is_none());
+
+        // Images alone narrow global trust, and full paths match any volume spelling.
+        let images_only = AccessPolicy::from_rule(&ProtectedRule {
+            authorized_images: vec![r"c:\program files\mozilla firefox\firefox.exe".to_string()],
+            ..Default::default()
+        })
+        .expect("restricted rule");
+        let nt_path = r"\Device\HarddiskVolume3\Program Files\Mozilla Firefox\firefox.exe";
+        assert!(images_only.permits(nt_path, true, None, None));
+        assert!(images_only.permits(firefox, true, None, None));
+        assert!(!images_only.permits(nt_path, false, None, None));
+    }
+
+    #[test]
+    fn invalid_regex_is_rejected() {
+        let err = PathMatcher::compile(&ProtectedRule {
+            pattern: "(unclosed".to_string(),
+            name: "bad".to_string(),
+            match_kind: MatchKind::Regex,
+            ..Default::default()
+        })
+        .expect_err("regex should not compile");
+        assert!(format!("{err:#}").contains("invalid regex"));
+    }
+}
@@ -0,0 +1,71 @@
+use parking_lot::{Mutex, MutexGuard};
+use std::{
+    collections::HashMap,
+    hash::{BuildHasher, Hash, RandomState},
+};
+
+/// A hash map split into independently locked shards.
+///
+/// Events for unrelated keys take different locks, and housekeeping such as a
+/// `retain` sweep only ever blocks one shard.
+#[derive(Debug)]
+pub struct Sharded<K, V> {
+    shards: Box<[Mutex<HashMap<K, V>>]>,
+    hasher: RandomState,
+}
+
+impl<K: Hash + Eq, V> Sharded<K, V> {
+    /// `shards` is rounded up to a power of two.
+    pub fn new(shards: usize) -> Self {
+        let count = shards.max(1).next_power_of_two();
+        Self {
+            shards: (0..count).map(|_| Mutex::new(HashMap::new())).collect(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    /// Lock the shard that owns `key`.
+    #[inline]
+    pub fn shard(&self, key: &K) -> MutexGuard<'_, HashMap<K, V>> {
+        let idx = self.hasher.hash_one(key) as usize & (self.shards.len() - 1);
+        self.shards[idx].lock()
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Entries across all shards; each shard is locked in turn, so this is a snapshot.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|s| s.lock().len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// `HashMap::retain` over every shard, locking one at a time.
+    pub fn retain(&self, mut keep: impl FnMut(&K, &mut V) -> bool) {
+        for shard in &self.shards {
+            shard.lock().retain(|k, v| keep(k, v));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_land_in_a_stable_shard() {
+        let map: Sharded<u64, u64> = Sharded::new(5);
+        assert_eq!(map.shard_count(), 8);
+        for k in 0..1000 {
+            map.shard(&k).insert(k, k * 2);
+        }
+        assert_eq!(map.len(), 1000);
+        for k in 0..1000 {
+            assert_eq!(map.shard(&k).get(&k), Some(&(k * 2)));
+        }
+    }
+}
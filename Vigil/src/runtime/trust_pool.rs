@@ -0,0 +1,207 @@
+use crate::{runtime::engine::RuleInfo, telemetry::event::FileOperation};
+use crossbeam_channel::Sender;
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+const PENDING_MAX: usize = 10_000;
+
+/// A protected-path hit, resolved to its rule and process image.
+#[derive(Debug, Clone)]
+pub struct AccessEvent {
+    pub pid: u32,
+    pub event_id: u16,
+    pub operation: FileOperation,
+    pub target: String,
+    pub file_object: u64,
+    pub process: String,
+    pub rule: Arc<RuleInfo>,
+    /// Every matching rule name under `watch.match_mode = "all"`, else empty.
+    pub matched_rules: Vec<String>,
+}
+
+/// Verify the image of `pid`, then release its parked accesses.
+#[derive(Debug)]
+pub struct TrustJob {
+    pub pid: u32,
+    pub process: String,
+}
+
+/// Outcome of [`TrustPool::park`].
+#[derive(Debug)]
+pub enum Parked<V> {
+    /// Held until the worker's verdict or the deadline.
+    Queued,
+    /// A verdict was already cached; evaluate now.
+    Ready(AccessEvent, V),
+    /// The pool is saturated; evaluate now without a verdict.
+    Full(AccessEvent),
+}
+
+#[derive(Debug)]
+struct PendingProc {
+    since: Instant,
+    accesses: Vec<AccessEvent>,
+    /// Released at the deadline; kept so the late verdict can still correlate them.
+    late: Vec<AccessEvent>,
+}
+
+#[derive(Debug, Default)]
+struct Pending {
+    procs: HashMap<u32, PendingProc>,
+    len: usize,
+}
+
+/// Accesses parked per pid while a worker verifies the process.
+#[derive(Debug)]
+pub struct TrustPool {
+    jobs: Sender<TrustJob>,
+    deadline: Duration,
+    pending: Mutex<Pending>,
+}
+
+impl TrustPool {
+    pub fn new(jobs: Sender<TrustJob>, deadline: Duration) -> Self {
+        Self {
+            jobs,
+            deadline,
+            pending: Mutex::new(Pending::default()),
+        }
+    }
+
+    pub fn deadline(&self) -> Duration {
+        self.deadline
+    }
+
+    /// Park `access` until its process is verified. `cached` is checked under the
+    /// pending lock, so a verdict stored before the worker drains its pid is never
+    /// missed and a pid gets at most one queued job.
+    pub fn park<V>(&self, access: AccessEvent, cached: impl FnOnce() -> Option<V>) -> Parked<V> {
+        let mut pending = self.pending.lock();
+        if let Some(verdict) = cached() {
+            return Parked::Ready(access, verdict);
+        }
+        if pending.len >= PENDING_MAX {
+            return Parked::Full(access);
+        }
+        if let Some(proc) = pending.procs.get_mut(&access.pid) {
+            proc.accesses.push(access);
+            pending.len += 1;
+            return Parked::Queued;
+        }
+
+        let job = TrustJob {
+            pid: access.pid,
+            process: access.process.clone(),
+        };
+        if self.jobs.try_send(job).is_err() {
+            return Parked::Full(access);
+        }
+        pending.procs.insert(
+            access.pid,
+            PendingProc {
+                since: Instant::now(),
+                accesses: vec![access],
+                late: Vec::new(),
+            },
+        );
+        pending.len += 1;
+        Parked::Queued
+    }
+
+    /// Accesses still parked for `pid` and those already released at the deadline,
+    /// each in arrival order.
+    pub fn take(&self, pid: u32) -> (Vec<AccessEvent>, Vec<AccessEvent>) {
+        let mut pending = self.pending.lock();
+        let Some(proc) = pending.procs.remove(&pid) else {
+            return (Vec::new(), Vec::new());
+        };
+        pending.len -= proc.accesses.len() + proc.late.len();
+        (proc.accesses, proc.late)
+    }
+
+    /// Accesses whose process has waited longer than the deadline. The pid stays
+    /// pending, with these kept as late, until its worker calls [`TrustPool::take`].
+    pub fn expired(&self, now: Instant) -> Vec<AccessEvent> {
+        let mut pending = self.pending.lock();
+        let mut out = Vec::new();
+        for proc in pending.procs.values_mut() {
+            if now.duration_since(proc.since) <= self.deadline || proc.accesses.is_empty() {
+                continue;
+            }
+            let released = std::mem::take(&mut proc.accesses);
+            proc.late.extend(released.iter().cloned());
+            out.extend(released);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::support::config::ProtectedRule;
+    use crossbeam_channel::bounded;
+
+    fn access(pid: u32) -> AccessEvent {
+        AccessEvent {
+            pid,
+            event_id: 12,
+            operation: FileOperation::Read,
+            target: "t".to_string(),
+            file_object: 0,
+            process: "p.exe".to_string(),
+            rule: Arc::new(
+                RuleInfo::new(&ProtectedRule {
+                    pattern: "t".to_string(),
+                    name: "T".to_string(),
+                    ..Default::default()
+                })
+                .expect("rule"),
+            ),
+            matched_rules: Vec::new(),
+        }
+    }
+
+    fn park(pool: &TrustPool, pid: u32) -> Parked<()> {
+        pool.park(access(pid), || None)
+    }
+
+    #[test]
+    fn one_job_per_pid_and_deadline_releases_accesses() {
+        let (tx, rx) = bounded(1);
+        let pool = TrustPool::new(tx, Duration::from_millis(10));
+        assert!(matches!(park(&pool, 7), Parked::Queued));
+        assert!(matches!(park(&pool, 7), Parked::Queued), "same job");
+        assert_eq!(rx.try_iter().count(), 1);
+
+        assert!(
+            matches!(park(&pool, 8), Parked::Queued),
+            "queue has room again"
+        );
+        // The job queue is full, so a third pid is handed back.
+        assert!(matches!(park(&pool, 9), Parked::Full(_)));
+
+        let later = Instant::now() + Duration::from_millis(50);
+        assert_eq!(pool.expired(later).len(), 3);
+        assert!(pool.expired(later).is_empty(), "released once");
+        let (parked, late) = pool.take(7);
+        assert!(parked.is_empty());
+        assert_eq!(late.len(), 2);
+    }
+
+    #[test]
+    fn a_cached_verdict_skips_the_job_queue() {
+        let (tx, rx) = bounded(4);
+        let pool = TrustPool::new(tx, Duration::from_millis(10));
+        assert!(matches!(
+            pool.park(access(7), || Some(true)),
+            Parked::Ready(_, true)
+        ));
+        assert_eq!(rx.try_iter().count(), 0);
+        assert_eq!(pool.take(7).0.len(), 0);
+    }
+}
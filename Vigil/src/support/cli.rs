@@ -1,46 +1,202 @@
+use anyhow::{Context, Result, bail};
 use std::path::PathBuf;
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Live ETW monitoring (default).
+    Run,
+    /// Push a recorded journal through the engine and configured sinks.
+    Replay { journal: PathBuf },
+    /// Ask a running sensor to reload its config.
+    Reload,
+    /// Turn a learn-mode baseline into proposed accessor config.
+    BaselineSuggest {
+        baseline: Option<PathBuf>,
+        min_hits: u64,
+    },
+    /// Print the JSON Schema of every alert output format.
+    SchemaAlerts,
+}
+
 #[derive(Debug, Clone)]
 pub struct Cli {
+    pub command: Command,
     pub config: PathBuf,
     pub config_explicit: bool,
     pub verbose: bool,
+    pub record: Option<PathBuf>,
 }
 
 impl Cli {
-    pub fn parse() -> Self {
+    pub fn parse() -> Result<Self> {
+        Self::parse_from(std::env::args().skip(1).collect())
+    }
+
+    /// Flags may come before or after the subcommand; the non-flag arguments name it.
+    /// Without any, the live sensor runs; anything else that is not a whole subcommand,
+    /// an unknown flag or a flag missing its value is an error.
+    pub fn parse_from(args: Vec<String>) -> Result<Self> {
+        let mut positional: Vec<&str> = Vec::new();
         let mut config = PathBuf::from("config.toml");
         let mut config_explicit = false;
         let mut verbose = false;
+        let mut record = None;
+        let mut baseline = None;
+        let mut min_hits = 1;
 
-        let args = std::env::args().skip(1).collect::<Vec<_>>();
-
-        let mut i = 0;
-        while i < args.len() {
-            match args[i].as_str() {
-                "--config" | "-c" => {
-                    if i + 1 < args.len() {
-                        config = PathBuf::from(&args[i + 1]);
-                        config_explicit = true;
-                        i += 2;
-                    } else {
-                        i += 1;
-                    }
+        let mut rest = args.iter().map(String::as_str);
+        while let Some(arg) = rest.next() {
+            let mut value = || {
+                rest.next()
+                    .with_context(|| format!("{arg} expects a value"))
+            };
+            match arg {
+                "--baseline" => baseline = Some(PathBuf::from(value()?)),
+                "--min-hits" => {
+                    let hits = value()?;
+                    min_hits = hits.parse().with_context(|| {
+                        format!("--min-hits expects a whole number, got '{hits}'")
+                    })?;
                 }
-                "--verbose" | "-v" => {
-                    verbose = true;
-                    i += 1;
-                }
-                _ => {
-                    i += 1;
+                "--config" | "-c" => {
+                    config = PathBuf::from(value()?);
+                    config_explicit = true;
                 }
+                "--record" => record = Some(PathBuf::from(value()?)),
+                "--verbose" | "-v" => verbose = true,
+                flag if flag.starts_with('-') => bail!("unknown option '{flag}'"),
+                arg => positional.push(arg),
             }
         }
 
-        Self {
+        let command = match positional.as_slice() {
+            [] => Command::Run,
+            ["replay", journal] => Command::Replay {
+                journal: PathBuf::from(journal),
+            },
+            ["replay"] => bail!("replay expects a journal path"),
+            ["reload"] => Command::Reload,
+            ["baseline", "suggest"] => Command::BaselineSuggest { baseline, min_hits },
+            ["schema", "alerts"] => Command::SchemaAlerts,
+            _ => bail!("unknown command '{}'", positional.join(" ")),
+        };
+
+        Ok(Self {
+            command,
             config,
             config_explicit,
             verbose,
+            record,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_defaults_to_live_run() {
+        let cli = Cli::parse_from(args(&["--record", "events.ndjson", "-v"])).expect("parse");
+        assert_eq!(cli.command, Command::Run);
+        assert_eq!(cli.record, Some(PathBuf::from("events.ndjson")));
+        assert!(cli.verbose);
+        assert!(!cli.config_explicit);
+    }
+
+    #[test]
+    fn parse_replay_subcommand() {
+        let cli = Cli::parse_from(args(&["replay", "incident.ndjson", "--config", "x.toml"]))
+            .expect("parse");
+        assert_eq!(
+            cli.command,
+            Command::Replay {
+                journal: PathBuf::from("incident.ndjson")
+            }
+        );
+        assert_eq!(cli.config, PathBuf::from("x.toml"));
+        assert!(cli.config_explicit);
+
+        let cli = Cli::parse_from(args(&["-v", "-c", "x.toml", "replay", "incident.ndjson"]))
+            .expect("parse");
+        assert_eq!(
+            cli.command,
+            Command::Replay {
+                journal: PathBuf::from("incident.ndjson")
+            }
+        );
+        assert!(cli.verbose);
+    }
+
+    #[test]
+    fn parse_reload_subcommand() {
+        let cli = Cli::parse_from(args(&["reload", "-c", "x.toml"])).expect("parse");
+        assert_eq!(cli.command, Command::Reload);
+        assert_eq!(cli.config, PathBuf::from("x.toml"));
+
+        let cli = Cli::parse_from(args(&["-c", "x.toml", "reload"])).expect("parse");
+        assert_eq!(cli.command, Command::Reload);
+    }
+
+    #[test]
+    fn parse_schema_alerts_subcommand() {
+        let cli = Cli::parse_from(args(&["schema", "alerts"])).expect("parse");
+        assert_eq!(cli.command, Command::SchemaAlerts);
+    }
+
+    #[test]
+    fn parse_rejects_unknown_or_incomplete_commands_and_flags() {
+        for bad in [
+            &["replay"][..],
+            &["baseline"],
+            &["schema"],
+            &["shema", "alerts"],
+            &["reload", "now"],
+            &["--record"],
+            &["baseline", "suggest", "--min-hits"],
+            &["-c"],
+            &["--no-such-flag"],
+        ] {
+            assert!(Cli::parse_from(args(bad)).is_err(), "{bad:?}");
         }
+        let err = Cli::parse_from(args(&["--record"])).expect_err("missing value");
+        assert!(format!("{err:#}").contains("--record"));
+    }
+
+    #[test]
+    fn parse_baseline_suggest_subcommand() {
+        let cli = Cli::parse_from(args(&[
+            "baseline",
+            "suggest",
+            "--baseline",
+            "fleet.json",
+            "--min-hits",
+            "5",
+        ]))
+        .expect("parse");
+        assert_eq!(
+            cli.command,
+            Command::BaselineSuggest {
+                baseline: Some(PathBuf::from("fleet.json")),
+                min_hits: 5,
+            }
+        );
+
+        let cli = Cli::parse_from(args(&["--min-hits", "3", "-v", "baseline", "suggest"]))
+            .expect("parse");
+        assert_eq!(
+            cli.command,
+            Command::BaselineSuggest {
+                baseline: None,
+                min_hits: 3,
+            }
+        );
+        let err = Cli::parse_from(args(&["baseline", "suggest", "--min-hits", "five"]))
+            .expect_err("non-numeric --min-hits");
+        assert!(format!("{err:#}").contains("--min-hits"));
     }
 }
@@ -1,6 +1,15 @@
+use crate::{
+    runtime::rules::RuleSet, support::templates::Templates, telemetry::event::FileOperation,
+    trust::hash::normalize_sha256,
+};
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashSet, fs, path::Path};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    net::SocketAddr,
+    path::Path,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -27,6 +36,25 @@ pub struct Config {
 
     #[serde(default)]
     pub trust_api: TrustApiConfig,
+
+    #[serde(default)]
+    pub correlation: CorrelationConfig,
+
+    #[serde(default)]
+    pub trust_cache: TrustCacheConfig,
+
+    #[serde(default)]
+    pub response: ResponseConfig,
+
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+
+    /// Names usable as `${NAME}` in protected rule patterns.
+    #[serde(default)]
+    pub vars: BTreeMap<String, String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,12 +67,196 @@ pub struct GeneralConfig {
 
     #[serde(default = "default_suppress_ms")]
     pub suppress_ms: u64,
+
+    /// What makes two alerts repeats of each other for `suppress_ms`.
+    #[serde(default)]
+    pub suppress_key: SuppressKey,
+
+    /// Labels stamped on every alert, e.g. site or asset group.
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    #[serde(default)]
+    pub mode: SensorMode,
+
+    /// Empty: `%LOCALAPPDATA%\TITAN-Vigil-CE\baseline.json`.
+    #[serde(default)]
+    pub baseline_path: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum SuppressKey {
+    /// The same process touching the same path.
+    #[default]
+    #[serde(rename = "pid+target")]
+    PidTarget,
+    /// Any instance of an image touching the same rule, across respawns.
+    #[serde(rename = "image+rule")]
+    ImageRule,
+    #[serde(rename = "image+target")]
+    ImageTarget,
+    /// Any process touching the same rule.
+    #[serde(rename = "rule")]
+    Rule,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SensorMode {
+    /// Alert on unauthorized access.
+    #[default]
+    Enforce,
+    /// Record every accessor of a protected path into the baseline; never alert.
+    Learn,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchKind {
+    #[default]
+    Substring,
+    Exact,
+    Prefix,
+    Suffix,
+    Glob,
+    Regex,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ProtectedRule {
-    pub substring: String,
+    #[serde(alias = "substring")]
+    pub pattern: String,
     pub name: String,
+
+    #[serde(default, rename = "match")]
+    pub match_kind: MatchKind,
+
+    /// Higher wins when several rules match one path; ties fall back to config order.
+    #[serde(default)]
+    pub priority: i32,
+
+    /// When any `authorized_*` list is set, only matching processes may touch the rule's
+    /// paths, whatever their global trust. Every non-empty list must match.
+    #[serde(default)]
+    pub authorized_images: Vec<String>,
+
+    #[serde(default)]
+    pub authorized_signers: Vec<String>,
+
+    #[serde(default)]
+    pub authorized_hashes: Vec<String>,
+
+    /// Sub-paths carved out of this rule; checked after a positive match.
+    #[serde(default)]
+    pub exclude: Vec<ExcludePattern>,
+
+    /// File operations this rule watches; empty means all of them.
+    #[serde(default)]
+    pub operations: Vec<FileOperation>,
+
+    /// Operations that alert even when the accessor is trusted or authorized.
+    #[serde(default)]
+    pub always_alert: Vec<FileOperation>,
+
+    /// Action taken against the offending process once this rule alerts.
+    #[serde(default)]
+    pub response: ResponseKind,
+
+    /// Overrides `general.suppress_ms` for this rule's alerts.
+    #[serde(default)]
+    pub suppress_ms: Option<u64>,
+
+    /// Set by template expansion: a `prefix`/`exact` pattern that starts below the
+    /// volume root and matches after any drive letter or `\Device\...` volume.
+    #[serde(skip)]
+    pub any_volume: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseKind {
+    #[default]
+    None,
+    Suspend,
+    Terminate,
+    QuarantineImage,
+}
+
+impl ResponseKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Suspend => "suspend",
+            Self::Terminate => "terminate",
+            Self::QuarantineImage => "quarantine_image",
+        }
+    }
+}
+
+/// A path carved out of protection. A bare string is a substring pattern; a table
+/// takes `pattern` and an optional `match` kind like a protected rule.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(from = "ExcludeSpec")]
+pub struct ExcludePattern {
+    pub pattern: String,
+
+    #[serde(rename = "match")]
+    pub match_kind: MatchKind,
+
+    /// See [`ProtectedRule::any_volume`].
+    #[serde(skip)]
+    pub any_volume: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ExcludeSpec {
+    Pattern(String),
+    Table {
+        pattern: String,
+        #[serde(default, rename = "match")]
+        match_kind: MatchKind,
+    },
+}
+
+impl From<ExcludeSpec> for ExcludePattern {
+    fn from(spec: ExcludeSpec) -> Self {
+        match spec {
+            ExcludeSpec::Pattern(pattern) => Self {
+                pattern,
+                match_kind: MatchKind::Substring,
+                any_volume: false,
+            },
+            ExcludeSpec::Table {
+                pattern,
+                match_kind,
+            } => Self {
+                pattern,
+                match_kind,
+                any_volume: false,
+            },
+        }
+    }
+}
+
+impl ExcludePattern {
+    pub fn to_rule(&self, name: &str) -> ProtectedRule {
+        ProtectedRule {
+            pattern: self.pattern.clone(),
+            name: name.to_string(),
+            match_kind: self.match_kind,
+            any_volume: self.any_volume,
+            ..Default::default()
+        }
+    }
+}
+
+impl ProtectedRule {
+    pub fn restricts_accessors(&self) -> bool {
+        !self.authorized_images.is_empty()
+            || !self.authorized_signers.is_empty()
+            || !self.authorized_hashes.is_empty()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -57,6 +269,30 @@ pub struct WatchConfig {
 
     #[serde(default)]
     pub exact_paths: Vec<ProtectedRule>,
+
+    /// Profile names for `${ANY_USER}`-style templates; discovered on disk when empty.
+    #[serde(default)]
+    pub profiles: Vec<String>,
+
+    /// Paths never alerted on, whichever rule matched them.
+    #[serde(default)]
+    pub exclude: Vec<ExcludePattern>,
+
+    /// Which of several rules matching one path reports the access.
+    #[serde(default)]
+    pub match_mode: MatchMode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchMode {
+    /// The highest-priority rule, then exact rules, then config order.
+    #[default]
+    First,
+    /// Among the highest-priority matches, the one with the narrowest pattern.
+    MostSpecific,
+    /// The `first` rule governs the access; the alert names every match.
+    All,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -66,6 +302,10 @@ pub struct AllowlistConfig {
 
     #[serde(default)]
     pub process_name_allow: Vec<String>,
+
+    /// Images trusted by SHA-256 whether or not they are signed.
+    #[serde(default)]
+    pub image_sha256: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -92,6 +332,10 @@ pub struct SecurityConfig {
 
     #[serde(default)]
     pub denylisted_cert_thumbprints: Vec<String>,
+
+    /// Images never trusted, overriding every other trust source.
+    #[serde(default)]
+    pub denylisted_image_sha256: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -121,6 +365,87 @@ pub struct TrustApiConfig {
     pub mode: TrustApiMode,
 }
 
+/// Signature verdicts cached per image SHA-256, persisted across restarts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrustCacheConfig {
+    #[serde(default = "default_trust_cache_enabled")]
+    pub enabled: bool,
+
+    #[serde(default = "default_trust_cache_ttl_secs")]
+    pub ttl_secs: u64,
+
+    #[serde(default = "default_trust_cache_max_entries")]
+    pub max_entries: usize,
+
+    /// Empty: `%ProgramData%\TITAN-Vigil-CE\trust_cache.json`.
+    #[serde(default)]
+    pub path: String,
+}
+
+/// Cross-rule correlation of untrusted hits by one process.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CorrelationConfig {
+    #[serde(default = "default_correlation_enabled")]
+    pub enabled: bool,
+
+    /// Distinct rules one process must touch within the window to raise `credential_sweep`.
+    #[serde(default = "default_sweep_threshold")]
+    pub sweep_threshold: usize,
+
+    #[serde(default = "default_sweep_window_ms")]
+    pub sweep_window_ms: u64,
+}
+
+/// Token buckets on per-hit alerts, globally and per rule. Held-back alerts are
+/// tallied into periodic `alert_storm` summaries.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    #[serde(default = "default_rate_limit_enabled")]
+    pub enabled: bool,
+
+    #[serde(default = "default_global_per_sec")]
+    pub global_per_sec: u32,
+
+    #[serde(default = "default_global_burst")]
+    pub global_burst: u32,
+
+    #[serde(default = "default_rule_per_sec")]
+    pub rule_per_sec: u32,
+
+    #[serde(default = "default_rule_burst")]
+    pub rule_burst: u32,
+
+    /// How often an `alert_storm` summary goes out while alerts are held back.
+    #[serde(default = "default_storm_summary_ms")]
+    pub storm_summary_ms: u64,
+}
+
+/// Local HTTP endpoint serving sensor internals in Prometheus text format.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// `ip:port` to serve `/metrics` on; keep it on loopback unless scraped remotely.
+    #[serde(default = "default_metrics_listen")]
+    pub listen: String,
+}
+
+/// Gate for per-rule `response` actions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Log and audit actions without touching the process.
+    #[serde(default = "default_response_dry_run")]
+    pub dry_run: bool,
+
+    /// Empty: `%LOCALAPPDATA%\TITAN-Vigil-CE\quarantine`.
+    #[serde(default)]
+    pub quarantine_dir: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConcurrencyConfig {
     #[serde(default = "default_worker_threads")]
@@ -128,6 +453,18 @@ pub struct ConcurrencyConfig {
 
     #[serde(default = "default_alert_channel_capacity")]
     pub alert_channel_capacity: usize,
+
+    /// Lock shards for the engine's FileKey, dedupe and whitelist maps (rounded up to a power of two).
+    #[serde(default = "default_state_shards")]
+    pub state_shards: usize,
+
+    /// Threads verifying process trust off the ETW callback; 0 verifies inline.
+    #[serde(default = "default_trust_workers")]
+    pub trust_workers: usize,
+
+    /// How long a protected-path hit waits for a verdict before it is evaluated as trust unknown.
+    #[serde(default = "default_trust_deadline_ms")]
+    pub trust_deadline_ms: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -194,6 +531,15 @@ fn default_worker_threads() -> usize {
 fn default_alert_channel_capacity() -> usize {
     4096
 }
+fn default_state_shards() -> usize {
+    16
+}
+fn default_trust_workers() -> usize {
+    2
+}
+fn default_trust_deadline_ms() -> u64 {
+    2000
+}
 fn default_connect_timeout_ms() -> u64 {
     1500
 }
@@ -219,12 +565,54 @@ fn default_generate_sigma_rules() -> bool {
 fn default_sigma_rules_file() -> String {
     "sigma_rules.yml".to_string()
 }
+fn default_correlation_enabled() -> bool {
+    true
+}
+fn default_sweep_threshold() -> usize {
+    3
+}
+fn default_sweep_window_ms() -> u64 {
+    10_000
+}
+fn default_response_dry_run() -> bool {
+    true
+}
+fn default_rate_limit_enabled() -> bool {
+    false
+}
+fn default_global_per_sec() -> u32 {
+    50
+}
+fn default_global_burst() -> u32 {
+    200
+}
+fn default_rule_per_sec() -> u32 {
+    20
+}
+fn default_rule_burst() -> u32 {
+    100
+}
+fn default_storm_summary_ms() -> u64 {
+    10_000
+}
+fn default_metrics_listen() -> String {
+    "127.0.0.1:9464".to_string()
+}
 fn default_trust_api_mode() -> TrustApiMode {
     TrustApiMode::WintrustOnly
 }
 fn default_trust_api_endpoint() -> String {
     String::new()
 }
+fn default_trust_cache_enabled() -> bool {
+    true
+}
+fn default_trust_cache_ttl_secs() -> u64 {
+    24 * 60 * 60
+}
+fn default_trust_cache_max_entries() -> usize {
+    4096
+}
 
 impl Default for GeneralConfig {
     fn default() -> Self {
@@ -232,6 +620,10 @@ impl Default for GeneralConfig {
             quiet: default_quiet(),
             jsonl: default_jsonl(),
             suppress_ms: default_suppress_ms(),
+            suppress_key: SuppressKey::default(),
+            tags: Vec::new(),
+            mode: SensorMode::default(),
+            baseline_path: String::new(),
         }
     }
 }
@@ -244,6 +636,60 @@ impl Default for SecurityConfig {
             allow_legacy_process_name_fallback: false,
             revocation_mode: RevocationMode::None,
             denylisted_cert_thumbprints: Vec::new(),
+            denylisted_image_sha256: Vec::new(),
+        }
+    }
+}
+
+impl Default for CorrelationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_correlation_enabled(),
+            sweep_threshold: default_sweep_threshold(),
+            sweep_window_ms: default_sweep_window_ms(),
+        }
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_rate_limit_enabled(),
+            global_per_sec: default_global_per_sec(),
+            global_burst: default_global_burst(),
+            rule_per_sec: default_rule_per_sec(),
+            rule_burst: default_rule_burst(),
+            storm_summary_ms: default_storm_summary_ms(),
+        }
+    }
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: default_metrics_listen(),
+        }
+    }
+}
+
+impl Default for ResponseConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dry_run: default_response_dry_run(),
+            quarantine_dir: String::new(),
+        }
+    }
+}
+
+impl Default for TrustCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_trust_cache_enabled(),
+            ttl_secs: default_trust_cache_ttl_secs(),
+            max_entries: default_trust_cache_max_entries(),
+            path: String::new(),
         }
     }
 }
@@ -253,6 +699,9 @@ impl Default for ConcurrencyConfig {
         Self {
             worker_threads: default_worker_threads(),
             alert_channel_capacity: default_alert_channel_capacity(),
+            state_shards: default_state_shards(),
+            trust_workers: default_trust_workers(),
+            trust_deadline_ms: default_trust_deadline_ms(),
         }
     }
 }
@@ -296,14 +745,63 @@ impl Config {
     pub fn load(path: &Path) -> Result<Self> {
         let text = fs::read_to_string(path)
             .with_context(|| format!("failed to read config file: {}", path.display()))?;
+        Self::from_toml(&text)
+    }
 
-        let mut cfg: Config = toml::from_str(&text).context("failed to parse config.toml")?;
+    /// Protected and exact-path rules as written at `path`, before template expansion,
+    /// for output meant to be pasted back into the file.
+    pub fn written_rules(path: &Path) -> Result<Vec<ProtectedRule>> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("failed to read config file: {}", path.display()))?;
+        let cfg: Config = toml::from_str(&text).context("failed to parse config.toml")?;
+        let exact = cfg.watch.exact_paths.into_iter().map(|rule| ProtectedRule {
+            match_kind: MatchKind::Exact,
+            ..rule
+        });
+        Ok(cfg.watch.protected.into_iter().chain(exact).collect())
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self> {
+        let mut cfg: Config = toml::from_str(text).context("failed to parse config.toml")?;
 
-        for rule in &mut cfg.watch.protected {
-            rule.substring = rule.substring.to_lowercase();
-        }
         for rule in &mut cfg.watch.exact_paths {
-            rule.substring = rule.substring.to_lowercase();
+            rule.match_kind = MatchKind::Exact;
+        }
+        let templates = Templates::new(&cfg.vars, &cfg.watch.profiles)
+            .context("failed to resolve PROFILES_ROOT")?;
+        cfg.watch.protected = expand_templates(&templates, cfg.watch.protected)?;
+        cfg.watch.exact_paths = expand_templates(&templates, cfg.watch.exact_paths)?;
+        cfg.watch.exclude = expand_excludes(&templates, cfg.watch.exclude, "watch.exclude")?;
+        normalize_excludes(&mut cfg.watch.exclude);
+        validate_excludes(&cfg.watch.exclude, "watch.exclude")?;
+        for rule in cfg
+            .watch
+            .protected
+            .iter_mut()
+            .chain(cfg.watch.exact_paths.iter_mut())
+        {
+            // Regex case folding happens at compile time; lowercasing would change escapes like `\D`.
+            if rule.match_kind != MatchKind::Regex {
+                rule.pattern = rule.pattern.to_lowercase();
+            }
+            normalize_excludes(&mut rule.exclude);
+            for image in &mut rule.authorized_images {
+                *image = image.trim().to_lowercase();
+            }
+            for signer in &mut rule.authorized_signers {
+                *signer = signer.trim().to_lowercase();
+            }
+            for hash in &mut rule.authorized_hashes {
+                let normalized = normalize_sha256(hash);
+                if normalized.len() != 64 {
+                    anyhow::bail!(
+                        "protected rule '{}' has an invalid authorized_hashes entry '{}' (expected SHA-256)",
+                        rule.name,
+                        hash
+                    );
+                }
+                *hash = normalized;
+            }
         }
 
         cfg.watch.protected_substrings = cfg
@@ -334,6 +832,11 @@ impl Config {
             .map(normalize_thumbprint)
             .filter(|s| !s.is_empty())
             .collect();
+        normalize_hashes(&mut cfg.allowlist.image_sha256, "allowlist.image_sha256")?;
+        normalize_hashes(
+            &mut cfg.security.denylisted_image_sha256,
+            "security.denylisted_image_sha256",
+        )?;
 
         if cfg.watch.protected.is_empty() && !cfg.watch.protected_substrings.is_empty() {
             cfg.watch.protected = cfg
@@ -341,18 +844,45 @@ impl Config {
                 .protected_substrings
                 .iter()
                 .map(|s| ProtectedRule {
-                    substring: s.clone(),
+                    pattern: s.clone(),
                     name: s.clone(),
+                    match_kind: MatchKind::Substring,
+                    ..Default::default()
                 })
                 .collect();
         }
 
+        // Build the combined set the engine will match with: every pattern can compile
+        // on its own and still push the set past the regex size limit.
+        RuleSet::new(cfg.watch.protected.iter().chain(&cfg.watch.exact_paths))
+            .context("failed to build the protected rule set")?;
+        for rule in cfg.watch.protected.iter().chain(&cfg.watch.exact_paths) {
+            validate_excludes(&rule.exclude, &format!("protected rule '{}'", rule.name))?;
+        }
+
         if cfg.concurrency.worker_threads == 0 {
             cfg.concurrency.worker_threads = default_worker_threads();
         }
         if cfg.concurrency.alert_channel_capacity == 0 {
             cfg.concurrency.alert_channel_capacity = default_alert_channel_capacity();
         }
+        if cfg.concurrency.state_shards == 0 {
+            cfg.concurrency.state_shards = default_state_shards();
+        }
+        if cfg.concurrency.trust_workers > 0 && cfg.concurrency.trust_deadline_ms == 0 {
+            anyhow::bail!("concurrency.trust_deadline_ms must be greater than zero");
+        }
+        if cfg.concurrency.state_shards > 1024 {
+            anyhow::bail!("concurrency.state_shards must be at most 1024");
+        }
+
+        if cfg.trust_cache.enabled && cfg.trust_cache.max_entries == 0 {
+            anyhow::bail!("trust_cache.max_entries must be greater than zero");
+        }
+
+        if cfg.correlation.enabled && cfg.correlation.sweep_threshold < 2 {
+            anyhow::bail!("correlation.sweep_threshold must be at least 2");
+        }
 
         if cfg.endpoint_alert.enabled && cfg.endpoint_alert.endpoint.trim().is_empty() {
             anyhow::bail!("endpoint_alert.enabled=true but endpoint_alert.endpoint is empty");
@@ -362,6 +892,13 @@ impl Config {
             anyhow::bail!("trust_api.enabled=true but trust_api.endpoint is empty");
         }
 
+        if cfg.metrics.enabled && cfg.metrics.listen.parse::<SocketAddr>().is_err() {
+            anyhow::bail!(
+                "metrics.listen '{}' is not an ip:port address",
+                cfg.metrics.listen
+            );
+        }
+
         cfg.siem.formats = cfg
             .siem
             .formats
@@ -376,6 +913,96 @@ impl Config {
 
         Ok(cfg)
     }
+
+    /// Sections that differ from `next` but only take effect after a restart.
+    pub fn restart_required(&self, next: &Config) -> Vec<&'static str> {
+        fn differs<T: Serialize>(a: &T, b: &T) -> bool {
+            serde_json::to_value(a).ok() != serde_json::to_value(b).ok()
+        }
+        let mut sections = Vec::new();
+        if self.general.mode != next.general.mode
+            || self.general.baseline_path != next.general.baseline_path
+        {
+            sections.push("general.mode");
+        }
+        if differs(&self.concurrency, &next.concurrency) {
+            sections.push("concurrency");
+        }
+        if differs(&self.trust_cache, &next.trust_cache) {
+            sections.push("trust_cache");
+        }
+        if differs(&self.endpoint_alert, &next.endpoint_alert) {
+            sections.push("endpoint_alert");
+        }
+        if self.response.quarantine_dir != next.response.quarantine_dir {
+            sections.push("response.quarantine_dir");
+        }
+        if differs(&self.metrics, &next.metrics) {
+            sections.push("metrics");
+        }
+        sections
+    }
+}
+
+fn normalize_hashes(hashes: &mut [String], key: &str) -> Result<()> {
+    for hash in hashes {
+        let normalized = normalize_sha256(hash);
+        if normalized.len() != 64 {
+            anyhow::bail!("{key} has an invalid entry '{hash}' (expected SHA-256)");
+        }
+        *hash = normalized;
+    }
+    Ok(())
+}
+
+fn expand_templates(
+    templates: &Templates,
+    rules: Vec<ProtectedRule>,
+) -> Result<Vec<ProtectedRule>> {
+    let mut out = Vec::with_capacity(rules.len());
+    for mut rule in rules {
+        let owner = format!("protected rule '{}'", rule.name);
+        rule.exclude = expand_excludes(templates, std::mem::take(&mut rule.exclude), &owner)?;
+        out.extend(
+            templates
+                .expand(rule)
+                .with_context(|| format!("invalid template in {owner}"))?,
+        );
+    }
+    Ok(out)
+}
+
+fn expand_excludes(
+    templates: &Templates,
+    excludes: Vec<ExcludePattern>,
+    owner: &str,
+) -> Result<Vec<ExcludePattern>> {
+    let mut out = Vec::with_capacity(excludes.len());
+    for exclude in excludes {
+        let expanded = templates
+            .expand(exclude.to_rule(owner))
+            .with_context(|| format!("invalid template in exclude of {owner}"))?;
+        out.extend(expanded.into_iter().map(|rule| ExcludePattern {
+            pattern: rule.pattern,
+            match_kind: rule.match_kind,
+            any_volume: rule.any_volume,
+        }));
+    }
+    Ok(out)
+}
+
+fn normalize_excludes(excludes: &mut [ExcludePattern]) {
+    for exclude in excludes {
+        if exclude.match_kind != MatchKind::Regex {
+            exclude.pattern = exclude.pattern.to_lowercase();
+        }
+    }
+}
+
+fn validate_excludes(excludes: &[ExcludePattern], owner: &str) -> Result<()> {
+    let rules: Vec<_> = excludes.iter().map(|ex| ex.to_rule(owner)).collect();
+    RuleSet::new(&rules).with_context(|| format!("invalid exclude pattern in {owner}"))?;
+    Ok(())
 }
 
 fn normalize_thumbprint(value: String) -> String {
@@ -480,6 +1107,181 @@ protected_substrings = ["\\Users\\Damon\\Cookies"]
         );
         assert_eq!(cfg.allowlist.process_name_allow[0], "chrome.exe");
         assert_eq!(cfg.security.denylisted_cert_thumbprints[0], "AABB11");
-        assert_eq!(cfg.watch.protected[0].substring, "\\users\\damon\\cookies");
+        assert_eq!(cfg.watch.protected[0].pattern, "\\users\\damon\\cookies");
+    }
+
+    #[test]
+    fn config_load_accepts_match_kinds_and_legacy_substring_key() {
+        let path = write_temp_config(
+            r#"
+[[watch.protected]]
+substring = "\\Mozilla\\Firefox\\Profiles"
+name = "Firefox"
+
+[[watch.protected]]
+pattern = "**\\User Data\\*\\Login Data"
+match = "glob"
+name = "Chromium Passwords"
+
+[[watch.protected]]
+pattern = "\\.KDBX$"
+match = "regex"
+name = "KeePass"
+"#,
+        );
+
+        let cfg = Config::load(&path).expect("config should load");
+        let _ = fs::remove_file(&path);
+
+        let rules = &cfg.watch.protected;
+        assert_eq!(rules[0].match_kind, MatchKind::Substring);
+        assert_eq!(rules[0].pattern, "\\mozilla\\firefox\\profiles");
+        assert_eq!(rules[1].match_kind, MatchKind::Glob);
+        assert_eq!(rules[1].pattern, "**\\user data\\*\\login data");
+        assert_eq!(rules[2].match_kind, MatchKind::Regex);
+        assert_eq!(rules[2].pattern, "\\.KDBX$");
+    }
+
+    #[test]
+    fn config_load_expands_path_templates_per_profile() {
+        let path = write_temp_config(
+            r#"
+[vars]
+PROFILES_ROOT = "C:\\Users"
+CHROME = "${LOCALAPPDATA}\\Google\\Chrome\\User Data"
+
+[watch]
+profiles = ["alice", "bob"]
+
+[[watch.protected]]
+pattern = "${CHROME}\\Default\\Login Data"
+name = "Chrome Passwords"
+
+[[watch.protected]]
+pattern = "\\Mozilla\\Firefox\\Profiles"
+name = "Firefox"
+"#,
+        );
+
+        let cfg = Config::load(&path).expect("config should load");
+        let _ = fs::remove_file(&path);
+
+        let rules = &cfg.watch.protected;
+        assert_eq!(rules.len(), 3);
+        assert!(rules[..2].iter().all(|r| r.name == "Chrome Passwords"));
+        assert_eq!(rules[0].match_kind, MatchKind::Prefix);
+        assert!(rules[0].any_volume);
+        assert_eq!(
+            rules[0].pattern,
+            "\\users\\alice\\appdata\\local\\google\\chrome\\user data\\default\\login data"
+        );
+        assert!(rules[1].pattern.contains("bob"));
+        assert_eq!(rules[2].pattern, "\\mozilla\\firefox\\profiles");
+    }
+
+    #[test]
+    fn config_rejects_rules_that_only_fail_as_a_set() {
+        // Each regex compiles on its own; together they exceed the regex size limit.
+        let mut toml = String::from(
+            "[[watch.protected]]\npattern = '.kdbx'\nmatch = 'suffix'\nname = 'KeePass'\n",
+        );
+        for i in 0..4 {
+            toml.push_str(&format!(
+                "[[watch.protected]]\npattern = '\\\\(?-u:\\w){{1,20000}}\\\\s{i}'\nmatch = 'regex'\nname = 'r{i}'\n"
+            ));
+        }
+        let err = Config::from_toml(&toml).expect_err("config should fail");
+        assert!(format!("{err:#}").contains("failed to build the protected rule set"));
+    }
+
+    #[test]
+    fn config_load_rejects_unknown_template_variable() {
+        let path = write_temp_config(
+            r#"
+[[watch.protected]]
+pattern = "${NO_SUCH_TITAN_VAR}\\secrets"
+name = "Broken"
+"#,
+        );
+
+        let err = Config::load(&path).expect_err("config should fail");
+        let _ = fs::remove_file(&path);
+        let msg = format!("{err:#}");
+        assert!(msg.contains("protected rule 'Broken'"));
+        assert!(msg.contains("NO_SUCH_TITAN_VAR"));
+    }
+
+    #[test]
+    fn config_load_accepts_plain_and_table_excludes() {
+        let path = write_temp_config(
+            r#"
+[watch]
+exclude = ["\\Temp\\", { pattern = "**\\cache2\\**", match = "glob" }]
+
+[[watch.protected]]
+pattern = "\\Network\\Cookies"
+name = "Cookies"
+exclude = ["Cookies-Journal"]
+"#,
+        );
+
+        let cfg = Config::load(&path).expect("config should load");
+        let _ = fs::remove_file(&path);
+
+        let global = &cfg.watch.exclude;
+        assert_eq!(global[0].pattern, "\\temp\\");
+        assert_eq!(global[0].match_kind, MatchKind::Substring);
+        assert_eq!(global[1].match_kind, MatchKind::Glob);
+        assert_eq!(cfg.watch.protected[0].exclude[0].pattern, "cookies-journal");
+    }
+
+    #[test]
+    fn config_load_rejects_invalid_regex_rule() {
+        let path = write_temp_config(
+            r#"
+[[watch.protected]]
+pattern = "(unclosed"
+match = "regex"
+name = "Broken"
+"#,
+        );
+
+        let err = Config::load(&path).expect_err("config should fail");
+        let _ = fs::remove_file(&path);
+        let msg = format!("{err:#}");
+        assert!(msg.contains("protected rule 'Broken'"));
+    }
+
+    #[test]
+    fn image_hash_lists_are_normalized_and_validated() {
+        let hash = "ab".repeat(32);
+        let cfg = Config::from_toml(&format!(
+            "[allowlist]\nimage_sha256 = [\"{hash}\"]\n[security]\ndenylisted_image_sha256 = [\"{}\"]\n",
+            "CD".repeat(32)
+        ))
+        .expect("config");
+        assert_eq!(cfg.allowlist.image_sha256, vec![hash.to_uppercase()]);
+        assert_eq!(cfg.security.denylisted_image_sha256[0], "CD".repeat(32));
+
+        let err = Config::from_toml("[security]\ndenylisted_image_sha256 = [\"abc\"]\n")
+            .expect_err("short hash should fail");
+        assert!(format!("{err:#}").contains("security.denylisted_image_sha256"));
+    }
+
+    #[test]
+    fn restart_required_lists_only_startup_sections() {
+        let current = Config::from_toml("").expect("default config");
+        let next = Config::from_toml(
+            r#"
+[general]
+suppress_ms = 10
+
+[concurrency]
+trust_workers = 4
+"#,
+        )
+        .expect("config");
+        assert_eq!(current.restart_required(&next), vec!["concurrency"]);
+        assert!(current.restart_required(&current).is_empty());
     }
 }
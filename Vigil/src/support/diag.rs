@@ -1,3 +1,40 @@
+use serde_json::{Value, json};
+use std::time::{SystemTime, UNIX_EPOCH};
+
 pub fn startup(msg: &str) {
     eprintln!("[STARTUP] {msg}");
 }
+
+/// Emit a structured operational event (e.g. `config_reload_failed`) as one JSON line.
+pub fn event(kind: &str, fields: Value) {
+    eprintln!("{}", event_line(kind, fields));
+}
+
+fn event_line(kind: &str, fields: Value) -> String {
+    let ts_unix = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let mut line = json!({ "event": kind, "ts_unix": ts_unix });
+    if let (Some(line), Value::Object(fields)) = (line.as_object_mut(), fields) {
+        line.extend(fields);
+    }
+    line.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_lines_are_flat_json() {
+        let line = event_line(
+            "config_reload_failed",
+            json!({ "path": "config.toml", "error": "bad pattern" }),
+        );
+        let value: Value = serde_json::from_str(&line).expect("json");
+        assert_eq!(value["event"], "config_reload_failed");
+        assert_eq!(value["error"], "bad pattern");
+        assert!(value["ts_unix"].as_u64().is_some());
+    }
+}
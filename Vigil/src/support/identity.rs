@@ -0,0 +1,144 @@
+use anyhow::{Context, Result};
+use std::{
+    collections::hash_map::RandomState,
+    fs,
+    hash::BuildHasher,
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+const CROCKFORD: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Who raised an alert: stamped on every alert so a SIEM can tell endpoints apart.
+#[derive(Debug, Clone, Default)]
+pub struct SensorIdentity {
+    /// Generated on first run and kept across restarts.
+    pub sensor_id: String,
+    pub hostname: String,
+}
+
+impl SensorIdentity {
+    /// Identity with the sensor id stored at `path`, generating it on first run.
+    pub fn load_or_create(path: &Path) -> Result<Self> {
+        let sensor_id = match fs::read_to_string(path) {
+            Ok(text) if !text.trim().is_empty() => text.trim().to_string(),
+            Ok(_) => create_sensor_id(path)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => create_sensor_id(path)?,
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("failed to read sensor id {}", path.display()));
+            }
+        };
+        Ok(Self {
+            sensor_id,
+            hostname: local_hostname(),
+        })
+    }
+}
+
+fn create_sensor_id(path: &Path) -> Result<String> {
+    let id = ulid(unix_millis());
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    }
+    fs::write(path, &id)
+        .with_context(|| format!("failed to write sensor id {}", path.display()))?;
+    Ok(id)
+}
+
+fn local_hostname() -> String {
+    ["COMPUTERNAME", "HOSTNAME"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok())
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+pub fn unix_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// A ULID for `unix_ms`: 48-bit millisecond time then 80 random bits, as 26 Crockford
+/// base32 characters, so ids sort by creation time.
+pub fn ulid(unix_ms: u64) -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    // RandomState is seeded from the OS once per thread and stepped per instance.
+    let state = RandomState::new();
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let random =
+        (u128::from(state.hash_one((n, 0u8))) << 64) | u128::from(state.hash_one((n, 1u8)));
+
+    let value = (u128::from(unix_ms & 0xFFFF_FFFF_FFFF) << 80) | (random & ((1 << 80) - 1));
+    (0..26)
+        .rev()
+        .map(|i| CROCKFORD[((value >> (i * 5)) & 0x1f) as usize] as char)
+        .collect()
+}
+
+/// `unix_ms` as RFC 3339 UTC with milliseconds, e.g. `2023-11-14T22:13:20.123Z`.
+pub fn rfc3339_millis(unix_ms: u64) -> String {
+    let secs = unix_ms / 1000;
+    let (days, rem) = ((secs / 86_400) as i64, secs % 86_400);
+
+    // Civil date from days since the epoch (Howard Hinnant's algorithm).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60,
+        unix_ms % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ulids_are_unique_and_time_ordered() {
+        let a = ulid(1_700_000_000_000);
+        let b = ulid(1_700_000_000_000);
+        let later = ulid(1_700_000_000_001);
+        assert_eq!(a.len(), 26);
+        assert!(a.bytes().all(|c| CROCKFORD.contains(&c)));
+        assert_ne!(a, b);
+        assert_eq!(a[..10], b[..10]);
+        assert!(later[..10] > a[..10]);
+    }
+
+    #[test]
+    fn rfc3339_formats_utc_millis() {
+        assert_eq!(rfc3339_millis(0), "1970-01-01T00:00:00.000Z");
+        assert_eq!(
+            rfc3339_millis(1_700_000_000_123),
+            "2023-11-14T22:13:20.123Z"
+        );
+        assert_eq!(rfc3339_millis(951_782_400_007), "2000-02-29T00:00:00.007Z");
+    }
+
+    #[test]
+    fn sensor_id_is_generated_once() {
+        let path = std::env::temp_dir().join(format!("titan-vigil-sensor-id-{}", ulid(0)));
+        let first = SensorIdentity::load_or_create(&path).expect("create");
+        let again = SensorIdentity::load_or_create(&path).expect("load");
+        let _ = fs::remove_file(&path);
+        assert_eq!(first.sensor_id.len(), 26);
+        assert_eq!(first.sensor_id, again.sensor_id);
+        assert!(!first.hostname.is_empty());
+    }
+}
@@ -0,0 +1,410 @@
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::{BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::atomic::{AtomicU64, Ordering},
+    thread,
+    time::Duration,
+};
+
+/// Upper bounds, in seconds, of the trust lookup latency histogram.
+const LATENCY_BUCKETS: [f64; 10] = [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0];
+const REQUEST_MAX: u64 = 16 * 1024;
+/// Providers counted per event id, sorted; `etw` names any other provider `other`.
+const PROVIDERS: [&str; 3] = [
+    "Microsoft-Windows-Kernel-File",
+    "Microsoft-Windows-Kernel-Process",
+    "other",
+];
+/// Event ids below this get their own counter; the rest share the overflow counter.
+const EVENT_IDS: usize = 64;
+
+/// Counters fed by the ETW callback, the engine and the alert sinks, rendered in the
+/// Prometheus text format for `[metrics]`.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    events: EventCounts,
+    rule_matches: Mutex<BTreeMap<String, u64>>,
+    alerts: Mutex<BTreeMap<String, u64>>,
+    trust_lookups: Mutex<BTreeMap<&'static str, u64>>,
+    trust_latency: Histogram,
+    dropped_alerts: AtomicU64,
+    rate_limited_alerts: AtomicU64,
+    excluded_hits: AtomicU64,
+    sink_errors: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+/// Lock-free per-event counters, bumped from the ETW callback.
+#[derive(Debug)]
+struct EventCounts {
+    by_id: [[AtomicU64; EVENT_IDS]; PROVIDERS.len()],
+    overflow: AtomicU64,
+}
+
+impl Default for EventCounts {
+    fn default() -> Self {
+        Self {
+            by_id: std::array::from_fn(|_| std::array::from_fn(|_| AtomicU64::new(0))),
+            overflow: AtomicU64::new(0),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Histogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+/// Engine state sampled when `/metrics` is scraped.
+#[derive(Debug, Clone, Default)]
+pub struct StateSizes {
+    pub proc_cache: usize,
+    pub filekey_cache: usize,
+    pub last_alert: usize,
+    pub whitelisted_file_objects: usize,
+    pub alert_channel_depth: usize,
+    pub alert_channel_capacity: usize,
+}
+
+impl Metrics {
+    pub fn record_event(&self, provider: &str, event_id: u16) {
+        let counter = PROVIDERS
+            .iter()
+            .position(|p| *p == provider)
+            .and_then(|idx| self.events.by_id[idx].get(usize::from(event_id)))
+            .unwrap_or(&self.events.overflow);
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_rule_match(&self, rule: &str) {
+        bump(&self.rule_matches, rule);
+    }
+
+    pub fn record_alert(&self, kind: &str) {
+        bump(&self.alerts, kind);
+    }
+
+    pub fn record_trust_lookup(&self, trusted: bool, elapsed: Duration) {
+        let result = if trusted { "trusted" } else { "untrusted" };
+        *self.trust_lookups.lock().entry(result).or_default() += 1;
+
+        let h = &self.trust_latency;
+        let secs = elapsed.as_secs_f64();
+        if let Some(idx) = LATENCY_BUCKETS.iter().position(|le| secs <= *le) {
+            h.buckets[idx].fetch_add(1, Ordering::Relaxed);
+        }
+        h.count.fetch_add(1, Ordering::Relaxed);
+        h.sum_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_dropped_alert(&self) {
+        self.dropped_alerts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Alerts dropped on a full alert channel since start.
+    pub fn dropped_alerts(&self) -> u64 {
+        self.dropped_alerts.load(Ordering::Relaxed)
+    }
+
+    pub fn record_rate_limited_alert(&self) {
+        self.rate_limited_alerts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_excluded_hit(&self) {
+        self.excluded_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A failed write to `sink`: a log format name or `endpoint`.
+    pub fn record_sink_error(&self, sink: &'static str) {
+        *self.sink_errors.lock().entry(sink).or_default() += 1;
+    }
+
+    /// Every metric in the Prometheus text exposition format.
+    pub fn render(&self, sizes: &StateSizes) -> String {
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "vigil_events_total",
+            "counter",
+            "ETW events received, by provider and event id.",
+        );
+        for (provider, counts) in PROVIDERS.iter().zip(&self.events.by_id) {
+            for (event_id, n) in counts.iter().enumerate() {
+                let n = n.load(Ordering::Relaxed);
+                if n > 0 {
+                    let _ = writeln!(
+                        out,
+                        "vigil_events_total{{provider=\"{provider}\",event_id=\"{event_id}\"}} {n}"
+                    );
+                }
+            }
+        }
+
+        header(
+            &mut out,
+            "vigil_rule_matches_total",
+            "counter",
+            "Protected-path hits per matching rule.",
+        );
+        for (rule, n) in self.rule_matches.lock().iter() {
+            let _ = writeln!(
+                out,
+                "vigil_rule_matches_total{{rule=\"{}\"}} {n}",
+                escape(rule)
+            );
+        }
+
+        header(
+            &mut out,
+            "vigil_alerts_total",
+            "counter",
+            "Alerts raised, by kind, including dropped ones.",
+        );
+        for (kind, n) in self.alerts.lock().iter() {
+            let _ = writeln!(out, "vigil_alerts_total{{kind=\"{}\"}} {n}", escape(kind));
+        }
+
+        header(
+            &mut out,
+            "vigil_trust_lookups_total",
+            "counter",
+            "Process image trust verifications, by verdict.",
+        );
+        for (result, n) in self.trust_lookups.lock().iter() {
+            let _ = writeln!(out, "vigil_trust_lookups_total{{result=\"{result}\"}} {n}");
+        }
+
+        header(
+            &mut out,
+            "vigil_trust_lookup_seconds",
+            "histogram",
+            "Time spent verifying a process image.",
+        );
+        let h = &self.trust_latency;
+        let mut cumulative = 0;
+        for (le, bucket) in LATENCY_BUCKETS.iter().zip(&h.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(
+                out,
+                "vigil_trust_lookup_seconds_bucket{{le=\"{le}\"}} {cumulative}"
+            );
+        }
+        let count = h.count.load(Ordering::Relaxed);
+        let _ = writeln!(
+            out,
+            "vigil_trust_lookup_seconds_bucket{{le=\"+Inf\"}} {count}"
+        );
+        let _ = writeln!(
+            out,
+            "vigil_trust_lookup_seconds_sum {}",
+            h.sum_micros.load(Ordering::Relaxed) as f64 / 1e6
+        );
+        let _ = writeln!(out, "vigil_trust_lookup_seconds_count {count}");
+
+        header(
+            &mut out,
+            "vigil_cache_entries",
+            "gauge",
+            "Entries in the engine's state maps.",
+        );
+        for (cache, n) in [
+            ("proc_cache", sizes.proc_cache),
+            ("filekey_cache", sizes.filekey_cache),
+            ("last_alert", sizes.last_alert),
+            ("whitelisted_file_objects", sizes.whitelisted_file_objects),
+        ] {
+            let _ = writeln!(out, "vigil_cache_entries{{cache=\"{cache}\"}} {n}");
+        }
+
+        for (name, kind, help, value) in [
+            (
+                "vigil_alert_channel_depth",
+                "gauge",
+                "Alerts waiting for a sink worker.",
+                sizes.alert_channel_depth as u64,
+            ),
+            (
+                "vigil_alert_channel_capacity",
+                "gauge",
+                "Alerts the channel to the sink workers holds before new ones are dropped.",
+                sizes.alert_channel_capacity as u64,
+            ),
+            (
+                "vigil_events_overflow_total",
+                "counter",
+                "ETW events from an event id too high to be counted on its own.",
+                self.events.overflow.load(Ordering::Relaxed),
+            ),
+            (
+                "vigil_dropped_alerts_total",
+                "counter",
+                "Alerts dropped because the alert channel was full.",
+                self.dropped_alerts.load(Ordering::Relaxed),
+            ),
+            (
+                "vigil_rate_limited_alerts_total",
+                "counter",
+                "Alerts held back by [rate_limit].",
+                self.rate_limited_alerts.load(Ordering::Relaxed),
+            ),
+            (
+                "vigil_excluded_hits_total",
+                "counter",
+                "Protected-path hits dropped by an exclude pattern.",
+                self.excluded_hits.load(Ordering::Relaxed),
+            ),
+        ] {
+            header(&mut out, name, kind, help);
+            let _ = writeln!(out, "{name} {value}");
+        }
+
+        header(
+            &mut out,
+            "vigil_sink_write_errors_total",
+            "counter",
+            "Failed alert writes, by sink.",
+        );
+        for (sink, n) in self.sink_errors.lock().iter() {
+            let _ = writeln!(out, "vigil_sink_write_errors_total{{sink=\"{sink}\"}} {n}");
+        }
+        out
+    }
+}
+
+fn bump(map: &Mutex<BTreeMap<String, u64>>, key: &str) {
+    let mut map = map.lock();
+    match map.get_mut(key) {
+        Some(n) => *n += 1,
+        None => {
+            map.insert(key.to_string(), 1);
+        }
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} {kind}");
+}
+
+/// A label value with `\`, `"` and newlines escaped.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Answer `GET /metrics` on `listen` with `render()` from a background thread.
+/// Returns the bound address.
+pub fn serve(listen: &str, render: impl Fn() -> String + Send + 'static) -> Result<SocketAddr> {
+    let listener = TcpListener::bind(listen)
+        .with_context(|| format!("failed to bind metrics listener on {listen}"))?;
+    let addr = listener.local_addr()?;
+    thread::Builder::new()
+        .name("vigil-metrics".to_string())
+        .spawn(move || {
+            for stream in listener.incoming().flatten() {
+                // A scraper that hangs up early is not worth reporting.
+                let _ = respond(stream, &render);
+            }
+        })?;
+    Ok(addr)
+}
+
+fn respond(mut stream: TcpStream, render: &impl Fn() -> String) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(2)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(2)))?;
+
+    let mut reader = BufReader::new((&stream).take(REQUEST_MAX));
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+
+    let mut parts = request.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(path)) if path.split('?').next() == Some("/metrics") => {
+            ("200 OK", render())
+        }
+        (Some("GET"), _) => ("404 Not Found", "not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "method not allowed\n".to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_uses_the_prometheus_text_format() {
+        let m = Metrics::default();
+        m.record_event("Microsoft-Windows-Kernel-File", 12);
+        m.record_event("Microsoft-Windows-Kernel-File", 12);
+        m.record_event("Microsoft-Windows-Kernel-Process", 1);
+        m.record_event("other", 500);
+        m.record_rule_match("Chrome \"Passwords\"");
+        m.record_trust_lookup(true, Duration::from_millis(3));
+        m.record_trust_lookup(false, Duration::from_secs(2));
+        m.record_sink_error("cef");
+        let text = m.render(&StateSizes {
+            proc_cache: 7,
+            alert_channel_capacity: 8192,
+            ..Default::default()
+        });
+
+        for line in [
+            "# TYPE vigil_events_total counter",
+            "vigil_events_total{provider=\"Microsoft-Windows-Kernel-File\",event_id=\"12\"} 2",
+            "vigil_events_total{provider=\"Microsoft-Windows-Kernel-Process\",event_id=\"1\"} 1",
+            "vigil_events_overflow_total 1",
+            "vigil_rule_matches_total{rule=\"Chrome \\\"Passwords\\\"\"} 1",
+            "vigil_trust_lookups_total{result=\"untrusted\"} 1",
+            "vigil_trust_lookup_seconds_bucket{le=\"0.001\"} 0",
+            "vigil_trust_lookup_seconds_bucket{le=\"0.005\"} 1",
+            "vigil_trust_lookup_seconds_bucket{le=\"5\"} 2",
+            "vigil_trust_lookup_seconds_bucket{le=\"+Inf\"} 2",
+            "vigil_trust_lookup_seconds_sum 2.003",
+            "vigil_cache_entries{cache=\"proc_cache\"} 7",
+            "vigil_alert_channel_capacity 8192",
+            "vigil_dropped_alerts_total 0",
+            "vigil_sink_write_errors_total{sink=\"cef\"} 1",
+        ] {
+            assert!(
+                text.lines().any(|l| l == line),
+                "missing {line:?} in\n{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn serves_metrics_over_http() {
+        let addr = serve("127.0.0.1:0", || "vigil_up 1\n".to_string()).expect("serve");
+        let get = |path: &str| {
+            let mut stream = TcpStream::connect(addr).expect("connect");
+            write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").expect("request");
+            let mut response = String::new();
+            stream.read_to_string(&mut response).expect("response");
+            response
+        };
+
+        let ok = get("/metrics");
+        assert!(ok.starts_with("HTTP/1.1 200 OK\r\n"), "{ok}");
+        assert!(ok.contains("Content-Type: text/plain; version=0.0.4"));
+        assert!(ok.ends_with("\r\n\r\nvigil_up 1\n"));
+        assert!(get("/").starts_with("HTTP/1.1 404"));
+    }
+}
@@ -1,4 +1,9 @@
 pub mod cli;
 pub mod config;
 pub mod diag;
+pub mod identity;
+pub mod metrics;
+pub mod reload;
+pub mod templates;
+#[cfg(windows)]
 pub mod win;
@@ -0,0 +1,83 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// Polls the config file for changes and for a reload trigger file next to it.
+#[derive(Debug)]
+pub struct ConfigWatcher {
+    path: PathBuf,
+    trigger: PathBuf,
+    stamp: Option<(u64, SystemTime)>,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            trigger: Self::trigger_for(path),
+            stamp: stamp(path),
+        }
+    }
+
+    /// `config.toml` → `config.toml.reload`. Creating this file requests a reload.
+    pub fn trigger_for(path: &Path) -> PathBuf {
+        let mut name = path.as_os_str().to_owned();
+        name.push(".reload");
+        PathBuf::from(name)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// True when the config changed since the last poll or a trigger was left. The
+    /// trigger is consumed.
+    pub fn poll(&mut self) -> bool {
+        let triggered = fs::remove_file(&self.trigger).is_ok();
+        let current = stamp(&self.path);
+        let changed = current.is_some() && current != self.stamp;
+        self.stamp = current;
+        triggered || changed
+    }
+}
+
+fn stamp(path: &Path) -> Option<(u64, SystemTime)> {
+    let meta = fs::metadata(path).ok()?;
+    Some((meta.len(), meta.modified().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn poll_reports_edits_and_consumes_triggers() {
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let dir = std::env::temp_dir().join(format!("titan-vigil-reload-tests-{ts}"));
+        fs::create_dir_all(&dir).expect("create temp dir");
+        let path = dir.join("config.toml");
+        fs::write(&path, "[general]\n").expect("write config");
+
+        let mut watcher = ConfigWatcher::new(&path);
+        assert!(!watcher.poll());
+
+        fs::write(&path, "[general]\nquiet = true\n").expect("edit config");
+        assert!(watcher.poll());
+        assert!(!watcher.poll());
+
+        let trigger = ConfigWatcher::trigger_for(&path);
+        assert_eq!(trigger, dir.join("config.toml.reload"));
+        fs::write(&trigger, "").expect("write trigger");
+        assert!(watcher.poll());
+        assert!(!trigger.exists());
+        assert!(!watcher.poll());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,559 @@
+use crate::{
+    runtime::rules::{VOLUME, glob_body},
+    support::config::{MatchKind, ProtectedRule},
+};
+use anyhow::{Context, Result, bail};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+};
+
+/// Tokens that fan a rule out over user profiles instead of resolving to one value.
+const PROFILE_TOKENS: [&str; 4] = ["USERPROFILE", "LOCALAPPDATA", "APPDATA", "ANY_USER"];
+
+/// Profile directories that never belong to an interactive user.
+const SKIPPED_PROFILES: [&str; 4] = ["public", "default", "default user", "all users"];
+
+const MAX_DEPTH: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ProfileToken {
+    UserProfile,
+    LocalAppData,
+    AppData,
+    AnyUser,
+}
+
+impl ProfileToken {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "USERPROFILE" => Some(Self::UserProfile),
+            "LOCALAPPDATA" => Some(Self::LocalAppData),
+            "APPDATA" => Some(Self::AppData),
+            "ANY_USER" => Some(Self::AnyUser),
+            _ => None,
+        }
+    }
+
+    /// Path below the profile directory, or `None` for the bare user name.
+    fn subdir(self) -> Option<&'static str> {
+        match self {
+            Self::UserProfile => Some(""),
+            Self::LocalAppData => Some(r"\AppData\Local"),
+            Self::AppData => Some(r"\AppData\Roaming"),
+            Self::AnyUser => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Segment {
+    /// Pattern text as written in the rule.
+    Text(String),
+    /// A resolved variable, always matched literally.
+    Value(String),
+    Token(ProfileToken),
+}
+
+/// Expands `${NAME}` templates in protected rule patterns.
+///
+/// Plain names resolve from `[vars]`, then the process environment. Profile tokens
+/// produce one rule per profile directory under `PROFILES_ROOT` (default
+/// `%SystemDrive%\Users`), or a single any-user rule when no profile is known, as when
+/// replaying a journal from another machine. A leading drive letter matches any volume,
+/// and a templated `substring` rule that starts with a path becomes an anchored prefix.
+/// A `suffix` or `substring` rule opening with `${ANY_USER}` matches whole profile names.
+/// Variable values are always literal, even inside a `regex` rule.
+/// Literal kinds stay literal once every token resolves to a name; wildcards (an unknown
+/// user, `glob`, `regex`) compile to case-insensitive regexes.
+#[derive(Debug)]
+pub struct Templates {
+    vars: HashMap<String, String>,
+    root: String,
+    profiles: Vec<String>,
+}
+
+impl Templates {
+    /// `profiles` pins the profile names; when empty they are discovered on disk.
+    pub fn new(vars: &BTreeMap<String, String>, profiles: &[String]) -> Result<Self> {
+        let mut templates = Self {
+            vars: vars
+                .iter()
+                .map(|(k, v)| (k.to_ascii_uppercase(), v.clone()))
+                .collect(),
+            root: String::new(),
+            profiles: profiles.to_vec(),
+        };
+        templates.root = match templates.lookup("PROFILES_ROOT") {
+            Some(root) => templates.expand_vars(&root, 0)?,
+            None => {
+                let drive = std::env::var("SystemDrive").unwrap_or_else(|_| "C:".to_string());
+                format!(r"{drive}\Users")
+            }
+        };
+        if templates.profiles.is_empty() {
+            templates.profiles = discover_profiles(Path::new(&templates.root));
+        }
+        Ok(templates)
+    }
+
+    pub fn profiles(&self) -> &[String] {
+        &self.profiles
+    }
+
+    /// Expand one rule; untemplated rules pass through unchanged.
+    pub fn expand(&self, rule: ProtectedRule) -> Result<Vec<ProtectedRule>> {
+        if !rule.pattern.contains("${") {
+            return Ok(vec![rule]);
+        }
+
+        let mut segments = Vec::new();
+        self.parse_segments(&rule.pattern, 0, &mut segments)?;
+        let per_profile = segments.iter().any(|seg| matches!(seg, Segment::Token(_)));
+
+        let users: Vec<Option<&str>> = if !per_profile || self.profiles.is_empty() {
+            vec![None]
+        } else {
+            self.profiles.iter().map(|p| Some(p.as_str())).collect()
+        };
+
+        Ok(users
+            .into_iter()
+            .map(
+                |user| match self.literal(&segments, rule.match_kind, user) {
+                    Some((pattern, match_kind, any_volume)) => ProtectedRule {
+                        pattern,
+                        match_kind,
+                        any_volume,
+                        ..rule.clone()
+                    },
+                    None => ProtectedRule {
+                        pattern: self.render(&segments, rule.match_kind, user),
+                        match_kind: MatchKind::Regex,
+                        any_volume: false,
+                        ..rule.clone()
+                    },
+                },
+            )
+            .collect())
+    }
+
+    fn lookup(&self, name: &str) -> Option<String> {
+        self.vars
+            .get(name)
+            .cloned()
+            .or_else(|| std::env::var(name).ok())
+    }
+
+    /// Resolve every non-profile `${NAME}`, leaving profile tokens in place.
+    fn expand_vars(&self, text: &str, depth: usize) -> Result<String> {
+        if depth > MAX_DEPTH {
+            bail!("template variables nest more than {MAX_DEPTH} levels deep");
+        }
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find("${") {
+            out.push_str(&rest[..start]);
+            let after = &rest[start + 2..];
+            let Some(end) = after.find('}') else {
+                bail!("unterminated '${{' in '{text}'");
+            };
+            let name = after[..end].to_ascii_uppercase();
+            if PROFILE_TOKENS.contains(&name.as_str()) {
+                out.push_str(&rest[start..start + end + 3]);
+            } else {
+                let value = self
+                    .lookup(&name)
+                    .with_context(|| format!("unknown template variable '${{{name}}}'"))?;
+                out.push_str(&self.expand_vars(&value, depth + 1)?);
+            }
+            rest = &after[end + 1..];
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+
+    /// Split `text` into pattern text, resolved variable values and profile tokens.
+    /// Everything below `depth` 0 came from a variable, so it is all literal.
+    fn parse_segments(&self, text: &str, depth: usize, out: &mut Vec<Segment>) -> Result<()> {
+        if depth > MAX_DEPTH {
+            bail!("template variables nest more than {MAX_DEPTH} levels deep");
+        }
+        let push_text = |out: &mut Vec<Segment>, text: &str| {
+            if text.is_empty() {
+                return;
+            }
+            let text = text.to_string();
+            out.push(if depth == 0 {
+                Segment::Text(text)
+            } else {
+                Segment::Value(text)
+            });
+        };
+        let mut rest = text;
+        while let Some(start) = rest.find("${") {
+            push_text(out, &rest[..start]);
+            let after = &rest[start + 2..];
+            let Some(end) = after.find('}') else {
+                bail!("unterminated '${{' in '{text}'");
+            };
+            let name = after[..end].to_ascii_uppercase();
+            if let Some(token) = ProfileToken::parse(&name) {
+                out.push(Segment::Token(token));
+            } else {
+                let value = self
+                    .lookup(&name)
+                    .with_context(|| format!("unknown template variable '${{{name}}}'"))?;
+                self.parse_segments(&value, depth + 1, out)?;
+            }
+            rest = &after[end + 1..];
+        }
+        push_text(out, rest);
+        Ok(())
+    }
+
+    /// The rule as plain text with its literal kind and `any_volume` flag, when nothing
+    /// in it needs a wildcard and a volume can only appear at the start.
+    fn literal(
+        &self,
+        segments: &[Segment],
+        kind: MatchKind,
+        user: Option<&str>,
+    ) -> Option<(String, MatchKind, bool)> {
+        if matches!(kind, MatchKind::Glob | MatchKind::Regex) {
+            return None;
+        }
+        let mut text = String::new();
+        let mut leading_path = false;
+        let mut any_volume = false;
+        for (idx, seg) in segments.iter().enumerate() {
+            match seg {
+                Segment::Text(segment) | Segment::Value(segment) => match strip_drive(segment) {
+                    Some(rest) if idx == 0 => {
+                        leading_path = true;
+                        any_volume = true;
+                        text.push_str(rest);
+                    }
+                    _ => text.push_str(segment),
+                },
+                Segment::Token(token) => {
+                    let user = user?;
+                    if let Some(subdir) = token.subdir() {
+                        leading_path |= idx == 0;
+                        match strip_drive(&self.root) {
+                            Some(rest) if idx == 0 => {
+                                any_volume = true;
+                                text.push_str(rest);
+                            }
+                            Some(_) => return None,
+                            None => text.push_str(&self.root),
+                        }
+                        text.push('\\');
+                        text.push_str(user);
+                        text.push_str(subdir);
+                    } else {
+                        if idx == 0 && starts_component(kind) {
+                            text.push('\\');
+                        }
+                        text.push_str(user);
+                    }
+                }
+            }
+        }
+        if any_volume && !text.starts_with('\\') {
+            return None;
+        }
+        let kind = match kind {
+            MatchKind::Suffix if any_volume => return None,
+            MatchKind::Substring if leading_path => MatchKind::Prefix,
+            kind => kind,
+        };
+        Some((text, kind, any_volume))
+    }
+
+    fn render(&self, segments: &[Segment], kind: MatchKind, user: Option<&str>) -> String {
+        let mut body = String::new();
+        let mut leading_path = false;
+        for (idx, seg) in segments.iter().enumerate() {
+            match seg {
+                Segment::Text(text) | Segment::Value(text) => {
+                    let value = matches!(seg, Segment::Value(_));
+                    let mut text = text.as_str();
+                    // A regex rule's own text is left alone; a drive in a resolved value
+                    // still has to match device paths wherever it lands.
+                    let volume = if kind == MatchKind::Regex {
+                        value
+                    } else {
+                        idx == 0
+                    };
+                    if volume && let Some(rest) = strip_drive(text) {
+                        leading_path |= idx == 0;
+                        body.push_str(VOLUME);
+                        text = rest;
+                    }
+                    match kind {
+                        MatchKind::Glob => body.push_str(&glob_body(text)),
+                        MatchKind::Regex if !value => body.push_str(text),
+                        _ => body.push_str(&regex::escape(text)),
+                    }
+                }
+                Segment::Token(token) => {
+                    if let Some(subdir) = token.subdir() {
+                        leading_path |= idx == 0;
+                        match strip_drive(&self.root) {
+                            Some(rest) => {
+                                body.push_str(VOLUME);
+                                body.push_str(&regex::escape(rest));
+                            }
+                            None => body.push_str(&regex::escape(&self.root)),
+                        }
+                        body.push_str(r"\\");
+                        push_user(&mut body, user);
+                        body.push_str(&regex::escape(subdir));
+                    } else {
+                        if idx == 0 && starts_component(kind) {
+                            body.push_str(r"\\");
+                        }
+                        push_user(&mut body, user);
+                    }
+                }
+            }
+        }
+
+        match kind {
+            MatchKind::Regex => body,
+            MatchKind::Substring if !leading_path => body,
+            MatchKind::Substring | MatchKind::Prefix => format!("^{body}"),
+            MatchKind::Suffix => format!("{body}$"),
+            MatchKind::Exact | MatchKind::Glob => format!("^{body}$"),
+        }
+    }
+}
+
+/// Whether a bare user name opening a `kind` pattern must start at a path component,
+/// so `alice` does not also match inside `malice`.
+fn starts_component(kind: MatchKind) -> bool {
+    matches!(kind, MatchKind::Suffix | MatchKind::Substring)
+}
+
+fn push_user(body: &mut String, user: Option<&str>) {
+    match user {
+        Some(name) => body.push_str(&regex::escape(name)),
+        None => body.push_str(r"[^\\]+"),
+    }
+}
+
+fn strip_drive(text: &str) -> Option<&str> {
+    let bytes = text.as_bytes();
+    if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+        Some(&text[2..])
+    } else {
+        None
+    }
+}
+
+/// User profile directories under `root`, skipping shared and template profiles.
+///
+/// Junctions such as `Default User` are not followed.
+pub fn discover_profiles(root: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(root) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| !SKIPPED_PROFILES.contains(&name.to_lowercase().as_str()))
+        .collect();
+    names.sort();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::rules::{PathMatcher, RuleSet};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    const LOGIN_DATA: &str = r"\Google\Chrome\User Data\Default\Login Data";
+
+    fn templates(vars: &[(&str, &str)], profiles: &[&str]) -> Templates {
+        let vars = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let profiles: Vec<String> = profiles.iter().map(|p| p.to_string()).collect();
+        Templates::new(&vars, &profiles).expect("templates")
+    }
+
+    fn rule(pattern: &str, match_kind: MatchKind) -> ProtectedRule {
+        ProtectedRule {
+            pattern: pattern.to_string(),
+            name: "test".to_string(),
+            match_kind,
+            ..Default::default()
+        }
+    }
+
+    fn matchers(rules: Vec<ProtectedRule>) -> Vec<PathMatcher> {
+        rules
+            .iter()
+            .map(|r| PathMatcher::compile(r).expect("compile"))
+            .collect()
+    }
+
+    #[test]
+    fn profile_tokens_expand_per_profile_as_anchored_prefixes() {
+        let t = templates(
+            &[
+                ("PROFILES_ROOT", r"C:\Users"),
+                ("chrome", r"${LOCALAPPDATA}\Google\Chrome\User Data"),
+            ],
+            &["alice", "bob"],
+        );
+        let rules = t
+            .expand(rule(r"${CHROME}\Default\Login Data", MatchKind::Substring))
+            .expect("expand");
+        assert_eq!(rules.len(), 2);
+        assert!(
+            rules
+                .iter()
+                .all(|r| r.match_kind == MatchKind::Prefix && r.any_volume)
+        );
+        assert_eq!(
+            rules[0].pattern,
+            format!(r"\Users\alice\AppData\Local{LOGIN_DATA}")
+        );
+
+        let set = RuleSet::new(&rules).expect("rule set");
+        let alice = format!(r"C:\Users\alice\AppData\Local{LOGIN_DATA}");
+        let bob = format!(r"\Device\HarddiskVolume3\Users\Bob\AppData\Local{LOGIN_DATA}");
+        assert_eq!(set.find(&alice), Some(0));
+        assert_eq!(set.find(&bob), Some(1));
+        assert_eq!(
+            set.find(&format!(
+                r"C:\Temp\C:\Users\alice\AppData\Local{LOGIN_DATA}"
+            )),
+            None
+        );
+        assert_eq!(
+            set.find(&format!(r"C:\Users\mallory\AppData\Local{LOGIN_DATA}")),
+            None
+        );
+        assert_eq!(
+            set.find(&format!(
+                r"\Device\HarddiskVolume3\Temp\Users\alice\AppData\Local{LOGIN_DATA}"
+            )),
+            None
+        );
+    }
+
+    #[test]
+    fn unknown_users_and_globs_stay_regexes() {
+        let t = templates(&[("PROFILES_ROOT", r"C:\Users")], &["alice"]);
+        let rules = t
+            .expand(rule(r"${ANY_USER}\secrets", MatchKind::Suffix))
+            .expect("expand");
+        assert_eq!(rules[0].match_kind, MatchKind::Suffix);
+        assert_eq!(rules[0].pattern, r"\alice\secrets");
+
+        let rules = t
+            .expand(rule(r"${LOCALAPPDATA}\*\Login Data", MatchKind::Glob))
+            .expect("expand");
+        assert_eq!(rules[0].match_kind, MatchKind::Regex);
+        let m = &matchers(rules)[0];
+        assert!(m.is_match(r"d:\users\alice\appdata\local\chrome\login data"));
+    }
+
+    #[test]
+    fn any_user_suffix_rules_match_whole_profile_names() {
+        let t = templates(&[("PROFILES_ROOT", r"C:\Users")], &["alice"]);
+        for kind in [MatchKind::Suffix, MatchKind::Substring] {
+            let set = RuleSet::new(
+                &t.expand(rule(r"${ANY_USER}\secrets", kind))
+                    .expect("expand"),
+            )
+            .expect("rule set");
+            assert_eq!(set.find(r"C:\Users\alice\secrets"), Some(0));
+            assert_eq!(set.find(r"C:\Users\malice\secrets"), None);
+        }
+
+        let t = templates(&[("PROFILES_ROOT", r"Z:\no-such-profiles-root")], &[]);
+        let set = RuleSet::new(
+            &t.expand(rule(r"${ANY_USER}\secrets", MatchKind::Suffix))
+                .expect("expand"),
+        )
+        .expect("rule set");
+        assert_eq!(set.find(r"C:\Users\malice\secrets"), Some(0));
+        assert_eq!(set.find(r"C:\secrets"), None);
+    }
+
+    #[test]
+    fn variables_stay_literal_inside_regex_rules() {
+        let t = templates(
+            &[
+                ("PROFILES_ROOT", r"C:\Users"),
+                ("VAULT", r"C:\Users\bob\AppData\Local\Vault.d"),
+            ],
+            &["alice"],
+        );
+        let rules = t
+            .expand(rule(r"^${VAULT}\\[^\\]+\.kdbx$", MatchKind::Regex))
+            .expect("expand");
+        assert_eq!(rules[0].match_kind, MatchKind::Regex);
+        let m = &matchers(rules)[0];
+        assert!(m.is_match(r"c:\users\bob\appdata\local\vault.d\main.kdbx"));
+        assert!(m.is_match(r"\device\harddiskvolume3\users\bob\appdata\local\vault.d\main.kdbx"));
+        assert!(!m.is_match(r"c:\users\bob\appdata\local\vaultxd\main.kdbx"));
+        assert!(!m.is_match(r"c:\users\bob\appdata\local\vault.d\old\main.kdbx"));
+    }
+
+    #[test]
+    fn any_user_falls_back_to_a_single_component_wildcard() {
+        let t = templates(&[("PROFILES_ROOT", r"Z:\no-such-profiles-root")], &[]);
+        assert!(t.profiles().is_empty());
+
+        let rules = t
+            .expand(rule(
+                r"C:\Users\${ANY_USER}\Documents\*.kdbx",
+                MatchKind::Glob,
+            ))
+            .expect("expand");
+        assert_eq!(rules.len(), 1);
+        let m = &matchers(rules)[0];
+        assert!(m.is_match(r"c:\users\carol\documents\vault.kdbx"));
+        assert!(!m.is_match(r"c:\users\carol\documents\old\vault.kdbx"));
+    }
+
+    #[test]
+    fn untemplated_rules_pass_through_and_unknown_names_fail() {
+        let t = templates(&[], &["alice"]);
+        let plain = rule(r"\cookies", MatchKind::Substring);
+        let rules = t.expand(plain.clone()).expect("expand");
+        assert_eq!(rules[0].pattern, plain.pattern);
+        assert_eq!(rules[0].match_kind, MatchKind::Substring);
+
+        let err = t
+            .expand(rule(r"${NO_SUCH_TITAN_VAR}\x", MatchKind::Substring))
+            .expect_err("unknown variable");
+        assert!(format!("{err:#}").contains("NO_SUCH_TITAN_VAR"));
+    }
+
+    #[test]
+    fn discover_profiles_skips_shared_profiles_and_files() {
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let root = std::env::temp_dir().join(format!("titan-vigil-profiles-{ts}"));
+        for dir in ["bob", "alice", "Public", "Default"] {
+            fs::create_dir_all(root.join(dir)).expect("create profile");
+        }
+        fs::write(root.join("desktop.ini"), "").expect("write file");
+
+        let profiles = discover_profiles(&root);
+        let _ = fs::remove_dir_all(&root);
+        assert_eq!(profiles, vec!["alice".to_string(), "bob".to_string()]);
+    }
+}
@@ -1,6 +1,10 @@
 use crate::{
     runtime::engine::Engine,
     support::{diag, win::to_wide},
+    telemetry::{
+        event::{FileOperation, TelemetryEvent},
+        source::EventSource,
+    },
 };
 use anyhow::{Result, anyhow};
 use std::{
@@ -35,6 +39,9 @@ const KERNEL_FILE_GUID: GUID = GUID::from_u128(0xedd08927_9cc4_4e65_b970_c2560fb
 
 const INVALID_TRACE_HANDLE: u64 = u64::MAX;
 
+const PROCESS_START_EVENT_ID: u16 = 1;
+const PROCESS_STOP_EVENT_ID: u16 = 2;
+
 struct CallbackCtx {
     engine: Arc<Engine>,
 }
@@ -54,6 +61,17 @@ impl Drop for EtwSession {
     }
 }
 
+/// Live Kernel-Process/Kernel-File ETW session.
+pub struct EtwSource;
+
+impl EventSource for EtwSource {
+    type Session = EtwSession;
+
+    fn start(self, engine: Arc<Engine>) -> Result<Self::Session> {
+        start_etw(engine)
+    }
+}
+
 pub fn start_etw(engine: Arc<Engine>) -> Result<EtwSession> {
     diag::startup("ETW start requested");
     let cleanup_result = cleanup_existing_vigil_session();
@@ -299,68 +317,99 @@ unsafe extern "system" fn event_record_callback(record: *mut EVENT_RECORD) {
     }
 
     let engine = unsafe { &(*ctx).engine };
+    let (provider, event_id) = unsafe {
+        (
+            (*record).EventHeader.ProviderId,
+            (*record).EventHeader.EventDescriptor.Id,
+        )
+    };
+    engine
+        .metrics()
+        .record_event(provider_name(&provider), event_id);
+
+    if let Some(event) = normalize_record(record) {
+        engine.handle_event(event);
+    }
+}
+
+fn provider_name(provider: &GUID) -> &'static str {
+    if *provider == KERNEL_PROCESS_GUID {
+        "Microsoft-Windows-Kernel-Process"
+    } else if *provider == KERNEL_FILE_GUID {
+        "Microsoft-Windows-Kernel-File"
+    } else {
+        "other"
+    }
+}
+
+fn normalize_record(record: *mut EVENT_RECORD) -> Option<TelemetryEvent> {
     let provider = unsafe { (*record).EventHeader.ProviderId };
     let event_id = unsafe { (*record).EventHeader.EventDescriptor.Id };
-    let pid = unsafe { (*record).EventHeader.ProcessId };
+    let header_pid = unsafe { (*record).EventHeader.ProcessId };
 
     if provider == KERNEL_PROCESS_GUID {
-        let image_name = match get_property_string(record, "ImageName") {
-            Some(v) => v,
-            None => return,
+        // The header carries the creating process; the subject lives in the payload.
+        let pid = get_property_u64(record, "ProcessID")
+            .map(|v| v as u32)
+            .unwrap_or(header_pid);
+        return match event_id {
+            PROCESS_START_EVENT_ID => Some(TelemetryEvent::ProcessStart {
+                pid,
+                image: get_property_string(record, "ImageName")?,
+                cmdline: get_property_string(record, "CommandLine"),
+                parent_pid: get_property_u64(record, "ParentProcessID").map(|v| v as u32),
+                start_time: get_property_u64(record, "CreateTime"),
+                // Resolved when an alert needs it, not on the callback.
+                user: None,
+            }),
+            PROCESS_STOP_EVENT_ID => Some(TelemetryEvent::ProcessStop {
+                pid,
+                start_time: get_property_u64(record, "CreateTime"),
+            }),
+            _ => None,
         };
-        let cmdline = get_property_string(record, "CommandLine");
-        engine.on_process_start(pid, image_name, cmdline);
-        return;
     }
 
     if provider != KERNEL_FILE_GUID {
-        return;
+        return None;
     }
 
-    if event_id != 12 && event_id != 0 && event_id != 65 && event_id != 66 {
-        return;
+    let operation = FileOperation::from_kernel_file_event(event_id);
+    if operation.is_none() && event_id != 0 && event_id != 65 && event_id != 66 {
+        return None;
     }
 
     let file_key = get_property_u64(record, "FileKey")
         .or_else(|| get_property_u64(record, "FileObject"))
         .unwrap_or(0);
 
-    let file_object = get_property_u64(record, "FileObject").unwrap_or(0);
-
     if event_id == 0 {
         if file_key == 0 {
-            return;
+            return None;
         }
-
-        let file_name = match get_property_string(record, "FileName") {
-            Some(v) => v,
-            None => return,
-        };
-
-        engine.on_file_name_mapping(file_key, file_name);
-        return;
+        return Some(TelemetryEvent::FileNameMapping {
+            file_key,
+            file_name: get_property_string(record, "FileName")?,
+        });
     }
 
     if event_id == 65 || event_id == 66 {
-        if file_key != 0 {
-            engine.clear_file_key(file_key);
+        if file_key == 0 {
+            return None;
         }
-        return;
+        return Some(TelemetryEvent::FileClose { file_key });
     }
 
-    let target = get_property_string(record, "FileName").or_else(|| {
-        if file_key != 0 {
-            engine.resolve_file_key(file_key)
-        } else {
-            None
-        }
-    });
-
-    let Some(target) = target else {
-        return;
-    };
-
-    engine.handle_file_access(pid, event_id, target, file_object);
+    // DeletePath/RenamePath name the file in `FilePath`; read/write carry only the key.
+    Some(TelemetryEvent::FileAccess {
+        pid: header_pid,
+        event_id,
+        operation: operation?,
+        file_name: get_property_string(record, "FileName")
+            .or_else(|| get_property_string(record, "FilePath")),
+        file_key,
+        file_object: get_property_u64(record, "FileObject").unwrap_or(0),
+    })
 }
 
 fn get_property_bytes(record: *mut EVENT_RECORD, name: &str) -> Option<Vec<u8>> {
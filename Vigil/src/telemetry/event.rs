@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+
+/// File operation carried by a [`TelemetryEvent::FileAccess`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FileOperation {
+    #[default]
+    Create,
+    Read,
+    Write,
+    Delete,
+    Rename,
+    SetInfo,
+}
+
+impl FileOperation {
+    /// Operation for a Microsoft-Windows-Kernel-File event id, if it is one we ingest.
+    pub fn from_kernel_file_event(event_id: u16) -> Option<Self> {
+        match event_id {
+            12 | 30 => Some(Self::Create),
+            15 => Some(Self::Read),
+            16 => Some(Self::Write),
+            17 => Some(Self::SetInfo),
+            18 | 26 => Some(Self::Delete),
+            19 | 27 => Some(Self::Rename),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Create => "create",
+            Self::Read => "read",
+            Self::Write => "write",
+            Self::Delete => "delete",
+            Self::Rename => "rename",
+            Self::SetInfo => "set_info",
+        }
+    }
+}
+
+/// Source-independent telemetry consumed by the engine.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TelemetryEvent {
+    ProcessStart {
+        pid: u32,
+        image: String,
+        cmdline: Option<String>,
+        #[serde(default)]
+        parent_pid: Option<u32>,
+        /// Creation time as a Windows FILETIME (100 ns ticks since 1601).
+        #[serde(default)]
+        start_time: Option<u64>,
+        /// `DOMAIN\user` owning the process token. Live ETW leaves it unset; the engine
+        /// resolves it when the process alerts.
+        #[serde(default)]
+        user: Option<String>,
+    },
+    ProcessStop {
+        pid: u32,
+        /// Creation FILETIME of the exiting incarnation, when the source reports it.
+        #[serde(default)]
+        start_time: Option<u64>,
+    },
+    /// Binds a FileKey to a path so later events that only carry the key can be resolved.
+    FileNameMapping {
+        file_key: u64,
+        file_name: String,
+    },
+    /// `file_name` is optional; the engine falls back to the FileKey mapping when absent.
+    FileAccess {
+        pid: u32,
+        event_id: u16,
+        operation: FileOperation,
+        file_name: Option<String>,
+        file_key: u64,
+        file_object: u64,
+    },
+    FileClose {
+        file_key: u64,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kernel_file_event_ids_map_to_operations() {
+        assert_eq!(
+            FileOperation::from_kernel_file_event(12),
+            Some(FileOperation::Create)
+        );
+        assert_eq!(
+            FileOperation::from_kernel_file_event(26),
+            Some(FileOperation::Delete)
+        );
+        assert_eq!(
+            FileOperation::from_kernel_file_event(27),
+            Some(FileOperation::Rename)
+        );
+        assert_eq!(FileOperation::from_kernel_file_event(14), None);
+        assert_eq!(
+            serde_json::to_string(&FileOperation::SetInfo).expect("serialize"),
+            "\"set_info\""
+        );
+    }
+}
@@ -0,0 +1,559 @@
+use crate::{
+    runtime::{
+        engine::Engine,
+        host::{Host, NullHost},
+    },
+    telemetry::{event::TelemetryEvent, source::EventSource},
+    trust::verdict::{RevocationPolicy, TrustResult},
+};
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Records buffered before the journal is flushed, whichever comes first of this and
+/// [`FLUSH_INTERVAL`].
+const FLUSH_EVERY: usize = 256;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// One NDJSON line of a telemetry journal.
+///
+/// Besides the events themselves, the journal keeps the host lookups the engine made
+/// while processing them, so a replay reaches the same trust decisions without the
+/// original machine.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "record", rename_all = "snake_case")]
+pub enum JournalRecord {
+    Event {
+        ts_ms: u64,
+        event: TelemetryEvent,
+    },
+    ProcessImage {
+        pid: u32,
+        image: Option<String>,
+    },
+    Signature {
+        path: String,
+        trust: TrustResult,
+    },
+    ImageHash {
+        path: String,
+        sha256: Option<String>,
+    },
+    ProcessIds {
+        pids: Vec<u32>,
+    },
+    FileObjects {
+        objects: Vec<(u64, Vec<u32>)>,
+    },
+    ProcessUser {
+        pid: u32,
+        user: Option<String>,
+    },
+    ProcessStartTime {
+        pid: u32,
+        start_time: Option<u64>,
+    },
+}
+
+#[derive(Debug)]
+pub struct JournalWriter {
+    writer: Mutex<PendingWriter>,
+}
+
+#[derive(Debug)]
+struct PendingWriter {
+    out: BufWriter<File>,
+    pending: usize,
+    last_flush: Instant,
+}
+
+impl JournalWriter {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("failed to open journal {}", path.display()))?;
+        Ok(Self {
+            writer: Mutex::new(PendingWriter {
+                out: BufWriter::new(file),
+                pending: 0,
+                last_flush: Instant::now(),
+            }),
+        })
+    }
+
+    /// Buffer `record`; the journal is flushed every [`FLUSH_EVERY`] records or
+    /// [`FLUSH_INTERVAL`], and by [`JournalWriter::flush`].
+    pub fn append(&self, record: &JournalRecord) -> Result<()> {
+        let mut w = self.writer.lock();
+        serde_json::to_writer(&mut w.out, record)?;
+        w.out.write_all(b"\n")?;
+        w.pending += 1;
+        if w.pending >= FLUSH_EVERY || w.last_flush.elapsed() >= FLUSH_INTERVAL {
+            w.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Write out buffered records; the live sensor calls this on its housekeeping tick.
+    pub fn flush(&self) -> Result<()> {
+        self.writer.lock().flush()
+    }
+
+    pub fn record_event(&self, event: &TelemetryEvent) {
+        let ts_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        self.append_logged(&JournalRecord::Event {
+            ts_ms,
+            event: event.clone(),
+        });
+    }
+
+    fn append_logged(&self, record: &JournalRecord) {
+        if let Err(e) = self.append(record) {
+            eprintln!("[JOURNAL] {:?}", e);
+        }
+    }
+}
+
+impl PendingWriter {
+    fn flush(&mut self) -> Result<()> {
+        self.out.flush().context("failed to flush journal")?;
+        self.pending = 0;
+        self.last_flush = Instant::now();
+        Ok(())
+    }
+}
+
+pub fn read_journal(path: &Path) -> Result<Vec<JournalRecord>> {
+    let file =
+        File::open(path).with_context(|| format!("failed to open journal {}", path.display()))?;
+    let mut records = Vec::new();
+    for (idx, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.with_context(|| format!("failed to read journal {}", path.display()))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = serde_json::from_str(&line)
+            .with_context(|| format!("invalid journal record at line {}", idx + 1))?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+pub fn journal_events(records: Vec<JournalRecord>) -> impl Iterator<Item = TelemetryEvent> {
+    records.into_iter().filter_map(|record| match record {
+        JournalRecord::Event { event, .. } => Some(event),
+        _ => None,
+    })
+}
+
+/// Replays the events of a journal in order. Each event first advances the engine's
+/// clock to its recorded `ts_ms`, so suppression and correlation windows close as
+/// they did live.
+pub struct JournalSource(pub Vec<JournalRecord>);
+
+impl EventSource for JournalSource {
+    type Session = ();
+
+    fn start(self, engine: Arc<Engine>) -> Result<Self::Session> {
+        for record in self.0 {
+            if let JournalRecord::Event { ts_ms, event } = record {
+                engine.advance_clock(ts_ms);
+                engine.handle_event(event);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Wraps the live host and journals every answer it gives the engine.
+#[derive(Debug)]
+pub struct RecordingHost {
+    inner: Arc<dyn Host>,
+    journal: Arc<JournalWriter>,
+}
+
+impl RecordingHost {
+    pub fn new(inner: Arc<dyn Host>, journal: Arc<JournalWriter>) -> Self {
+        Self { inner, journal }
+    }
+}
+
+impl Host for RecordingHost {
+    fn verify_signature(&self, path: &str, revocation: RevocationPolicy) -> TrustResult {
+        let trust = self.inner.verify_signature(path, revocation);
+        self.journal.append_logged(&JournalRecord::Signature {
+            path: path.to_string(),
+            trust: trust.clone(),
+        });
+        trust
+    }
+
+    fn process_image_path(&self, pid: u32) -> Option<String> {
+        let image = self.inner.process_image_path(pid);
+        self.journal.append_logged(&JournalRecord::ProcessImage {
+            pid,
+            image: image.clone(),
+        });
+        image
+    }
+
+    fn image_sha256(&self, path: &str) -> Option<String> {
+        let sha256 = self.inner.image_sha256(path);
+        self.journal.append_logged(&JournalRecord::ImageHash {
+            path: path.to_string(),
+            sha256: sha256.clone(),
+        });
+        sha256
+    }
+
+    fn enum_process_ids(&self) -> Result<Vec<u32>> {
+        let pids = self.inner.enum_process_ids()?;
+        self.journal
+            .append_logged(&JournalRecord::ProcessIds { pids: pids.clone() });
+        Ok(pids)
+    }
+
+    fn file_objects_for_pids(&self, pids: &[u32]) -> Result<HashMap<u64, HashSet<u32>>> {
+        let objects = self.inner.file_objects_for_pids(pids)?;
+        self.journal.append_logged(&JournalRecord::FileObjects {
+            objects: objects
+                .iter()
+                .map(|(obj, owners)| (*obj, owners.iter().copied().collect()))
+                .collect(),
+        });
+        Ok(objects)
+    }
+
+    fn process_user(&self, pid: u32) -> Option<String> {
+        let user = self.inner.process_user(pid);
+        self.journal.append_logged(&JournalRecord::ProcessUser {
+            pid,
+            user: user.clone(),
+        });
+        user
+    }
+
+    fn process_start_time(&self, pid: u32) -> Option<u64> {
+        let start_time = self.inner.process_start_time(pid);
+        self.journal
+            .append_logged(&JournalRecord::ProcessStartTime { pid, start_time });
+        start_time
+    }
+}
+
+/// Answers host lookups from a recorded journal.
+///
+/// Lookups are indexed up front and the first observation wins, so a PID reused
+/// within one capture resolves to its earliest image.
+#[derive(Debug, Default)]
+pub struct ReplayHost {
+    images: HashMap<u32, String>,
+    signatures: HashMap<String, TrustResult>,
+    hashes: HashMap<String, String>,
+    pids: Vec<u32>,
+    file_objects: HashMap<u64, HashSet<u32>>,
+    users: HashMap<u32, String>,
+    start_times: HashMap<u32, u64>,
+}
+
+impl ReplayHost {
+    pub fn from_records(records: &[JournalRecord]) -> Self {
+        let mut host = Self::default();
+        for record in records {
+            match record {
+                JournalRecord::ProcessImage {
+                    pid,
+                    image: Some(image),
+                } => {
+                    host.images.entry(*pid).or_insert_with(|| image.clone());
+                }
+                JournalRecord::Event {
+                    event: TelemetryEvent::ProcessStart { pid, image, .. },
+                    ..
+                } => {
+                    host.images.entry(*pid).or_insert_with(|| image.clone());
+                }
+                JournalRecord::ProcessUser {
+                    pid,
+                    user: Some(user),
+                } => {
+                    host.users.entry(*pid).or_insert_with(|| user.clone());
+                }
+                JournalRecord::ProcessStartTime {
+                    pid,
+                    start_time: Some(start_time),
+                } => {
+                    host.start_times.entry(*pid).or_insert(*start_time);
+                }
+                JournalRecord::Signature { path, trust } => {
+                    host.signatures
+                        .entry(path.clone())
+                        .or_insert_with(|| trust.clone());
+                }
+                JournalRecord::ImageHash {
+                    path,
+                    sha256: Some(sha256),
+                } => {
+                    host.hashes
+                        .entry(path.clone())
+                        .or_insert_with(|| sha256.clone());
+                }
+                JournalRecord::ProcessIds { pids } if host.pids.is_empty() => {
+                    host.pids = pids.clone();
+                }
+                JournalRecord::FileObjects { objects } => {
+                    for (obj, owners) in objects {
+                        host.file_objects
+                            .entry(*obj)
+                            .or_default()
+                            .extend(owners.iter().copied());
+                    }
+                }
+                _ => {}
+            }
+        }
+        host
+    }
+}
+
+impl Host for ReplayHost {
+    fn verify_signature(&self, path: &str, revocation: RevocationPolicy) -> TrustResult {
+        self.signatures
+            .get(path)
+            .cloned()
+            .unwrap_or_else(|| NullHost.verify_signature(path, revocation))
+    }
+
+    fn process_image_path(&self, pid: u32) -> Option<String> {
+        self.images
+            .get(&pid)
+            .cloned()
+            .or_else(|| NullHost.process_image_path(pid))
+    }
+
+    fn image_sha256(&self, path: &str) -> Option<String> {
+        self.hashes.get(path).cloned()
+    }
+
+    fn enum_process_ids(&self) -> Result<Vec<u32>> {
+        Ok(self.pids.clone())
+    }
+
+    fn file_objects_for_pids(&self, pids: &[u32]) -> Result<HashMap<u64, HashSet<u32>>> {
+        let wanted: HashSet<u32> = pids.iter().copied().collect();
+        let mut out: HashMap<u64, HashSet<u32>> = HashMap::new();
+        for (obj, owners) in &self.file_objects {
+            let owners: HashSet<u32> = owners.intersection(&wanted).copied().collect();
+            if !owners.is_empty() {
+                out.insert(*obj, owners);
+            }
+        }
+        Ok(out)
+    }
+
+    fn process_user(&self, pid: u32) -> Option<String> {
+        self.users.get(&pid).cloned()
+    }
+
+    fn process_start_time(&self, pid: u32) -> Option<u64> {
+        self.start_times.get(&pid).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        output::alerts::Alert,
+        runtime::engine::Engine,
+        support::config::Config,
+        telemetry::{
+            event::FileOperation,
+            source::{EventSource, IterSource},
+        },
+    };
+    use crossbeam_channel::unbounded;
+    use std::fs;
+
+    const CONFIG: &str = r#"
+[allowlist]
+signer_subject_allow = ["Google LLC"]
+
+[[watch.protected]]
+substring = "\\google\\chrome\\user data\\default\\login data"
+name = "Chrome Passwords"
+"#;
+
+    const LOGIN_DATA: &str =
+        r"C:\Users\bob\AppData\Local\Google\Chrome\User Data\Default\Login Data";
+
+    /// Live stand-in: only `chrome.exe` carries a valid signature.
+    #[derive(Debug)]
+    struct ChromeSignedHost;
+
+    impl Host for ChromeSignedHost {
+        fn verify_signature(&self, path: &str, revocation: RevocationPolicy) -> TrustResult {
+            if path != "chrome.exe" {
+                return NullHost.verify_signature(path, revocation);
+            }
+            TrustResult {
+                is_signed: true,
+                is_trusted: true,
+                signer_subject: Some("Google LLC".to_string()),
+                signer_thumbprint: None,
+            }
+        }
+
+        fn process_image_path(&self, pid: u32) -> Option<String> {
+            NullHost.process_image_path(pid)
+        }
+
+        fn image_sha256(&self, path: &str) -> Option<String> {
+            NullHost.image_sha256(path)
+        }
+
+        fn enum_process_ids(&self) -> Result<Vec<u32>> {
+            NullHost.enum_process_ids()
+        }
+
+        fn file_objects_for_pids(&self, pids: &[u32]) -> Result<HashMap<u64, HashSet<u32>>> {
+            NullHost.file_objects_for_pids(pids)
+        }
+    }
+
+    fn temp_journal() -> std::path::PathBuf {
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        std::env::temp_dir().join(format!("titan-vigil-journal-{ts}.ndjson"))
+    }
+
+    fn access(pid: u32) -> TelemetryEvent {
+        TelemetryEvent::FileAccess {
+            pid,
+            event_id: 12,
+            operation: FileOperation::Create,
+            file_name: Some(LOGIN_DATA.to_string()),
+            file_key: 0,
+            file_object: 0,
+        }
+    }
+
+    #[test]
+    fn records_round_trip_through_ndjson() {
+        let path = temp_journal();
+        let writer = JournalWriter::create(&path).expect("create journal");
+        writer.record_event(&access(42));
+        writer
+            .append(&JournalRecord::FileObjects {
+                objects: vec![(0xabc, vec![1, 2])],
+            })
+            .expect("append");
+        drop(writer);
+
+        let records = read_journal(&path).expect("read journal");
+        let _ = fs::remove_file(&path);
+        assert_eq!(records.len(), 2);
+        let events: Vec<_> = journal_events(records).collect();
+        assert_eq!(events, vec![access(42)]);
+    }
+
+    #[test]
+    fn replay_reproduces_recorded_trust_decisions() {
+        let path = temp_journal();
+        let journal = Arc::new(JournalWriter::create(&path).expect("create journal"));
+        let host = RecordingHost::new(Arc::new(ChromeSignedHost), journal.clone());
+        let (tx, _rx) = unbounded::<Alert>();
+        let cfg = Config::from_toml(CONFIG).expect("config");
+        let engine = Arc::new(
+            Engine::with_host(cfg.clone(), tx, Arc::new(host))
+                .expect("engine")
+                .with_journal(journal),
+        );
+        IterSource(vec![
+            TelemetryEvent::ProcessStart {
+                pid: 10,
+                image: "chrome.exe".to_string(),
+                cmdline: None,
+                parent_pid: None,
+                start_time: None,
+                user: None,
+            },
+            TelemetryEvent::ProcessStart {
+                pid: 20,
+                image: "stealer.exe".to_string(),
+                cmdline: None,
+                parent_pid: None,
+                start_time: None,
+                user: None,
+            },
+            access(10),
+            access(20),
+        ])
+        .start(engine)
+        .expect("record");
+
+        let records = read_journal(&path).expect("read journal");
+        let _ = fs::remove_file(&path);
+        let host = ReplayHost::from_records(&records);
+        let (tx, rx) = unbounded::<Alert>();
+        let engine = Arc::new(Engine::with_host(cfg, tx, Arc::new(host)).expect("engine"));
+        IterSource(journal_events(records))
+            .start(engine.clone())
+            .expect("replay");
+        drop(engine);
+
+        let alerts: Vec<Alert> = rx.iter().collect();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].pid, 20);
+        assert_eq!(alerts[0].process, "stealer.exe");
+    }
+
+    #[test]
+    fn replay_runs_suppression_windows_on_recorded_time() {
+        let event = |ts_ms, event| JournalRecord::Event { ts_ms, event };
+        let records = vec![
+            event(
+                1_000,
+                TelemetryEvent::ProcessStart {
+                    pid: 20,
+                    image: "stealer.exe".to_string(),
+                    cmdline: None,
+                    parent_pid: None,
+                    start_time: None,
+                    user: None,
+                },
+            ),
+            event(1_000, access(20)),
+            event(1_500, access(20)),
+            // Past general.suppress_ms (1500) of recorded time, however fast the replay.
+            event(4_000, access(20)),
+        ];
+        let host = ReplayHost::from_records(&records);
+        let (tx, rx) = unbounded::<Alert>();
+        let cfg = Config::from_toml(CONFIG).expect("config");
+        let engine = Arc::new(Engine::with_host(cfg, tx, Arc::new(host)).expect("engine"));
+        JournalSource(records)
+            .start(engine.clone())
+            .expect("replay");
+        drop(engine);
+
+        let alerts: Vec<Alert> = rx.iter().collect();
+        assert_eq!(alerts.len(), 2);
+        assert_eq!(alerts[1].suppressed_count, 1);
+    }
+}
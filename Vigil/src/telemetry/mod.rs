@@ -1,2 +1,7 @@
+#[cfg(windows)]
 pub mod etw;
+pub mod event;
+#[cfg(windows)]
 pub mod handles;
+pub mod journal;
+pub mod source;
@@ -0,0 +1,28 @@
+use crate::{runtime::engine::Engine, telemetry::event::TelemetryEvent};
+use anyhow::Result;
+use std::sync::Arc;
+
+/// A producer of normalized telemetry for the engine.
+pub trait EventSource {
+    /// Keeps a live source running; dropping it stops delivery.
+    type Session;
+
+    fn start(self, engine: Arc<Engine>) -> Result<Self::Session>;
+}
+
+/// Delivers a finite sequence of events synchronously (fixtures, captured streams).
+pub struct IterSource<I>(pub I);
+
+impl<I> EventSource for IterSource<I>
+where
+    I: IntoIterator<Item = TelemetryEvent>,
+{
+    type Session = ();
+
+    fn start(self, engine: Arc<Engine>) -> Result<Self::Session> {
+        for event in self.0 {
+            engine.handle_event(event);
+        }
+        Ok(())
+    }
+}
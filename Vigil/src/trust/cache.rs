@@ -0,0 +1,453 @@
+use crate::{
+    support::config::TrustCacheConfig,
+    trust::verdict::{RevocationPolicy, TrustResult},
+};
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const CACHE_VERSION: u32 = 2;
+
+/// Size and modification time of an image, used to tell when a memoized hash is stale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileIdentity {
+    pub size: u64,
+    pub mtime_ns: u64,
+}
+
+impl FileIdentity {
+    pub fn of(path: &Path) -> Option<Self> {
+        let meta = fs::metadata(path).ok()?;
+        let mtime = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Self {
+            size: meta.len(),
+            mtime_ns: mtime.as_nanos() as u64,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedVerdict {
+    revocation: RevocationPolicy,
+    verified_at: u64,
+    trust: TrustResult,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheFile {
+    version: u32,
+    entries: HashMap<String, CachedVerdict>,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    entries: HashMap<String, CachedVerdict>,
+    dirty: bool,
+}
+
+/// Signature verdicts keyed by image SHA-256, valid while the revocation policy is
+/// unchanged and the entry is younger than the TTL.
+#[derive(Debug)]
+pub struct TrustCache {
+    path: Option<PathBuf>,
+    ttl_secs: u64,
+    max_entries: usize,
+    state: Mutex<CacheState>,
+}
+
+impl TrustCache {
+    /// In-memory cache; `save` is a no-op.
+    pub fn new(cfg: &TrustCacheConfig) -> Self {
+        Self {
+            path: None,
+            ttl_secs: cfg.ttl_secs,
+            max_entries: cfg.max_entries.max(1),
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    /// Cache persisted at `path`, whose directory and file are first restricted to
+    /// SYSTEM and Administrators; when that fails the cache stays in memory. A file
+    /// that is unreadable, from another version or holds any malformed entry is
+    /// deleted and the cache starts empty.
+    pub fn load(cfg: &TrustCacheConfig, path: PathBuf) -> Self {
+        let mut cache = Self::new(cfg);
+        if let Err(e) = restrict_cache_path(&path) {
+            eprintln!("[TRUST_CACHE] {e:?}; keeping verdicts in memory only");
+            return cache;
+        }
+        let now = unix_now();
+        match read_cache_file(&path) {
+            Ok(Some(file)) if is_valid(&file, now) => {
+                let entries = file
+                    .entries
+                    .into_iter()
+                    .filter(|(_, v)| now.saturating_sub(v.verified_at) <= cache.ttl_secs)
+                    .collect();
+                cache.state.get_mut().entries = entries;
+            }
+            Ok(None) => {}
+            Ok(Some(_)) => {
+                eprintln!(
+                    "[TRUST_CACHE] {} failed validation; discarding it",
+                    path.display()
+                );
+                let _ = fs::remove_file(&path);
+            }
+            Err(e) => {
+                eprintln!("[TRUST_CACHE] {e:?}; discarding it");
+                let _ = fs::remove_file(&path);
+            }
+        }
+        cache.path = Some(path);
+        cache
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Cached verdict for the image with hash `sha256`, or the result of `verify`.
+    pub fn get_or_verify(
+        &self,
+        sha256: &str,
+        revocation: RevocationPolicy,
+        verify: impl FnOnce() -> TrustResult,
+    ) -> TrustResult {
+        let now = unix_now();
+
+        if let Some(hit) = self.state.lock().entries.get(sha256)
+            && hit.revocation == revocation
+            && now.saturating_sub(hit.verified_at) <= self.ttl_secs
+        {
+            return hit.trust.clone();
+        }
+
+        let trust = verify();
+        let mut state = self.state.lock();
+        if state.entries.len() >= self.max_entries && !state.entries.contains_key(sha256) {
+            self.evict(&mut state, now);
+        }
+        state.entries.insert(
+            sha256.to_string(),
+            CachedVerdict {
+                revocation,
+                verified_at: now,
+                trust: trust.clone(),
+            },
+        );
+        state.dirty = true;
+        trust
+    }
+
+    /// Drop expired entries, then the oldest until there is room for one more.
+    fn evict(&self, state: &mut CacheState, now: u64) {
+        state
+            .entries
+            .retain(|_, v| now.saturating_sub(v.verified_at) <= self.ttl_secs);
+        while state.entries.len() >= self.max_entries {
+            let Some(oldest) = state
+                .entries
+                .iter()
+                .min_by_key(|(_, v)| v.verified_at)
+                .map(|(k, _)| k.clone())
+            else {
+                break;
+            };
+            state.entries.remove(&oldest);
+        }
+    }
+
+    /// Write the cache if it changed since the last save. A failed write leaves it
+    /// marked changed, so the next save retries.
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let file = {
+            // Cleared before the snapshot, so verdicts stored while writing mark it again.
+            let mut state = self.state.lock();
+            if !state.dirty {
+                return Ok(());
+            }
+            state.dirty = false;
+            CacheFile {
+                version: CACHE_VERSION,
+                entries: state.entries.clone(),
+            }
+        };
+        let written = write_cache_file(path, &file);
+        if written.is_err() {
+            self.state.lock().dirty = true;
+        }
+        written
+    }
+}
+
+fn write_cache_file(path: &Path, file: &CacheFile) -> Result<()> {
+    let text = serde_json::to_string(file).context("failed to serialize trust cache")?;
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, text)
+        .with_context(|| format!("failed to write trust cache {}", tmp.display()))?;
+    fs::rename(&tmp, path)
+        .with_context(|| format!("failed to replace trust cache {}", path.display()))?;
+    Ok(())
+}
+
+/// Every entry must be keyed by an uppercase SHA-256 and verified no later than now;
+/// one bad entry means the file was not written by this sensor.
+fn is_valid(file: &CacheFile, now: u64) -> bool {
+    file.version == CACHE_VERSION
+        && file.entries.iter().all(|(key, v)| {
+            key.len() == 64
+                && key.bytes().all(|b| matches!(b, b'0'..=b'9' | b'A'..=b'F'))
+                && v.verified_at <= now
+        })
+}
+
+/// Owned by Administrators; SYSTEM and Administrators get full control and nothing
+/// is inherited from the parent.
+#[cfg(windows)]
+const CACHE_SDDL: &str = "O:BAD:P(A;OICI;FA;;;SY)(A;OICI;FA;;;BA)";
+
+/// Create the cache directory and restrict it, and any existing cache file, to
+/// [`CACHE_SDDL`] so a non-admin cannot plant or edit verdicts. A cache file owned by
+/// anyone but SYSTEM or Administrators was planted and is deleted unread.
+#[cfg(windows)]
+fn restrict_cache_path(path: &Path) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+        restrict_to_admins(dir)?;
+    }
+    if path.exists() {
+        if !owned_by_admins(path)? {
+            fs::remove_file(path)
+                .with_context(|| format!("failed to delete foreign-owned {}", path.display()))?;
+            return Ok(());
+        }
+        restrict_to_admins(path)?;
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn owned_by_admins(path: &Path) -> Result<bool> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows::{
+        Win32::{
+            Foundation::{HLOCAL, LocalFree},
+            Security::{
+                Authorization::{GetNamedSecurityInfoW, SE_FILE_OBJECT},
+                IsWellKnownSid, OWNER_SECURITY_INFORMATION, PSECURITY_DESCRIPTOR, PSID,
+                WinBuiltinAdministratorsSid, WinLocalSystemSid,
+            },
+        },
+        core::PCWSTR,
+    };
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut owner = PSID::default();
+    let mut sd = PSECURITY_DESCRIPTOR::default();
+    unsafe {
+        GetNamedSecurityInfoW(
+            PCWSTR(wide.as_ptr()),
+            SE_FILE_OBJECT,
+            OWNER_SECURITY_INFORMATION,
+            Some(&mut owner),
+            None,
+            None,
+            None,
+            &mut sd,
+        )
+        .ok()
+        .with_context(|| format!("failed to read the owner of {}", path.display()))?;
+        let admin = IsWellKnownSid(owner, WinLocalSystemSid).as_bool()
+            || IsWellKnownSid(owner, WinBuiltinAdministratorsSid).as_bool();
+        let _ = LocalFree(Some(HLOCAL(sd.0)));
+        Ok(admin)
+    }
+}
+
+#[cfg(windows)]
+fn restrict_to_admins(path: &Path) -> Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows::{
+        Win32::{
+            Foundation::{HLOCAL, LocalFree},
+            Security::{
+                Authorization::{
+                    ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1,
+                },
+                DACL_SECURITY_INFORMATION, OWNER_SECURITY_INFORMATION,
+                PROTECTED_DACL_SECURITY_INFORMATION, PSECURITY_DESCRIPTOR, SetFileSecurityW,
+            },
+        },
+        core::PCWSTR,
+    };
+
+    let sddl: Vec<u16> = CACHE_SDDL.encode_utf16().chain(Some(0)).collect();
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut sd = PSECURITY_DESCRIPTOR::default();
+    unsafe {
+        ConvertStringSecurityDescriptorToSecurityDescriptorW(
+            PCWSTR(sddl.as_ptr()),
+            SDDL_REVISION_1,
+            &mut sd,
+            None,
+        )
+        .context("failed to build the trust cache security descriptor")?;
+        let set = SetFileSecurityW(
+            PCWSTR(wide.as_ptr()),
+            OWNER_SECURITY_INFORMATION
+                | DACL_SECURITY_INFORMATION
+                | PROTECTED_DACL_SECURITY_INFORMATION,
+            sd,
+        );
+        let _ = LocalFree(Some(HLOCAL(sd.0)));
+        set.ok()
+            .with_context(|| format!("failed to restrict access to {}", path.display()))
+    }
+}
+
+/// Only Windows has the admin-only location; elsewhere the file is used as configured.
+#[cfg(not(windows))]
+fn restrict_cache_path(path: &Path) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    }
+    Ok(())
+}
+
+fn read_cache_file(path: &Path) -> Result<Option<CacheFile>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(e)
+                .with_context(|| format!("failed to read trust cache {}", path.display()));
+        }
+    };
+    let file = serde_json::from_str(&text)
+        .with_context(|| format!("failed to parse trust cache {}", path.display()))?;
+    Ok(Some(file))
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn signed() -> TrustResult {
+        TrustResult {
+            is_signed: true,
+            is_trusted: true,
+            signer_subject: Some("Google LLC".to_string()),
+            signer_thumbprint: None,
+        }
+    }
+
+    const HASH_A: &str = "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA";
+    const HASH_B: &str = "BBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBB";
+
+    fn temp_cache_path(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("titan-vigil-{name}-{}", unix_now()))
+            .join("trust_cache.json")
+    }
+
+    #[test]
+    fn verdicts_survive_restart_until_the_hash_changes() {
+        let cache_path = temp_cache_path("trust-cache");
+        let cfg = TrustCacheConfig::default();
+        let calls = Cell::new(0);
+        let verify = || {
+            calls.set(calls.get() + 1);
+            signed()
+        };
+
+        let cache = TrustCache::load(&cfg, cache_path.clone());
+        cache.get_or_verify(HASH_A, RevocationPolicy::None, verify);
+        cache.get_or_verify(HASH_A, RevocationPolicy::None, verify);
+        assert_eq!(calls.get(), 1);
+        cache.save().expect("save");
+
+        let cache = TrustCache::load(&cfg, cache_path.clone());
+        assert_eq!(cache.len(), 1);
+        cache.get_or_verify(HASH_A, RevocationPolicy::None, verify);
+        assert_eq!(calls.get(), 1);
+
+        // A policy change or different image content forces a fresh verification.
+        cache.get_or_verify(HASH_A, RevocationPolicy::WholeChain, verify);
+        assert_eq!(calls.get(), 2);
+        cache.get_or_verify(HASH_B, RevocationPolicy::WholeChain, verify);
+        assert_eq!(calls.get(), 3);
+
+        let _ = fs::remove_dir_all(cache_path.parent().expect("cache dir"));
+    }
+
+    #[test]
+    fn a_malformed_entry_discards_the_whole_file() {
+        let cache_path = temp_cache_path("trust-cache-forged");
+        let cfg = TrustCacheConfig::default();
+        let cache = TrustCache::load(&cfg, cache_path.clone());
+        cache.get_or_verify(HASH_A, RevocationPolicy::None, signed);
+        cache.save().expect("save");
+
+        let text = fs::read_to_string(&cache_path).expect("read cache");
+        let forged = text.replace(HASH_A, "DUMP.EXE");
+        fs::write(&cache_path, forged).expect("forge cache");
+
+        let cache = TrustCache::load(&cfg, cache_path.clone());
+        assert!(cache.is_empty());
+        assert!(!cache_path.exists());
+
+        let _ = fs::remove_dir_all(cache_path.parent().expect("cache dir"));
+    }
+
+    #[test]
+    fn failed_save_is_retried() {
+        let cache_path = temp_cache_path("trust-cache-retry");
+        let dir = cache_path.parent().expect("cache dir").to_path_buf();
+        let cfg = TrustCacheConfig::default();
+        let cache = TrustCache::load(&cfg, cache_path.clone());
+        cache.get_or_verify(HASH_A, RevocationPolicy::None, signed);
+
+        fs::remove_dir_all(&dir).expect("remove cache dir");
+        assert!(cache.save().is_err(), "directory is gone");
+
+        fs::create_dir_all(&dir).expect("recreate cache dir");
+        cache.save().expect("retried save");
+        let reloaded = TrustCache::load(&cfg, cache_path);
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(reloaded.len(), 1);
+    }
+
+    #[test]
+    fn full_cache_evicts_the_oldest_verdict() {
+        let cfg = TrustCacheConfig {
+            max_entries: 1,
+            ..TrustCacheConfig::default()
+        };
+        let cache = TrustCache::new(&cfg);
+        cache.get_or_verify(HASH_A, RevocationPolicy::None, signed);
+        cache.get_or_verify(HASH_B, RevocationPolicy::None, signed);
+        assert_eq!(cache.len(), 1);
+    }
+}
@@ -1,4 +1,9 @@
 #[cfg(feature = "trust_api")]
 pub mod api;
+pub mod cache;
+pub mod hash;
+#[cfg(windows)]
 pub mod process;
+pub mod verdict;
+#[cfg(windows)]
 pub mod wintrust;
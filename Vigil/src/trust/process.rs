@@ -1,18 +1,28 @@
+use crate::runtime::response::ResponseTarget;
 use anyhow::Result;
-use std::mem::size_of;
+use std::{ffi::c_void, mem::size_of};
 
 use windows::{
     Win32::{
-        Foundation::{CloseHandle, ERROR_INSUFFICIENT_BUFFER, GetLastError},
+        Foundation::{CloseHandle, ERROR_INSUFFICIENT_BUFFER, FILETIME, GetLastError, HANDLE},
+        Security::{
+            GetTokenInformation, LookupAccountSidW, SID_NAME_USE, TOKEN_QUERY, TOKEN_USER,
+            TokenUser,
+        },
         System::{
+            Diagnostics::ToolHelp::{
+                CreateToolhelp32Snapshot, TH32CS_SNAPTHREAD, THREADENTRY32, Thread32First,
+                Thread32Next,
+            },
             ProcessStatus::EnumProcesses,
             Threading::{
-                OpenProcess, PROCESS_NAME_FORMAT, PROCESS_QUERY_LIMITED_INFORMATION,
-                QueryFullProcessImageNameW,
+                GetProcessTimes, OpenProcess, OpenProcessToken, OpenThread, PROCESS_ACCESS_RIGHTS,
+                PROCESS_NAME_FORMAT, PROCESS_QUERY_LIMITED_INFORMATION, PROCESS_TERMINATE,
+                QueryFullProcessImageNameW, SuspendThread, THREAD_SUSPEND_RESUME, TerminateProcess,
             },
         },
     },
-    core::PWSTR,
+    core::{PCWSTR, PWSTR},
 };
 
 pub fn enum_process_ids() -> Result<Vec<u32>> {
@@ -51,33 +61,199 @@ pub fn get_process_image_path(pid: u32) -> Option<String> {
 
     unsafe {
         let h = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid).ok()?;
-        let mut cap = 512usize;
-        loop {
-            let mut buf: Vec<u16> = vec![0u16; cap];
-            let mut size: u32 = buf.len() as u32;
+        let path = image_path(h);
+        let _ = CloseHandle(h);
+        path
+    }
+}
 
-            let ok = QueryFullProcessImageNameW(
+/// Win32 image path of the process behind `h`.
+fn image_path(h: HANDLE) -> Option<String> {
+    let mut cap = 512usize;
+    loop {
+        let mut buf: Vec<u16> = vec![0u16; cap];
+        let mut size: u32 = buf.len() as u32;
+
+        let ok = unsafe {
+            QueryFullProcessImageNameW(
                 h,
                 PROCESS_NAME_FORMAT(0),
                 PWSTR(buf.as_mut_ptr()),
                 &mut size,
             )
+        }
+        .is_ok();
+
+        if ok && size > 0 {
+            buf.truncate(size as usize);
+            return Some(String::from_utf16_lossy(&buf));
+        }
+
+        let last = unsafe { GetLastError() };
+        if last == ERROR_INSUFFICIENT_BUFFER && cap < 32 * 1024 {
+            cap = cap.saturating_mul(2);
+            continue;
+        }
+        return None;
+    }
+}
+
+/// `DOMAIN\user` owning the process token, if the process is still queryable.
+pub fn get_process_user(pid: u32) -> Option<String> {
+    if pid == 0 || pid == 4 {
+        return None;
+    }
+
+    unsafe {
+        let h = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid).ok()?;
+        let mut token = HANDLE::default();
+        let opened = OpenProcessToken(h, TOKEN_QUERY, &mut token).is_ok();
+        let _ = CloseHandle(h);
+        if !opened {
+            return None;
+        }
+
+        let mut len = 0u32;
+        let _ = GetTokenInformation(token, TokenUser, None, 0, &mut len);
+        let mut buf = vec![0u8; len as usize];
+        let ok = len > 0
+            && GetTokenInformation(
+                token,
+                TokenUser,
+                Some(buf.as_mut_ptr() as *mut c_void),
+                len,
+                &mut len,
+            )
             .is_ok();
+        let _ = CloseHandle(token);
+        if !ok {
+            return None;
+        }
 
-            if ok && size > 0 {
-                let _ = CloseHandle(h);
-                buf.truncate(size as usize);
-                return Some(String::from_utf16_lossy(&buf));
-            }
+        let sid = std::ptr::read_unaligned(buf.as_ptr() as *const TOKEN_USER)
+            .User
+            .Sid;
+        let mut name = [0u16; 256];
+        let mut name_len = name.len() as u32;
+        let mut domain = [0u16; 256];
+        let mut domain_len = domain.len() as u32;
+        let mut sid_use = SID_NAME_USE::default();
+        LookupAccountSidW(
+            PCWSTR::null(),
+            sid,
+            Some(PWSTR(name.as_mut_ptr())),
+            &mut name_len,
+            Some(PWSTR(domain.as_mut_ptr())),
+            &mut domain_len,
+            &mut sid_use,
+        )
+        .ok()?;
+
+        let name = String::from_utf16_lossy(&name[..name_len as usize]);
+        if domain_len == 0 {
+            return Some(name);
+        }
+        let domain = String::from_utf16_lossy(&domain[..domain_len as usize]);
+        Some(format!("{domain}\\{name}"))
+    }
+}
+
+/// Creation time of the process now holding `pid`, as a FILETIME.
+pub fn get_process_start_time(pid: u32) -> Option<u64> {
+    if pid == 0 || pid == 4 {
+        return None;
+    }
+
+    unsafe {
+        let h = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid).ok()?;
+        let created = creation_time(h);
+        let _ = CloseHandle(h);
+        created
+    }
+}
+
+fn creation_time(h: HANDLE) -> Option<u64> {
+    let mut created = FILETIME::default();
+    let mut exited = FILETIME::default();
+    let mut kernel = FILETIME::default();
+    let mut user = FILETIME::default();
+    let ok =
+        unsafe { GetProcessTimes(h, &mut created, &mut exited, &mut kernel, &mut user) }.is_ok();
+    ok.then(|| (u64::from(created.dwHighDateTime) << 32) | u64::from(created.dwLowDateTime))
+}
+
+/// Open `target.pid` with `access` and confirm it is still the process the alert was
+/// raised for. The handle keeps the pid from being reused until it is closed.
+fn open_target(target: &ResponseTarget, access: PROCESS_ACCESS_RIGHTS) -> Result<HANDLE> {
+    let pid = target.pid;
+    unsafe {
+        let h = OpenProcess(access | PROCESS_QUERY_LIMITED_INFORMATION, false, pid)
+            .map_err(|e| anyhow::anyhow!("OpenProcess({pid}) failed: {e}"))?;
+        let current = creation_time(h).zip(image_path(h));
+        if current.is_some_and(|(created, image)| target.matches(created, &image)) {
+            return Ok(h);
+        }
+        let _ = CloseHandle(h);
+        anyhow::bail!("pid {pid} is no longer {}; not acting", target.image)
+    }
+}
+
+/// Win32 path of `target`'s image, read only after confirming the pid is still it.
+pub fn target_image_path(target: &ResponseTarget) -> Result<String> {
+    unsafe {
+        let h = open_target(target, PROCESS_QUERY_LIMITED_INFORMATION)?;
+        let path = image_path(h);
+        let _ = CloseHandle(h);
+        path.ok_or_else(|| anyhow::anyhow!("failed to query the image of pid {}", target.pid))
+    }
+}
 
-            let last = GetLastError();
-            if last == ERROR_INSUFFICIENT_BUFFER && cap < 32 * 1024 {
-                cap = cap.saturating_mul(2);
-                continue;
+pub fn terminate_process(target: &ResponseTarget) -> Result<()> {
+    let pid = target.pid;
+    unsafe {
+        let h = open_target(target, PROCESS_TERMINATE)?;
+        let result = TerminateProcess(h, 1);
+        let _ = CloseHandle(h);
+        result.map_err(|e| anyhow::anyhow!("TerminateProcess({pid}) failed: {e}"))
+    }
+}
+
+/// Suspend every thread of `target`. Returns the number of threads suspended.
+pub fn suspend_process(target: &ResponseTarget) -> Result<usize> {
+    unsafe {
+        // Held across the thread walk so the pid cannot be handed to another process.
+        let process = open_target(target, PROCESS_QUERY_LIMITED_INFORMATION)?;
+        let pid = target.pid;
+        let snapshot = match CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                let _ = CloseHandle(process);
+                anyhow::bail!("CreateToolhelp32Snapshot failed: {e}");
             }
+        };
+        let mut entry = THREADENTRY32 {
+            dwSize: size_of::<THREADENTRY32>() as u32,
+            ..Default::default()
+        };
+        let mut suspended = 0usize;
+        let mut more = Thread32First(snapshot, &mut entry).is_ok();
+        while more {
+            if entry.th32OwnerProcessID == pid
+                && let Ok(thread) = OpenThread(THREAD_SUSPEND_RESUME, false, entry.th32ThreadID)
+            {
+                if SuspendThread(thread) != u32::MAX {
+                    suspended += 1;
+                }
+                let _ = CloseHandle(thread);
+            }
+            more = Thread32Next(snapshot, &mut entry).is_ok();
+        }
+        let _ = CloseHandle(snapshot);
+        let _ = CloseHandle(process);
 
-            let _ = CloseHandle(h);
-            return None;
+        if suspended == 0 {
+            anyhow::bail!("no thread of pid {pid} could be suspended");
         }
+        Ok(suspended)
     }
 }
@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RevocationPolicy {
+    None,
+    WholeChain,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrustResult {
+    pub is_signed: bool,
+    pub is_trusted: bool,
+    pub signer_subject: Option<String>,
+    pub signer_thumbprint: Option<String>,
+}
@@ -1,5 +1,6 @@
 use std::{ffi::c_void, mem::size_of, ptr::null_mut};
 
+use super::verdict::{RevocationPolicy, TrustResult};
 use crate::support::win::to_wide;
 use windows::{
     Win32::{
@@ -24,20 +25,6 @@ use windows::{
     core::PCWSTR,
 };
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum RevocationPolicy {
-    None,
-    WholeChain,
-}
-
-#[derive(Debug, Clone)]
-pub struct TrustResult {
-    pub is_signed: bool,
-    pub is_trusted: bool,
-    pub signer_subject: Option<String>,
-    pub signer_thumbprint: Option<String>,
-}
-
 fn bytes_to_hex_upper(bytes: &[u8]) -> String {
     let mut out = String::with_capacity(bytes.len() * 2);
     for b in bytes {